@@ -0,0 +1,1070 @@
+//! The configuration file and the profiles, and the choice of the profile matching the connected
+//! outputs.
+//!
+//! The outputs of a profile are keyed by connector name, or by any name when they are identified by
+//! the fingerprint of their monitor. The profiles returned by [`Config::profile_for`] and
+//! [`Config::named_profile`] are keyed by connector name.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    edid::Edid,
+    matching::{self, Matcher, find_monitor},
+    outputs::{self, Alias},
+};
+
+/// In milliseconds.
+const DEFAULT_DELAY: u64 = 500;
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Outputs to make primary, in order of preference.
+    pub primary: Option<OutputChain>,
+    /// How the primary output is chosen when none of the configured ones is connected.
+    pub primary_strategy: PrimaryStrategy,
+    pub positions: Vec<MonitorPos>,
+    /// Milliseconds to wait for the outputs to settle before configuring them.
+    pub pre_apply_delay: Option<u64>,
+    /// Maximum number of milliseconds to wait for the window manager to report the new outputs
+    /// before moving the workspaces.
+    pub post_apply_delay: Option<u64>,
+    /// Put the mouse pointer back on a monitor when it is plugged back.
+    pub restore_pointer: bool,
+    /// Scale of the outputs when no profile matches.
+    pub scale: Option<Scale>,
+    /// Center the scratchpad windows on the primary output when they are first shown after a layout
+    /// change.
+    pub center_scratchpad: bool,
+    /// Send desktop notifications when a profile is applied or fails to be.
+    pub notifications: bool,
+    /// Show the errors in a nagbar.
+    pub error_nagbar: bool,
+    /// Where the messages go: stderr, journald or syslog.
+    pub log_target: Option<LogTarget>,
+    /// Format of the messages written to stderr: text or json.
+    pub log_format: Option<LogFormat>,
+    /// Address of the HTTP endpoint of the Prometheus metrics, like "127.0.0.1:9753".
+    pub metrics_address: Option<String>,
+    /// Shell commands run before and after every reconfiguration.
+    pub pre_switch: Vec<String>,
+    pub post_switch: Vec<String>,
+    /// Wait for the session to be unlocked to change the outputs and move the workspaces.
+    pub defer_while_locked: bool,
+    /// Rotate the internal panel of convertibles with the device, following its accelerometer.
+    pub auto_rotate: bool,
+    /// Turn the internal panel of a laptop off while its lid is closed and other monitors are on.
+    pub lid_switch: bool,
+    /// Put the monitors of the outputs that the profile turns off in standby over DDC/CI, and wake
+    /// them up when a profile enables them again.
+    pub standby_disabled: bool,
+    /// Minutes of inactivity after which the external monitors are put in standby over DDC/CI. They
+    /// are woken up on the next input, and the changes of outputs that happened in the meantime
+    /// are applied then.
+    pub idle_standby: Option<u64>,
+    /// Connectors that are never configured nor given workspaces, e.g. a capture card.
+    pub ignore: Vec<String>,
+    /// Which outputs are configured: all of them, or only the ones listed in the config.
+    pub scope: Scope,
+    /// Names that can be used instead of the connector names of the monitors they identify.
+    pub aliases: BTreeMap<String, Alias>,
+    /// Play the sound on the speakers of the HDMI or DisplayPort monitors when they are enabled,
+    /// the primary one first, and go back to the previous sink when they are not anymore.
+    pub audio: bool,
+    /// Shell command starting a status bar, like "polybar main", run for every enabled output with
+    /// the output in $MONITOR. The bars are restarted after every reconfiguration.
+    pub bar: Option<String>,
+    /// Shell command starting a compositor, like "picom". It is restarted when the geometry of the
+    /// outputs changes.
+    pub compositor: Option<String>,
+    /// Input devices, like touchscreens and drawing tablets, mapped to the output they track, e.g.
+    /// { "Wacom Intuos Pen stylus" = "DP-1" }. The devices are named as in `xinput list`, or by
+    /// their sway identifier.
+    pub inputs: BTreeMap<String, String>,
+    /// Images shown on the outputs, by output, e.g. { "DP-1" = "~/Pictures/left.png" }.
+    pub wallpapers: BTreeMap<String, String>,
+    /// ICC profiles of the monitors, by output or alias, e.g. { "office" = "~/.local/share/icc/u2720q.icc" }.
+    /// Their calibration is loaded after every reconfiguration.
+    pub color_profiles: BTreeMap<String, String>,
+    /// Give the outputs back the gamma ramps they had before the reconfiguration, which resets them,
+    /// e.g. to keep the color temperature set by redshift, unless the profile sets their colors.
+    pub preserve_gamma: bool,
+    /// Shell command run after every reconfiguration instead, to set the color temperature of the
+    /// outputs again, like "redshift -P -O 4500".
+    pub night_light: Option<String>,
+    /// Set the Xft.dpi resource to the DPI of the densest output of the profile, 96 when none is
+    /// scaled, so that the applications started afterwards have fonts of the right size.
+    pub xft_dpi: bool,
+    /// Shell commands run when Xft.dpi changes, e.g. to reload the running applications that can.
+    pub dpi_change: Vec<String>,
+    /// Outputs to which each workspace is moved after every reconfiguration, by workspace name.
+    pub workspaces: BTreeMap<String, OutputChain>,
+    pub profiles: Vec<Profile>,
+}
+
+/// A layout that is selected when the connected outputs are exactly the ones it lists. Outputs are
+/// keyed by connector name, or by any name when they are identified by their EDID fingerprint.
+/// The profiles saved in the profiles directory default to the name of their file. A profile can
+/// also require a dock, by the USB vendor and product IDs, like "17ef:3082".
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_apply_delay: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_apply_delay: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dock: Option<String>,
+    /// Shell commands run before and after switching to this profile, after the global pre-switch
+    /// hooks and before the global post-switch ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exec_before: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exec_after: Vec<String>,
+    /// Take precedence over the global wallpapers.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub wallpapers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<InputConfig>,
+    /// Changes of the outputs while the laptop runs on battery, e.g.
+    /// { "eDP-1" = { mode = "1920x1200@60" }, "HDMI-A-0" = { enabled = false } }.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub battery: BTreeMap<String, BatteryOutput>,
+    /// Local times between which the profile can match, e.g. after = "19:00" for a TV layout in the
+    /// evening. The daemon switches profiles when these times are reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<TimeOfDay>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<TimeOfDay>,
+    #[serde(default)]
+    pub outputs: BTreeMap<String, OutputConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryOutput {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ModePreference>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// EDID fingerprint (as printed by Edid::fingerprint) of the monitor to match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edid: Option<String>,
+    /// A disabled output must be connected for the profile to match, but is turned off.
+    #[serde(skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    /// Extra xrandr arguments for this output, e.g. ["--right-of", "HDMI-A-0"] or
+    /// ["--panning", "3840x2160", "--filter", "nearest"].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ModePreference>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<Scale>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotate: Option<Rotation>,
+    /// Output whose picture this one shows, e.g. for a projector. The larger of them is scaled down
+    /// to the mode of the smaller one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+    /// Brightness of the backlight of the monitor, in percent, set over DDC/CI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    /// Like xrandr --gamma, e.g. "1.0:0.9:0.8", and --brightness, which only scales the colors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gamma: Option<Gamma>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software_brightness: Option<f64>,
+    /// Takes precedence over the global ICC profile of the monitor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_profile: Option<String>,
+    /// An output without a monitor, like VIRTUAL1 or an unused connector, for a VNC or streaming
+    /// server: it does not need to be connected for the profile to match, and is enabled with its
+    /// exact mode, which is created if needed.
+    #[serde(skip_serializing_if = "is_false")]
+    pub headless: bool,
+    /// Split the output in this many monitors of equal width, like xrandr --setmonitor, e.g. to use
+    /// an ultrawide monitor as two outputs. i3 names them after the output, like DP-1~1 and DP-1~2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split: Option<u32>,
+    /// Variable refresh rate (FreeSync, G-Sync compatible), e.g. on for a gaming profile. Left as is
+    /// when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adaptive_sync: Option<bool>,
+}
+
+/// Gamma correction of the red, green and blue channels, or of all of them, like "0.9".
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Gamma(pub [f64; 3]);
+
+/// Local time, like "19:00", in seconds since midnight.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u32);
+
+/// Settings of the keyboards and pointers applied with the layout of a profile, e.g. to use another
+/// keyboard layout with the external keyboard of a dock.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Like the layouts of setxkbmap, e.g. "us,fr".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyboard_layout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyboard_variant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub natural_scrolling: Option<bool>,
+    /// Between -1 (slowest) and 1 (fastest).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pointer_acceleration: Option<f64>,
+}
+
+/// Like xrandr: left and right turn the picture counterclockwise and clockwise.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Normal,
+    Left,
+    Right,
+    Inverted,
+}
+
+/// Either an exact mode, like "2560x1440@144" (the refresh rate is optional), or a policy:
+/// "preferred" (the default) or "highest-refresh", the highest refresh rate of the preferred
+/// resolution.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ModePreference {
+    Preferred,
+    HighestRefresh,
+    Exact {
+        mode: String,
+        rate: Option<f64>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrimaryStrategy {
+    /// The first enabled output, by connector name.
+    First,
+    LargestResolution,
+    /// Largest physical size.
+    LargestSize,
+    /// Prefer the external monitors to the internal panel of a laptop.
+    InternalLast,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Every output is turned on or off according to the profile.
+    All,
+    /// The outputs that are not named in the config (in the positions, the primary outputs or the
+    /// profiles) are left as they are.
+    Listed,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    /// The standard error output.
+    #[default]
+    Stderr,
+    /// The systemd journal, with the fields of the messages.
+    Journald,
+    /// The syslog daemon, through /dev/log.
+    Syslog,
+}
+
+/// Format of the messages written to stderr.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// The message, prefixed by the names of its spans.
+    #[default]
+    Text,
+    /// A JSON object per line, with the level, the spans and the fields of the message, for the log
+    /// pipelines and the bug reports.
+    Json,
+}
+
+/// Either a factor, or "auto" to compute it from the physical size of the monitor.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Scale {
+    Factor(f64),
+    Auto(AutoScale),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoScale {
+    Auto,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            primary: None,
+            primary_strategy: PrimaryStrategy::First,
+            positions: vec![],
+            pre_apply_delay: None,
+            post_apply_delay: None,
+            restore_pointer: true,
+            scale: None,
+            center_scratchpad: false,
+            notifications: true,
+            error_nagbar: false,
+            log_target: None,
+            log_format: None,
+            metrics_address: None,
+            pre_switch: vec![],
+            post_switch: vec![],
+            defer_while_locked: false,
+            auto_rotate: false,
+            lid_switch: false,
+            standby_disabled: false,
+            idle_standby: None,
+            ignore: vec![],
+            scope: Scope::All,
+            aliases: BTreeMap::new(),
+            audio: false,
+            bar: None,
+            compositor: None,
+            inputs: BTreeMap::new(),
+            wallpapers: BTreeMap::new(),
+            color_profiles: BTreeMap::new(),
+            preserve_gamma: true,
+            night_light: None,
+            xft_dpi: false,
+            dpi_change: vec![],
+            workspaces: BTreeMap::new(),
+            profiles: vec![],
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            edid: None,
+            enabled: true,
+            args: vec![],
+            mode: None,
+            scale: None,
+            rotate: None,
+            mirror: None,
+            brightness: None,
+            gamma: None,
+            software_brightness: None,
+            color_profile: None,
+            headless: false,
+            split: None,
+            adaptive_sync: None,
+        }
+    }
+}
+
+/// Either a single output or outputs in order of preference, e.g. ["DP-1", "HDMI-A-0", "primary"]:
+/// the workspace goes to the first one that is active.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OutputChain {
+    Output(String),
+    Chain(Vec<String>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct MonitorPos {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl Config {
+    /// A missing file is only an error when the path was explicitly requested.
+    pub fn load(path: Option<&Path>) -> io::Result<Self> {
+        let (path, required) =
+            match path {
+                Some(path) => (path.to_path_buf(), true),
+                None => match default_path() {
+                    Some(path) => (path, false),
+                    None => return Ok(Self::default()),
+                },
+            };
+
+        let mut config: Self =
+            match fs::read_to_string(&path) {
+                Ok(content) => parse_toml(&path, &content)?,
+                Err(error) if error.kind() == ErrorKind::NotFound && !required => Self::default(),
+                Err(error) => return Err(error),
+            };
+
+        if let Some(dir) = profiles_dir(Some(&path)) {
+            config.profiles.extend(load_profiles(&dir)?);
+        }
+        for profile in &config.profiles {
+            profile.check_times()?;
+        }
+
+        Ok(config)
+    }
+
+    /// Unlike profile_for, the outputs of the profile that are not connected are simply skipped.
+    pub fn named_profile(&self, name: &str, connected: &BTreeMap<String, Option<String>>) -> Option<Profile> {
+        let profile = self.profiles.iter().find(|profile| profile.name == name)?;
+        let connectors = profile.match_outputs(connected)
+            .unwrap_or_else(|| profile.partial_match(connected));
+        Some(profile.resolve(&connectors))
+    }
+
+    /// `connected` maps the connector names of the connected outputs to their EDID fingerprints, and
+    /// `docks` are the attached ones among [`Config::docks`].
+    pub fn profile_for(&self, connected: &BTreeMap<String, Option<String>>, docks: &BTreeSet<String>, now: TimeOfDay) -> Profile {
+        for profile in &self.profiles {
+            if profile.dock.as_ref().is_some_and(|dock| !docks.contains(&dock.to_ascii_lowercase())) {
+                continue;
+            }
+            if !profile.is_active_at(now) {
+                continue;
+            }
+            if let Some(connectors) = profile.match_outputs(connected) {
+                return profile.resolve(&connectors);
+            }
+        }
+
+        // No profile matches: enable every connected output with the global settings.
+        let outputs = connected.keys()
+            .map(|name| {
+                let args = self.positions.iter()
+                    .filter(|monitor_pos| &monitor_pos.name == name)
+                    .flat_map(|monitor_pos| monitor_pos.args.iter().cloned())
+                    .collect();
+                (name.clone(), OutputConfig { args, scale: self.scale, ..OutputConfig::default() })
+            })
+            .collect();
+        Profile {
+            name: "default".to_string(),
+            primary: None,
+            pre_apply_delay: None,
+            post_apply_delay: None,
+            dock: None,
+            exec_before: vec![],
+            exec_after: vec![],
+            wallpapers: BTreeMap::new(),
+            input: None,
+            battery: BTreeMap::new(),
+            after: None,
+            before: None,
+            outputs,
+        }
+    }
+
+    /// Time until the next time where a profile starts or stops being able to match.
+    pub fn next_switch(&self, now: TimeOfDay) -> Option<Duration> {
+        self.profiles.iter()
+            .flat_map(|profile| profile.after.into_iter().chain(profile.before))
+            .map(|time| time.since(now))
+            .min()
+    }
+
+    /// The docks required by the profiles, by USB vendor and product IDs in lowercase.
+    pub fn docks(&self) -> BTreeSet<String> {
+        self.profiles.iter()
+            .filter_map(|profile| Some(profile.dock.as_ref()?.to_ascii_lowercase()))
+            .collect()
+    }
+
+    /// Replace the aliases by the connector names of the monitors they identify, everywhere in the
+    /// config. `monitors` maps the connector names of the connected outputs to their EDID. The
+    /// aliases of the monitors that are not connected are left as is, so they match no output.
+    pub fn resolve_aliases(mut self, monitors: &BTreeMap<String, Edid>) -> Self {
+        let connectors = self.alias_connectors(monitors);
+        if connectors.is_empty() {
+            return self;
+        }
+
+        let rename = |name: &String| connectors.get(name).unwrap_or(name).clone();
+        for monitor_pos in &mut self.positions {
+            monitor_pos.name = rename(&monitor_pos.name);
+            monitor_pos.args = monitor_pos.args.iter().map(rename).collect();
+        }
+        if let Some(ref mut primary) = self.primary {
+            primary.rename(rename);
+        }
+        for chain in self.workspaces.values_mut() {
+            chain.rename(rename);
+        }
+        self.ignore = self.ignore.iter().map(rename).collect();
+        self.wallpapers = self.wallpapers.iter()
+            .map(|(output, image)| (rename(output), image.clone()))
+            .collect();
+        self.color_profiles = self.color_profiles.iter()
+            .map(|(output, path)| (rename(output), path.clone()))
+            .collect();
+        for output in self.inputs.values_mut() {
+            *output = rename(output);
+        }
+        for profile in &mut self.profiles {
+            *profile = profile.resolve(&connectors);
+        }
+        self
+    }
+
+    /// The connector names of the connected monitors that have an alias, by alias.
+    pub fn alias_connectors(&self, monitors: &BTreeMap<String, Edid>) -> BTreeMap<String, String> {
+        outputs::alias_connectors(&self.aliases, monitors)
+    }
+
+    /// Whether the output is neither configured nor given workspaces.
+    pub fn ignores(&self, name: &str) -> bool {
+        self.ignore.iter().any(|ignored| ignored == name)
+    }
+
+    /// Whether the output is configured by the daemon. `fingerprint` is the EDID fingerprint of the
+    /// monitor connected to it.
+    pub fn manages(&self, name: &str, fingerprint: Option<&str>) -> bool {
+        if self.ignores(name) {
+            return false;
+        }
+        match self.scope {
+            Scope::All => true,
+            Scope::Listed => {
+                self.positions.iter().any(|monitor_pos| monitor_pos.name == name)
+                    || self.primary.as_ref().is_some_and(|chain| chain.outputs().iter().any(|output| output == name))
+                    || self.profiles.iter()
+                        .flat_map(|profile| &profile.outputs)
+                        .any(|(key, output)|
+                            match output.edid {
+                                Some(ref edid) => Some(edid.as_str()) == fingerprint,
+                                None => key == name,
+                            })
+            },
+        }
+    }
+
+    /// The wallpapers of the enabled outputs of the profile.
+    pub fn wallpapers(&self, profile: &Profile) -> BTreeMap<String, String> {
+        self.wallpapers.iter()
+            .chain(&profile.wallpapers)
+            .filter(|(output, _)| profile.outputs.get(*output).is_some_and(|output_config| output_config.enabled))
+            .map(|(output, image)| (output.clone(), image.clone()))
+            .collect()
+    }
+
+    /// The ICC profile of the monitor of the output in this profile.
+    pub fn color_profile<'a>(&'a self, profile: &'a Profile, output: &str) -> Option<&'a str> {
+        profile.outputs.get(output)
+            .and_then(|output_config| output_config.color_profile.as_deref())
+            .or_else(|| self.color_profiles.get(output).map(String::as_str))
+    }
+
+    /// The input devices mapped to the enabled outputs of the profile.
+    pub fn input_mappings(&self, profile: &Profile) -> BTreeMap<String, String> {
+        self.inputs.iter()
+            .filter(|(_, output)| profile.outputs.get(*output).is_some_and(|output_config| output_config.enabled))
+            .map(|(device, output)| (device.clone(), output.clone()))
+            .collect()
+    }
+
+    /// The delays of the profile take precedence over the global ones.
+    pub fn pre_apply_delay(&self, profile: &Profile) -> Duration {
+        Duration::from_millis(profile.pre_apply_delay.or(self.pre_apply_delay).unwrap_or(DEFAULT_DELAY))
+    }
+
+    pub fn post_apply_delay(&self, profile: &Profile) -> Duration {
+        Duration::from_millis(profile.post_apply_delay.or(self.post_apply_delay).unwrap_or(DEFAULT_DELAY))
+    }
+}
+
+impl Profile {
+    /// Write the profile in the profiles directory, returning the path of the file.
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        if self.name.is_empty() || self.name.contains('/') {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("invalid profile name \"{}\"", self.name)));
+        }
+
+        let content = toml::to_string(self)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.toml", self.name));
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    /// The profile with the changes of its outputs on battery.
+    pub fn on_battery(mut self) -> Profile {
+        for (name, battery_output) in &self.battery {
+            if let Some(output) = self.outputs.get_mut(name) {
+                if let Some(enabled) = battery_output.enabled {
+                    output.enabled = enabled;
+                }
+                if let Some(ref mode) = battery_output.mode {
+                    output.mode = Some(mode.clone());
+                }
+            }
+        }
+        self
+    }
+
+    /// An empty time range could never match, which is more likely a mistake than the wish to
+    /// disable the profile.
+    fn check_times(&self) -> io::Result<()> {
+        match (self.after, self.before) {
+            (Some(after), Some(before)) if after == before => {
+                let message = format!("profile \"{}\" can never match: after and before are both {}", self.name, String::from(after));
+                Err(io::Error::new(ErrorKind::InvalidData, message))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the time conditions of the profile hold. The range wraps around midnight when `before`
+    /// is earlier than `after`.
+    fn is_active_at(&self, now: TimeOfDay) -> bool {
+        match (self.after, self.before) {
+            (Some(after), Some(before)) if before < after => now >= after || now < before,
+            (after, before) => after.is_none_or(|after| now >= after) && before.is_none_or(|before| now < before),
+        }
+    }
+
+    /// Returns the connector assigned to each output of the profile if the profile matches the
+    /// connected outputs.
+    fn match_outputs(&self, connected: &BTreeMap<String, Option<String>>) -> Option<BTreeMap<String, String>> {
+        let matchers = self.outputs.iter()
+            .map(|(key, output)| (key.clone(), Matcher {
+                edid: output.edid.clone(),
+                headless: output.headless,
+            }))
+            .collect();
+        matching::match_outputs(&matchers, connected)
+    }
+
+    /// Like match_outputs, but the outputs that cannot be found are left out.
+    fn partial_match(&self, connected: &BTreeMap<String, Option<String>>) -> BTreeMap<String, String> {
+        let mut connectors = BTreeMap::new();
+        let mut used = BTreeSet::new();
+        for (key, output) in &self.outputs {
+            let connector =
+                match output.edid {
+                    Some(ref fingerprint) => find_monitor(connected, &used, fingerprint),
+                    None => Some(key.clone()),
+                };
+            if let Some(connector) = connector {
+                used.insert(connector.clone());
+                connectors.insert(key.clone(), connector);
+            }
+        }
+        connectors
+    }
+
+    /// Rename the outputs, and the references to them in the arguments, to their connector names.
+    fn resolve(&self, connectors: &BTreeMap<String, String>) -> Profile {
+        let rename = |name: &String| connectors.get(name).unwrap_or(name).clone();
+        let outputs = self.outputs.iter()
+            .map(|(key, output)| {
+                let output = OutputConfig {
+                    args: output.args.iter().map(rename).collect(),
+                    mirror: output.mirror.as_ref().map(rename),
+                    ..output.clone()
+                };
+                (rename(key), output)
+            })
+            .collect();
+        Profile {
+            name: self.name.clone(),
+            primary: self.primary.as_ref().map(rename),
+            pre_apply_delay: self.pre_apply_delay,
+            post_apply_delay: self.post_apply_delay,
+            dock: self.dock.clone(),
+            exec_before: self.exec_before.clone(),
+            exec_after: self.exec_after.clone(),
+            wallpapers: self.wallpapers.iter()
+                .map(|(output, image)| (rename(output), image.clone()))
+                .collect(),
+            input: self.input.clone(),
+            battery: self.battery.iter()
+                .map(|(output, battery_output)| (rename(output), battery_output.clone()))
+                .collect(),
+            after: self.after,
+            before: self.before,
+            outputs,
+        }
+    }
+}
+
+impl TryFrom<String> for ModePreference {
+    type Error = String;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        match data.as_str() {
+            "preferred" => return Ok(ModePreference::Preferred),
+            "highest-refresh" => return Ok(ModePreference::HighestRefresh),
+            _ => (),
+        }
+
+        let invalid = || format!("invalid mode \"{}\", expected WIDTHxHEIGHT[@RATE], preferred or highest-refresh", data);
+        let (mode, rate) =
+            match data.split_once('@') {
+                Some((mode, rate)) => (mode, Some(rate.trim_end_matches("Hz").parse().map_err(|_| invalid())?)),
+                None => (data.as_str(), None),
+            };
+        let valid_mode = mode.split_once('x')
+            .is_some_and(|(width, height)| width.parse::<u32>().is_ok() && height.parse::<u32>().is_ok());
+        if !valid_mode {
+            return Err(invalid());
+        }
+        Ok(ModePreference::Exact {
+            mode: mode.to_string(),
+            rate,
+        })
+    }
+}
+
+impl From<ModePreference> for String {
+    fn from(preference: ModePreference) -> Self {
+        match preference {
+            ModePreference::Preferred => "preferred".to_string(),
+            ModePreference::HighestRefresh => "highest-refresh".to_string(),
+            ModePreference::Exact { mode, rate: Some(rate) } => format!("{}@{}", mode, rate),
+            ModePreference::Exact { mode, rate: None } => mode,
+        }
+    }
+}
+
+impl TryFrom<String> for Gamma {
+    type Error = String;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid gamma \"{}\", expected RED:GREEN:BLUE or a single value", data);
+        let values: Vec<f64> = data.split(':')
+            .map(|value| value.parse().ok().filter(|&value: &f64| value > 0.0))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        match values[..] {
+            [value] => Ok(Gamma([value; 3])),
+            [red, green, blue] => Ok(Gamma([red, green, blue])),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<Gamma> for String {
+    fn from(Gamma([red, green, blue]): Gamma) -> Self {
+        format!("{}:{}:{}", red, green, blue)
+    }
+}
+
+impl TimeOfDay {
+    const DAY: u32 = 24 * 60 * 60;
+
+    pub fn now() -> Self {
+        let time = unsafe {
+            let now = libc::time(std::ptr::null_mut());
+            let mut time = std::mem::zeroed();
+            libc::localtime_r(&now, &mut time);
+            time
+        };
+        TimeOfDay((time.tm_hour * 3600 + time.tm_min * 60 + time.tm_sec) as u32 % Self::DAY)
+    }
+
+    /// Time until this time of day is next reached after `now`.
+    fn since(self, now: TimeOfDay) -> Duration {
+        let seconds = (self.0 + Self::DAY - now.0) % Self::DAY;
+        Duration::from_secs(if seconds == 0 { Self::DAY as u64 } else { seconds as u64 })
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time \"{}\", expected HH:MM", data);
+        let (hours, minutes) = data.split_once(':').ok_or_else(invalid)?;
+        let hours: u32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+        if hours >= 24 || minutes >= 60 {
+            return Err(invalid());
+        }
+        Ok(TimeOfDay(hours * 3600 + minutes * 60))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(TimeOfDay(seconds): TimeOfDay) -> Self {
+        format!("{:02}:{:02}", seconds / 3600, seconds / 60 % 60)
+    }
+}
+
+impl Rotation {
+    pub fn xrandr_name(self) -> &'static str {
+        match self {
+            Rotation::Normal => "normal",
+            Rotation::Left => "left",
+            Rotation::Right => "right",
+            Rotation::Inverted => "inverted",
+        }
+    }
+
+    /// Sway turns the outputs clockwise.
+    pub fn sway_transform(self) -> &'static str {
+        match self {
+            Rotation::Normal => "normal",
+            Rotation::Left => "270",
+            Rotation::Right => "90",
+            Rotation::Inverted => "180",
+        }
+    }
+}
+
+impl Scale {
+    pub fn factor(&self, edid: Option<&Edid>) -> f64 {
+        match *self {
+            Scale::Factor(factor) => factor,
+            Scale::Auto(AutoScale::Auto) => edid.and_then(Edid::scale).unwrap_or(1.0),
+        }
+    }
+}
+
+impl OutputChain {
+    pub fn outputs(&self) -> &[String] {
+        match self {
+            OutputChain::Output(output) => std::slice::from_ref(output),
+            OutputChain::Chain(outputs) => outputs,
+        }
+    }
+
+    fn rename<F: Fn(&String) -> String>(&mut self, rename: F) {
+        match self {
+            OutputChain::Output(output) => *output = rename(output),
+            OutputChain::Chain(outputs) => *outputs = outputs.iter().map(rename).collect(),
+        }
+    }
+
+    /// "primary" stands for the primary output, whichever it is.
+    pub fn pick<'a>(&'a self, active: &'a [String], primary: Option<&'a String>) -> Option<&'a String> {
+        self.outputs().iter()
+            .filter_map(|output| {
+                if output == "primary" && !active.contains(output) {
+                    primary
+                }
+                else {
+                    Some(output)
+                }
+            })
+            .find(|output| active.contains(output))
+    }
+}
+
+impl MonitorPos {
+    pub fn parse(data: &str) -> Option<Self> {
+        let mut data = data.split(':');
+        let name = data.next()?.to_string();
+        let args_string = data.next()?.to_string();
+        let args = args_string.split_ascii_whitespace()
+            .map(|str| str.to_string())
+            .collect();
+        Some(Self {
+            name,
+            args,
+        })
+    }
+}
+
+impl TryFrom<String> for MonitorPos {
+    type Error = String;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        Self::parse(&data)
+            .ok_or_else(|| format!("invalid position \"{}\", expected OUTPUT:XRANDR-ARGS", data))
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    let base =
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+    Some(base.join("i3-aww"))
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// The profiles directory lives next to the configuration file.
+pub fn profiles_dir(config_path: Option<&Path>) -> Option<PathBuf> {
+    let config_path =
+        match config_path {
+            Some(path) => path.to_path_buf(),
+            None => default_path()?,
+        };
+    Some(config_path.parent()?.join("profiles"))
+}
+
+fn load_profiles(dir: &Path) -> io::Result<Vec<Profile>> {
+    let entries =
+        match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error),
+        };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .collect();
+    paths.sort();
+
+    let mut profiles = vec![];
+    for path in paths {
+        let mut profile: Profile = parse_toml(&path, &fs::read_to_string(&path)?)?;
+        if profile.name.is_empty() {
+            if let Some(stem) = path.file_stem() {
+                profile.name = stem.to_string_lossy().to_string();
+            }
+        }
+        profiles.push(profile);
+    }
+    Ok(profiles)
+}
+
+fn parse_toml<T: for<'de> Deserialize<'de>>(path: &Path, content: &str) -> io::Result<T> {
+    toml::from_str(content)
+        .map_err(|error| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), error)))
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, OutputChain, Profile, TimeOfDay};
+
+    fn time(data: &str) -> TimeOfDay {
+        TimeOfDay::try_from(data.to_string()).expect("time")
+    }
+
+    fn profile(after: Option<&str>, before: Option<&str>) -> Profile {
+        let mut profile: Profile = toml::from_str("name = \"evening\"").expect("profile");
+        profile.after = after.map(time);
+        profile.before = before.map(time);
+        profile
+    }
+
+    #[test]
+    fn time_range() {
+        let profile = profile(Some("09:00"), Some("17:00"));
+        assert!(!profile.is_active_at(time("08:59")));
+        assert!(profile.is_active_at(time("09:00")));
+        assert!(profile.is_active_at(time("16:59")));
+        assert!(!profile.is_active_at(time("17:00")));
+    }
+
+    #[test]
+    fn time_range_around_midnight() {
+        let profile = profile(Some("22:00"), Some("06:00"));
+        assert!(!profile.is_active_at(time("21:59")));
+        assert!(profile.is_active_at(time("22:00")));
+        assert!(profile.is_active_at(time("23:59")));
+        assert!(profile.is_active_at(time("00:00")));
+        assert!(profile.is_active_at(time("05:59")));
+        assert!(!profile.is_active_at(time("06:00")));
+        assert!(!profile.is_active_at(time("12:00")));
+    }
+
+    #[test]
+    fn open_time_ranges() {
+        assert!(profile(None, None).is_active_at(time("00:00")));
+
+        let profile_after = profile(Some("19:00"), None);
+        assert!(!profile_after.is_active_at(time("18:59")));
+        assert!(profile_after.is_active_at(time("19:00")));
+        assert!(profile_after.is_active_at(time("23:59")));
+
+        let profile_before = profile(None, Some("07:00"));
+        assert!(profile_before.is_active_at(time("00:00")));
+        assert!(!profile_before.is_active_at(time("07:00")));
+    }
+
+    #[test]
+    fn empty_time_range_is_rejected() {
+        assert!(profile(Some("19:00"), Some("19:00")).check_times().is_err());
+        assert!(profile(Some("19:00"), Some("07:00")).check_times().is_ok());
+        assert!(profile(Some("19:00"), None).check_times().is_ok());
+    }
+
+    #[test]
+    fn battery_overrides() {
+        let profile: Profile = toml::from_str(r#"
+            name = "docked"
+            [outputs.eDP-1]
+            mode = "2560x1600@120"
+            [outputs.HDMI-A-0]
+            [battery.eDP-1]
+            mode = "1920x1200@60"
+            [battery.HDMI-A-0]
+            enabled = false
+            [battery.DP-1]
+            enabled = false
+        "#).expect("profile");
+
+        let on_battery = profile.clone().on_battery();
+        let mode = on_battery.outputs["eDP-1"].mode.clone().map(String::from);
+        assert_eq!(mode.as_deref(), Some("1920x1200@60"));
+        assert!(on_battery.outputs["eDP-1"].enabled);
+        assert!(!on_battery.outputs["HDMI-A-0"].enabled);
+        // The overrides of the outputs that are not in the profile are ignored.
+        assert!(!on_battery.outputs.contains_key("DP-1"));
+
+        assert!(profile.outputs["HDMI-A-0"].enabled);
+    }
+
+    #[test]
+    fn workspace_chains() {
+        let config: Config = toml::from_str(r#"
+            [workspaces]
+            1 = "DP-1"
+            2 = ["HDMI-A-0", "DP-1", "primary"]
+            3 = ["HDMI-A-0", "primary"]
+        "#).expect("config");
+        let chain = |workspace: &str| -> &OutputChain { &config.workspaces[workspace] };
+        let active = ["eDP-1".to_string(), "DP-1".to_string()];
+        let primary = "eDP-1".to_string();
+
+        assert_eq!(chain("1").pick(&active, Some(&primary)).map(String::as_str), Some("DP-1"));
+        assert_eq!(chain("1").pick(&active[..1], Some(&primary)), None);
+        // The first active output of the chain.
+        assert_eq!(chain("2").pick(&active, Some(&primary)).map(String::as_str), Some("DP-1"));
+        assert_eq!(chain("2").pick(&active[..1], Some(&primary)).map(String::as_str), Some("eDP-1"));
+        assert_eq!(chain("3").pick(&active, Some(&primary)).map(String::as_str), Some("eDP-1"));
+        assert_eq!(chain("3").pick(&active, None), None);
+    }
+}
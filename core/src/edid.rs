@@ -0,0 +1,180 @@
+//! Identification of the monitors from their EDID.
+
+use serde::{Deserialize, Serialize};
+
+const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+const DESCRIPTOR_OFFSETS: [usize; 4] = [54, 72, 90, 108];
+const DESCRIPTOR_MONITOR_NAME: u8 = 0xFC;
+const DESCRIPTOR_SERIAL: u8 = 0xFF;
+/// Density of the monitors rendered without scaling.
+pub const REFERENCE_DPI: f64 = 96.0;
+
+/// What i3-aww uses from the EDID of a monitor.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Edid {
+    pub manufacturer: String,
+    pub product: u16,
+    pub serial: u32,
+    pub model: Option<String>,
+    pub serial_string: Option<String>,
+    /// Resolution of the preferred mode, in pixels, and physical size of the picture, in millimeters.
+    pub preferred_size: Option<(u32, u32)>,
+    pub size_mm: Option<(u32, u32)>,
+}
+
+impl Edid {
+    /// Returns None when `data` is not an EDID.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 128 || data[..8] != HEADER {
+            return None;
+        }
+
+        let id = u16::from_be_bytes([data[8], data[9]]);
+        let manufacturer = [(id >> 10) & 0x1F, (id >> 5) & 0x1F, id & 0x1F].iter()
+            .map(|&letter| (b'A' - 1 + letter as u8) as char)
+            .collect();
+
+        let mut model = None;
+        let mut serial_string = None;
+        let mut preferred_size = None;
+        let mut size_mm = None;
+        for &offset in &DESCRIPTOR_OFFSETS {
+            let descriptor = &data[offset..offset + 18];
+            // Display descriptors start with a zero pixel clock, the others are detailed timings, the
+            // first one being the preferred mode.
+            if descriptor[0] != 0 || descriptor[1] != 0 {
+                if offset == DESCRIPTOR_OFFSETS[0] {
+                    let width = descriptor[2] as u32 | (descriptor[4] as u32 & 0xF0) << 4;
+                    let height = descriptor[5] as u32 | (descriptor[7] as u32 & 0xF0) << 4;
+                    let width_mm = descriptor[12] as u32 | (descriptor[14] as u32 & 0xF0) << 4;
+                    let height_mm = descriptor[13] as u32 | (descriptor[14] as u32 & 0x0F) << 8;
+                    preferred_size = Some((width, height));
+                    if width_mm > 0 && height_mm > 0 {
+                        size_mm = Some((width_mm, height_mm));
+                    }
+                }
+                continue;
+            }
+            match descriptor[3] {
+                DESCRIPTOR_MONITOR_NAME => model = descriptor_text(descriptor),
+                DESCRIPTOR_SERIAL => serial_string = descriptor_text(descriptor),
+                _ => (),
+            }
+        }
+
+        Some(Self {
+            manufacturer,
+            product: u16::from_le_bytes([data[10], data[11]]),
+            serial: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            model,
+            serial_string,
+            preferred_size,
+            // Fall back to the size in centimeters of the basic parameters.
+            size_mm: size_mm.or_else(|| {
+                let (width, height) = (data[21] as u32, data[22] as u32);
+                (width > 0 && height > 0).then_some((width * 10, height * 10))
+            }),
+        })
+    }
+
+    /// Scale making the monitor look like a 96 DPI one, by steps of 0.5. None when the monitor does
+    /// not report its size, like projectors.
+    pub fn scale(&self) -> Option<f64> {
+        let (width, _) = self.preferred_size?;
+        let (width_mm, _) = self.size_mm?;
+        let dpi = width as f64 * 25.4 / width_mm as f64;
+        Some(((dpi / REFERENCE_DPI) * 2.0).round().max(2.0) / 2.0)
+    }
+
+    /// Identifies a physical monitor independently of the connector it is plugged into. Many
+    /// monitors leave the serial number at zero and only have it in a descriptor: without it, two
+    /// identical monitors would have the same fingerprint.
+    pub fn fingerprint(&self) -> String {
+        match self.serial_string {
+            Some(ref serial) if self.serial == 0 => format!("{}-{:04X}-{}", self.manufacturer, self.product, serial),
+            _ => format!("{}-{:04X}-{:08X}", self.manufacturer, self.product, self.serial),
+        }
+    }
+}
+
+fn descriptor_text(descriptor: &[u8]) -> Option<String> {
+    let text = &descriptor[5..];
+    let end = text.iter().position(|&byte| byte == b'\n').unwrap_or(text.len());
+    let text = String::from_utf8_lossy(&text[..end]).trim().to_string();
+    if text.is_empty() {
+        None
+    }
+    else {
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Edid;
+
+    // A 27" 1920x1080 monitor, with its serial number in a descriptor.
+    fn edid(serial: u32, serial_string: Option<&str>) -> Vec<u8> {
+        let mut data = vec![0; 128];
+        data[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+        // DEL, as 3 letters of 5 bits.
+        data[8..10].copy_from_slice(&((4 << 10 | 5 << 5 | 12) as u16).to_be_bytes());
+        data[10..12].copy_from_slice(&0xA0F1u16.to_le_bytes());
+        data[12..16].copy_from_slice(&serial.to_le_bytes());
+        data[21] = 60;
+        data[22] = 34;
+
+        // Preferred mode: 1920x1080 on 600x340 mm.
+        data[54..72].copy_from_slice(&[0x02, 0x3A, 0x80, 0x18, 0x71, 0x38, 0x2D, 0x40, 0x58, 0x2C, 0x45, 0x00, 0x58, 0x54, 0x21, 0x00, 0x00, 0x1E]);
+        descriptor(&mut data[72..90], 0xFC, "DELL U2720Q");
+        if let Some(serial) = serial_string {
+            descriptor(&mut data[90..108], 0xFF, serial);
+        }
+        data
+    }
+
+    fn descriptor(descriptor: &mut [u8], tag: u8, text: &str) {
+        descriptor[3] = tag;
+        let text = format!("{:<13}", format!("{}\n", text));
+        descriptor[5..].copy_from_slice(text.as_bytes());
+    }
+
+    #[test]
+    fn parse() {
+        let edid = Edid::parse(&edid(0, Some("ABC123"))).expect("edid");
+        assert_eq!(edid, Edid {
+            manufacturer: "DEL".to_string(),
+            product: 0xA0F1,
+            serial: 0,
+            model: Some("DELL U2720Q".to_string()),
+            serial_string: Some("ABC123".to_string()),
+            preferred_size: Some((1920, 1080)),
+            size_mm: Some((600, 340)),
+        });
+        assert_eq!(edid.scale(), Some(1.0));
+    }
+
+    #[test]
+    fn not_an_edid() {
+        let mut data = edid(0, None);
+        assert_eq!(Edid::parse(&data[..127]), None);
+        data[0] = 0xFF;
+        assert_eq!(Edid::parse(&data), None);
+    }
+
+    #[test]
+    fn size_in_centimeters() {
+        let mut data = edid(0, None);
+        data[66..69].fill(0);
+        assert_eq!(Edid::parse(&data).and_then(|edid| edid.size_mm), Some((600, 340)));
+    }
+
+    #[test]
+    fn fingerprint() {
+        let fingerprint = |serial, serial_string| Edid::parse(&edid(serial, serial_string)).expect("edid").fingerprint();
+        assert_eq!(fingerprint(0, Some("ABC123")), "DEL-A0F1-ABC123");
+        assert_eq!(fingerprint(42, Some("ABC123")), "DEL-A0F1-0000002A");
+        assert_eq!(fingerprint(0, None), "DEL-A0F1-00000000");
+        assert_ne!(fingerprint(0, Some("ABC123")), fingerprint(0, Some("ABC124")));
+    }
+}
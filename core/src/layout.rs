@@ -0,0 +1,317 @@
+//! Planning of the xrandr configuration of a profile: the arguments of every output, their scale
+//! and the DPI of the screen.
+
+use std::{collections::BTreeMap, iter};
+
+use crate::{
+    config::{ModePreference, OutputConfig, Profile},
+    edid::REFERENCE_DPI,
+    outputs::MonitorData,
+};
+
+/// What xrandr is asked to do with an output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DesiredOutput {
+    pub name: String,
+    pub enabled: bool,
+    pub primary: bool,
+    pub args: Vec<String>,
+}
+
+/// The modes of an output, as reported by the X server.
+#[derive(Clone, Debug, Default)]
+pub struct OutputModes {
+    pub preferred_mode: Option<String>,
+    /// Available modes with their refresh rate, the preferred ones first.
+    pub modes: Vec<(String, f64)>,
+}
+
+/// What xrandr is asked to do with every output, and the DPI of the screen when the profile scales
+/// the outputs. `modes` are the modes of the outputs, by name.
+pub fn desired_outputs(profile: &Profile, monitor_data: &[MonitorData], modes: &BTreeMap<String, OutputModes>)
+    -> (Vec<DesiredOutput>, Option<f64>)
+{
+    let primary_connected = profile.primary.as_ref()
+        .and_then(|primary| profile.outputs.get(primary))
+        .is_some_and(|output_config| output_config.enabled);
+
+    let mut primary_set = primary_connected;
+
+    // X11 applications only know a single DPI: it is set for the densest output, and the others
+    // render at a higher resolution, downscaled to their size.
+    let scales = output_scales(profile, monitor_data);
+    let max_scale = scales.values().copied().fold(1.0, f64::max);
+    let dpi = (!scales.is_empty()).then_some(REFERENCE_DPI * max_scale);
+
+    let mirror_scales = mirror_scales(profile, modes);
+    let mut desired = vec![];
+    for monitor in monitor_data {
+        let mut output = DesiredOutput {
+            name: monitor.name.clone(),
+            enabled: false,
+            primary: false,
+            args: vec![],
+        };
+
+        match profile.outputs.get(&monitor.name) {
+            Some(output_config) if (monitor.connected || output_config.headless) && output_config.enabled => {
+                output.enabled = true;
+                output.args.push("--auto".to_string());
+                output.args.extend(mode_args(output_config.mode.as_ref(), modes.get(&monitor.name)));
+                if let Some(rotation) = output_config.rotate {
+                    output.args.extend(["--rotate".to_string(), rotation.xrandr_name().to_string()]);
+                }
+                output.args.extend(output_config.args.iter().cloned());
+                if let Some(ref other) = output_config.mirror {
+                    output.args.extend(["--same-as".to_string(), other.clone()]);
+                }
+                // xrandr only keeps the last --scale, so the one for the DPI and the one of the
+                // mirrors are combined.
+                let dpi_scale = dpi.map(|_| max_scale / scales.get(&monitor.name).copied().unwrap_or(1.0));
+                let mirror_scale = mirror_scales.get(&monitor.name).copied();
+                if dpi_scale.is_some() || mirror_scale.is_some() {
+                    let dpi_scale = dpi_scale.unwrap_or(1.0);
+                    let (x, y) = mirror_scale.unwrap_or((1.0, 1.0));
+                    output.args.extend(["--scale".to_string(), format!("{}x{}", dpi_scale * x, dpi_scale * y)]);
+                }
+
+                if profile.primary.as_ref() == Some(&monitor.name) || !primary_set {
+                    output.primary = true;
+                    primary_set = true;
+                }
+            },
+            _ => (),
+        }
+        desired.push(output);
+    }
+    (desired, dpi)
+}
+
+/// The scale of the outputs of the profile that have one, by output.
+pub fn output_scales(profile: &Profile, monitor_data: &[MonitorData]) -> BTreeMap<String, f64> {
+    monitor_data.iter()
+        .filter_map(|monitor| {
+            let scale = profile.outputs.get(&monitor.name)?.scale?;
+            Some((monitor.name.clone(), scale.factor(monitor.edid.as_ref())))
+        })
+        .collect()
+}
+
+/// Mirrored outputs show the same area of the screen, the size of the smallest mode among them, so
+/// that the smallest output, often a projector, is not downscaled: the larger outputs are scaled
+/// down to it. Returns the horizontal and vertical scale of those, by output.
+pub fn mirror_scales(profile: &Profile, modes: &BTreeMap<String, OutputModes>) -> BTreeMap<String, (f64, f64)> {
+    let size = |name: &String| {
+        let mode = profile.outputs.get(name).and_then(|output_config| output_mode(output_config, modes.get(name)))?;
+        mode_size(&mode)
+    };
+
+    // The outputs showing the picture of each output.
+    let mut groups: BTreeMap<&String, Vec<&String>> = BTreeMap::new();
+    for (name, output_config) in &profile.outputs {
+        if let (Some(other), true) = (&output_config.mirror, output_config.enabled) {
+            groups.entry(other).or_default().push(name);
+        }
+    }
+
+    let mut scales = BTreeMap::new();
+    for (source, mirrors) in groups {
+        let sizes: Vec<_> = iter::once(source).chain(mirrors)
+            .filter_map(|name| Some((name, size(name)?)))
+            .collect();
+        let smallest = sizes.iter()
+            .map(|&(_, size)| size)
+            .min_by(|(width1, height1), (width2, height2)| (width1 * height1).total_cmp(&(width2 * height2)));
+        if let Some((width, height)) = smallest {
+            for (name, (own_width, own_height)) in sizes {
+                if (own_width, own_height) != (width, height) {
+                    scales.insert(name.clone(), (width / own_width, height / own_height));
+                }
+            }
+        }
+    }
+    scales
+}
+
+/// The xrandr command line equivalent to the desired outputs.
+pub fn xrandr_args(desired: &[DesiredOutput], dpi: Option<f64>) -> String {
+    let mut args = vec![];
+    for output in desired {
+        args.extend(["--output".to_string(), output.name.clone()]);
+        if output.enabled {
+            args.extend(output.args.iter().cloned());
+            if output.primary {
+                args.push("--primary".to_string());
+            }
+        }
+        else {
+            args.push("--off".to_string());
+        }
+    }
+    if let Some(dpi) = dpi {
+        args.extend(["--dpi".to_string(), dpi.round().to_string()]);
+    }
+    args.join(" ")
+}
+
+/// The xrandr arguments selecting the mode of an output. `--auto` already picks the preferred one.
+pub fn mode_args(preference: Option<&ModePreference>, modes: Option<&OutputModes>) -> Vec<String> {
+    match preference {
+        None | Some(ModePreference::Preferred) => vec![],
+        Some(ModePreference::Exact { mode, rate }) => {
+            let mut args = vec!["--mode".to_string(), mode.clone()];
+            if let Some(rate) = rate {
+                args.extend(["--rate".to_string(), rate.to_string()]);
+            }
+            args
+        },
+        Some(ModePreference::HighestRefresh) => {
+            let highest = modes.and_then(|modes| {
+                let resolution = modes.preferred_mode.as_ref().or(modes.modes.first().map(|(mode, _)| mode))?;
+                modes.modes.iter()
+                    .filter(|(mode, _)| mode == resolution)
+                    .max_by(|(_, rate1), (_, rate2)| rate1.total_cmp(rate2))
+            });
+            match highest {
+                Some((mode, rate)) => vec!["--mode".to_string(), mode.clone(), "--rate".to_string(), format!("{:.2}", rate)],
+                None => vec![],
+            }
+        },
+    }
+}
+
+/// The connected and headless outputs that the profile enables.
+pub fn enabled_outputs(profile: &Profile, monitor_data: &[MonitorData]) -> Vec<String> {
+    monitor_data.iter()
+        .filter(|monitor| {
+            profile.outputs.get(&monitor.name)
+                .is_some_and(|output_config| (monitor.connected || output_config.headless) && output_config.enabled)
+        })
+        .map(|monitor| monitor.name.clone())
+        .collect()
+}
+
+/// The mode an output gets, when known.
+pub fn output_mode(output_config: &OutputConfig, modes: Option<&OutputModes>) -> Option<String> {
+    match output_config.mode {
+        Some(ModePreference::Exact { ref mode, .. }) => Some(mode.clone()),
+        _ => modes?.preferred_mode.clone(),
+    }
+}
+
+/// Parses the size of a mode named like WIDTHxHEIGHT, with an optional suffix like i for the
+/// interlaced modes.
+pub fn mode_size(name: &str) -> Option<(f64, f64)> {
+    let (width, height) = name.split_once('x')?;
+    let height: String = height.chars().take_while(char::is_ascii_digit).collect();
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        config::{ModePreference, Profile},
+        outputs::MonitorData,
+    };
+
+    use super::{OutputModes, desired_outputs, mirror_scales, mode_args, mode_size, xrandr_args};
+
+    fn monitor(name: &str, connected: bool) -> MonitorData {
+        MonitorData {
+            name: name.to_string(),
+            connected,
+            edid: None,
+            mode: None,
+        }
+    }
+
+    fn profile(content: &str) -> Profile {
+        toml::from_str(content).expect("profile")
+    }
+
+    // The arguments following the option, up to the next option.
+    fn values(args: &[String], option: &str) -> Vec<String> {
+        args.iter()
+            .skip_while(|arg| *arg != option)
+            .skip(1)
+            .take_while(|arg| !arg.starts_with("--"))
+            .cloned()
+            .collect()
+    }
+
+    // The projector keeps its mode unscaled, the laptop panel shows the same area.
+    #[test]
+    fn mirror_scales_larger_output_down() {
+        let profile = profile(r#"
+            [outputs.eDP-1]
+            mode = "1920x1080"
+
+            [outputs.HDMI-1]
+            mode = "1024x768"
+            mirror = "eDP-1"
+        "#);
+
+        let scales = mirror_scales(&profile, &BTreeMap::new());
+        assert_eq!(scales.len(), 1);
+        assert_eq!(scales.get("eDP-1"), Some(&(1024.0 / 1920.0, 768.0 / 1080.0)));
+    }
+
+    // The mirrored output of a profile that scales the outputs gets a single --scale, combining both.
+    #[test]
+    fn mirror_scale_combines_with_dpi_scale() {
+        let profile = profile(r#"
+            [outputs.eDP-1]
+            mode = "1920x1080"
+            scale = 2.0
+
+            [outputs.HDMI-1]
+            mode = "1024x768"
+            mirror = "eDP-1"
+        "#);
+
+        let (desired, dpi) = desired_outputs(&profile, &[monitor("eDP-1", true), monitor("HDMI-1", true)], &BTreeMap::new());
+        assert_eq!(dpi, Some(192.0));
+        let scales = |name: &str| {
+            let output = desired.iter().find(|output| output.name == name).expect("output");
+            assert_eq!(output.args.iter().filter(|arg| *arg == "--scale").count(), 1);
+            values(&output.args, "--scale")
+        };
+        assert_eq!(scales("eDP-1"), [format!("{}x{}", 1024.0 / 1920.0, 768.0 / 1080.0)]);
+        assert_eq!(scales("HDMI-1"), ["2x2"]);
+    }
+
+    #[test]
+    fn primary_and_disabled_outputs() {
+        let profile = profile(r#"
+            [outputs.eDP-1]
+            enabled = false
+
+            [outputs.DP-1]
+            rotate = "left"
+            args = ["--right-of", "eDP-1"]
+        "#);
+
+        let monitors = [monitor("eDP-1", true), monitor("DP-1", true), monitor("HDMI-1", false)];
+        let (desired, dpi) = desired_outputs(&profile, &monitors, &BTreeMap::new());
+        assert_eq!(dpi, None);
+        assert_eq!(xrandr_args(&desired, dpi), "--output eDP-1 --off --output DP-1 --auto --rotate left --right-of eDP-1 --primary --output HDMI-1 --off");
+    }
+
+    #[test]
+    fn modes() {
+        let modes = OutputModes {
+            preferred_mode: Some("2560x1440".to_string()),
+            modes: vec![("2560x1440".to_string(), 59.95), ("2560x1440".to_string(), 143.91), ("3840x2160".to_string(), 60.0)],
+        };
+        assert_eq!(mode_args(Some(&ModePreference::HighestRefresh), Some(&modes)), ["--mode", "2560x1440", "--rate", "143.91"]);
+        assert!(mode_args(Some(&ModePreference::HighestRefresh), None).is_empty());
+        assert!(mode_args(Some(&ModePreference::Preferred), Some(&modes)).is_empty());
+        let exact = ModePreference::Exact { mode: "1920x1080".to_string(), rate: Some(60.0) };
+        assert_eq!(mode_args(Some(&exact), Some(&modes)), ["--mode", "1920x1080", "--rate", "60"]);
+
+        assert_eq!(mode_size("1920x1080i"), Some((1920.0, 1080.0)));
+        assert_eq!(mode_size("preferred"), None);
+    }
+}
@@ -0,0 +1,16 @@
+//! The logic of i3-aww that does not talk to the X server or the window manager: the
+//! configuration, the identification of the monitors and of the connected outputs, the choice of
+//! the profile for the connected outputs and the planning of its xrandr configuration, and the
+//! tracking of the workspaces and the planning of their restoration.
+//!
+//! The `i3-aww` binary gathers the state, calls these functions and runs the commands they
+//! return, which makes it possible to reuse them in other tools and to check their result
+//! without a display.
+
+pub mod config;
+pub mod edid;
+pub mod layout;
+pub mod matching;
+pub mod outputs;
+pub mod restore;
+pub mod selection;
@@ -0,0 +1,177 @@
+//! Matching of the outputs of a profile with the connected outputs.
+//!
+//! The connected outputs are given as a map from their connector name, like "DP-1", to the
+//! fingerprint of their monitor (see [`Edid::fingerprint`](crate::edid::Edid::fingerprint)), when
+//! it has an EDID.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How an output of a profile finds its connector.
+pub struct Matcher {
+    /// Fingerprint of the monitor. Without it, the output is matched by connector name.
+    pub edid: Option<String>,
+    /// An output without a monitor, which does not need to be connected.
+    pub headless: bool,
+}
+
+/// Returns the connector assigned to each output of the profile, keyed like `outputs`, if the
+/// profile matches the connected outputs: every monitor of the profile is connected, and nothing
+/// else is.
+pub fn match_outputs(outputs: &BTreeMap<String, Matcher>, connected: &BTreeMap<String, Option<String>>)
+    -> Option<BTreeMap<String, String>>
+{
+    let monitors = outputs.values().filter(|output| !output.headless).count();
+    if monitors != connected.len() {
+        return None;
+    }
+
+    let mut connectors = BTreeMap::new();
+    let mut used = BTreeSet::new();
+
+    // Outputs identified by connector name go first so that EDID matches pick among the rest.
+    let mut renamed = vec![];
+    for (key, output) in outputs {
+        if output.headless {
+            connectors.insert(key.clone(), key.clone());
+        }
+        else if output.edid.is_none() {
+            if !connected.contains_key(key) {
+                renamed.push(key);
+                continue;
+            }
+            used.insert(key.clone());
+            connectors.insert(key.clone(), key.clone());
+        }
+    }
+
+    // A dock can give another MST branch to the same monitor after a replug, e.g. DP-1-2
+    // instead of DP-1-1.
+    for key in renamed {
+        let connector = connected.keys()
+            .find(|name| !used.contains(*name) && !outputs.contains_key(*name) && mst_port(name) == mst_port(key))?
+            .clone();
+        used.insert(connector.clone());
+        connectors.insert(key.clone(), connector);
+    }
+
+    for (key, output) in outputs {
+        if let Some(ref fingerprint) = output.edid {
+            let connector = find_monitor(connected, &used, fingerprint)?;
+            used.insert(connector.clone());
+            connectors.insert(key.clone(), connector);
+        }
+    }
+
+    Some(connectors)
+}
+
+/// The connector without its MST branch number: DP-1-1 and DP-1-2 are both on the port DP-1.
+pub fn mst_port(name: &str) -> &str {
+    let mut parts = name.rsplitn(3, '-');
+    let (Some(branch), Some(port), Some(prefix)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return name;
+        };
+    let numeric = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    if numeric(branch) && numeric(port) {
+        &name[..prefix.len() + 1 + port.len()]
+    }
+    else {
+        name
+    }
+}
+
+/// The connector of the monitor with this fingerprint that is not in `used`. The fingerprints
+/// saved before the serial number descriptor was used end with a zero serial number: they match
+/// any monitor of the same model, when no monitor has the exact fingerprint.
+pub fn find_monitor(connected: &BTreeMap<String, Option<String>>, used: &BTreeSet<String>, fingerprint: &str) -> Option<String> {
+    let available = connected.iter()
+        .filter(|(name, _)| !used.contains(*name))
+        .filter_map(|(name, edid)| Some((name, edid.as_deref()?)));
+    let legacy_model = fingerprint.strip_suffix("-00000000");
+    let mut model_match = None;
+    for (name, edid) in available {
+        if edid == fingerprint {
+            return Some(name.clone());
+        }
+        let same_model = legacy_model.is_some_and(|model| edid.strip_prefix(model).is_some_and(|rest| rest.starts_with('-')));
+        if same_model && model_match.is_none() {
+            model_match = Some(name.clone());
+        }
+    }
+    model_match
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::{Matcher, find_monitor, match_outputs, mst_port};
+
+    fn connected(outputs: &[(&str, Option<&str>)]) -> BTreeMap<String, Option<String>> {
+        outputs.iter()
+            .map(|(name, edid)| (name.to_string(), edid.map(str::to_string)))
+            .collect()
+    }
+
+    fn matchers(outputs: &[(&str, Option<&str>, bool)]) -> BTreeMap<String, Matcher> {
+        outputs.iter()
+            .map(|&(key, edid, headless)| (key.to_string(), Matcher { edid: edid.map(str::to_string), headless }))
+            .collect()
+    }
+
+    fn connectors(pairs: &[(&str, &str)]) -> Option<BTreeMap<String, String>> {
+        Some(pairs.iter().map(|(key, connector)| (key.to_string(), connector.to_string())).collect())
+    }
+
+    #[test]
+    fn by_name_and_edid() {
+        let outputs = matchers(&[("eDP-1", None, false), ("desk", Some("DEL-A0F1-ABC123"), false)]);
+        let current = connected(&[("eDP-1", None), ("DP-2", Some("DEL-A0F1-ABC123"))]);
+        assert_eq!(match_outputs(&outputs, &current), connectors(&[("eDP-1", "eDP-1"), ("desk", "DP-2")]));
+    }
+
+    #[test]
+    fn every_monitor_is_needed() {
+        let outputs = matchers(&[("eDP-1", None, false), ("DP-1", None, false)]);
+        assert_eq!(match_outputs(&outputs, &connected(&[("eDP-1", None)])), None);
+        assert_eq!(match_outputs(&outputs, &connected(&[("eDP-1", None), ("DP-1", None), ("DP-2", None)])), None);
+        let outputs = matchers(&[("desk", Some("DEL-A0F1-ABC123"), false)]);
+        assert_eq!(match_outputs(&outputs, &connected(&[("DP-1", Some("DEL-A0F1-ABC124"))])), None);
+    }
+
+    #[test]
+    fn headless_outputs_need_no_monitor() {
+        let outputs = matchers(&[("eDP-1", None, false), ("VIRTUAL1", None, true)]);
+        assert_eq!(match_outputs(&outputs, &connected(&[("eDP-1", None)])),
+            connectors(&[("VIRTUAL1", "VIRTUAL1"), ("eDP-1", "eDP-1")]));
+    }
+
+    #[test]
+    fn other_mst_branch() {
+        let outputs = matchers(&[("eDP-1", None, false), ("DP-1-1", None, false)]);
+        assert_eq!(match_outputs(&outputs, &connected(&[("eDP-1", None), ("DP-1-2", None)])),
+            connectors(&[("DP-1-1", "DP-1-2"), ("eDP-1", "eDP-1")]));
+        assert_eq!(match_outputs(&outputs, &connected(&[("eDP-1", None), ("DP-2-1", None)])), None);
+    }
+
+    #[test]
+    fn port() {
+        assert_eq!(mst_port("DP-1-2"), "DP-1");
+        assert_eq!(mst_port("DP-1"), "DP-1");
+        assert_eq!(mst_port("eDP-1"), "eDP-1");
+        assert_eq!(mst_port("DisplayPort-1-2"), "DisplayPort-1");
+        assert_eq!(mst_port("HDMI-A-1"), "HDMI-A-1");
+        assert_eq!(mst_port("DP-1-"), "DP-1-");
+    }
+
+    #[test]
+    fn legacy_fingerprint() {
+        let current = connected(&[("DP-1", Some("DEL-A0F1-ABC123")), ("DP-2", Some("DEL-A0F1-ABC124"))]);
+        let used = BTreeSet::from(["DP-1".to_string()]);
+        assert_eq!(find_monitor(&current, &BTreeSet::new(), "DEL-A0F1-00000000"), Some("DP-1".to_string()));
+        assert_eq!(find_monitor(&current, &used, "DEL-A0F1-00000000"), Some("DP-2".to_string()));
+        assert_eq!(find_monitor(&current, &BTreeSet::new(), "DEL-A0F1-ABC124"), Some("DP-2".to_string()));
+        assert_eq!(find_monitor(&current, &BTreeSet::new(), "DEL-A0F-00000000"), None);
+    }
+}
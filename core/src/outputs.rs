@@ -0,0 +1,188 @@
+//! Detection of the connected outputs and of the monitors they show, and resolution of the
+//! aliases given to the monitors in the configuration.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::edid::Edid;
+
+/// An output of the display server, connected or not, as reported by the backend.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MonitorData {
+    pub name: String,
+    pub connected: bool,
+    pub edid: Option<Edid>,
+    /// Current mode and refresh rate, when the backend reports it.
+    pub mode: Option<String>,
+}
+
+/// A monitor identified by the name and serial number from its EDID, e.g.
+/// `{ model = "DELL U2720Q", serial = "ABC123" }`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Alias {
+    pub model: String,
+    pub serial: Option<String>,
+}
+
+impl Alias {
+    /// The serial is either the one of the serial number descriptor or the numeric one.
+    pub fn matches(&self, edid: &Edid) -> bool {
+        if edid.model.as_ref() != Some(&self.model) {
+            return false;
+        }
+        match self.serial {
+            Some(ref serial) => edid.serial_string.as_ref() == Some(serial) || edid.serial.to_string() == *serial,
+            None => true,
+        }
+    }
+}
+
+/// The connector names of the connected monitors that have an alias, by alias. `monitors` maps
+/// the connector names of the connected outputs to their EDID.
+pub fn alias_connectors(aliases: &BTreeMap<String, Alias>, monitors: &BTreeMap<String, Edid>) -> BTreeMap<String, String> {
+    aliases.iter()
+        .filter_map(|(alias, identity)| {
+            let connector = monitors.iter()
+                .find(|(_, edid)| identity.matches(edid))
+                .map(|(name, _)| name.clone())?;
+            Some((alias.clone(), connector))
+        })
+        .collect()
+}
+
+/// The EDID of the connected monitors, by connector name.
+pub fn connected_edids(monitor_data: &[MonitorData]) -> BTreeMap<String, Edid> {
+    monitor_data.iter()
+        .filter(|monitor| monitor.connected)
+        .filter_map(|monitor| Some((monitor.name.clone(), monitor.edid.clone()?)))
+        .collect()
+}
+
+/// The connected outputs with the fingerprint of their monitor, as expected by
+/// [`match_outputs`](crate::matching::match_outputs).
+pub fn connected_outputs(monitor_data: &[MonitorData]) -> BTreeMap<String, Option<String>> {
+    monitor_data.iter()
+        .filter(|monitor| monitor.connected)
+        .map(|monitor| (monitor.name.clone(), monitor.edid.as_ref().map(Edid::fingerprint)))
+        .collect()
+}
+
+/// The connected monitors and their modes: nothing needs to be done when these did not change.
+pub fn outputs_state(monitor_data: &[MonitorData]) -> BTreeMap<String, (Option<String>, Option<String>)> {
+    monitor_data.iter()
+        .filter(|monitor| monitor.connected)
+        .map(|monitor| {
+            let fingerprint = monitor.edid.as_ref().map(Edid::fingerprint);
+            (monitor.name.clone(), (fingerprint, monitor.mode.clone()))
+        })
+        .collect()
+}
+
+/// The outputs for which `manages` returns true, given their name and the fingerprint of their
+/// monitor.
+pub fn managed<F: Fn(&str, Option<&str>) -> bool>(monitor_data: Vec<MonitorData>, manages: F) -> Vec<MonitorData> {
+    monitor_data.into_iter()
+        .filter(|monitor| {
+            let fingerprint = monitor.edid.as_ref().map(Edid::fingerprint);
+            manages(&monitor.name, fingerprint.as_deref())
+        })
+        .collect()
+}
+
+/// The output of a monitor split by a profile, and the part of the output, e.g. ("DP-1", Some(2))
+/// for DP-1~2.
+pub fn split_part(name: &str) -> (&str, Option<u32>) {
+    match name.rsplit_once('~') {
+        Some((output, part)) => match part.parse() {
+            Ok(part) => (output, Some(part)),
+            Err(_) => (name, None),
+        },
+        None => (name, None),
+    }
+}
+
+/// Identifies the monitor shown on the output: its EDID fingerprint, followed by the part of the
+/// monitor when it is split, like "DEL-A0F1-ABC123~2".
+pub fn monitor_id(fingerprints: &BTreeMap<String, Option<String>>, output: &str) -> Option<String> {
+    let (output, part) = split_part(output);
+    let fingerprint = fingerprints.get(output)?.clone()?;
+    match part {
+        Some(part) => Some(format!("{}~{}", fingerprint, part)),
+        None => Some(fingerprint),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::edid::Edid;
+
+    use super::{Alias, MonitorData, alias_connectors, connected_outputs, managed, monitor_id, split_part};
+
+    fn edid(serial: u32, serial_string: Option<&str>) -> Edid {
+        Edid {
+            manufacturer: "DEL".to_string(),
+            product: 0xA0F1,
+            serial,
+            model: Some("DELL U2720Q".to_string()),
+            serial_string: serial_string.map(str::to_string),
+            preferred_size: Some((3840, 2160)),
+            size_mm: Some((600, 340)),
+        }
+    }
+
+    fn monitor(name: &str, edid: Option<Edid>) -> MonitorData {
+        MonitorData {
+            name: name.to_string(),
+            connected: name != "HDMI-1",
+            edid,
+            mode: None,
+        }
+    }
+
+    #[test]
+    fn aliases() {
+        let alias = |serial: Option<&str>| Alias { model: "DELL U2720Q".to_string(), serial: serial.map(str::to_string) };
+        let aliases = BTreeMap::from([
+            ("left".to_string(), alias(Some("ABC123"))),
+            ("right".to_string(), alias(Some("42"))),
+            ("gone".to_string(), alias(Some("XYZ"))),
+        ]);
+        let monitors = BTreeMap::from([("DP-1".to_string(), edid(0, Some("ABC123"))), ("DP-2".to_string(), edid(42, None))]);
+        assert_eq!(alias_connectors(&aliases, &monitors), BTreeMap::from([
+            ("left".to_string(), "DP-1".to_string()),
+            ("right".to_string(), "DP-2".to_string()),
+        ]));
+        assert!(alias(None).matches(&edid(0, None)));
+        assert!(!Alias { model: "DELL U2723QE".to_string(), serial: None }.matches(&edid(0, None)));
+    }
+
+    #[test]
+    fn detection() {
+        let monitor_data = vec![monitor("eDP-1", None), monitor("DP-1", Some(edid(0, Some("ABC123")))), monitor("HDMI-1", None)];
+        assert_eq!(connected_outputs(&monitor_data), BTreeMap::from([
+            ("DP-1".to_string(), Some("DEL-A0F1-ABC123".to_string())),
+            ("eDP-1".to_string(), None),
+        ]));
+        let managed: Vec<_> = managed(monitor_data, |name, fingerprint| name == "eDP-1" || fingerprint.is_some()).into_iter()
+            .map(|monitor| monitor.name)
+            .collect();
+        assert_eq!(managed, ["eDP-1", "DP-1"]);
+    }
+
+    #[test]
+    fn split_monitors() {
+        assert_eq!(split_part("DP-1~2"), ("DP-1", Some(2)));
+        assert_eq!(split_part("DP-1"), ("DP-1", None));
+        assert_eq!(split_part("DP-1~left"), ("DP-1~left", None));
+
+        let fingerprints = BTreeMap::from([("DP-1".to_string(), Some("DEL-A0F1-ABC123".to_string())), ("eDP-1".to_string(), None)]);
+        assert_eq!(monitor_id(&fingerprints, "DP-1~2").as_deref(), Some("DEL-A0F1-ABC123~2"));
+        assert_eq!(monitor_id(&fingerprints, "DP-1").as_deref(), Some("DEL-A0F1-ABC123"));
+        assert_eq!(monitor_id(&fingerprints, "eDP-1"), None);
+        assert_eq!(monitor_id(&fingerprints, "DP-2"), None);
+    }
+}
@@ -0,0 +1,623 @@
+//! Planning of the restoration of the workspaces after the outputs changed.
+//!
+//! The plan is computed from the workspace history kept by the daemon, the state of the window
+//! manager before the reconfiguration and its current state, and is returned as the i3 commands
+//! to run, so that it can be inspected before sending it as a single message.
+
+use std::collections::BTreeMap;
+
+use i3ipc_types::reply::{self, FullscreenMode, Rect};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, Profile},
+    matching::mst_port,
+    outputs::{monitor_id, split_part},
+};
+
+/// A workspace of the history kept across the reconfigurations.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Workspace {
+    pub focused: bool,
+    pub visible: bool,
+    pub name: String,
+    /// -1 for the named workspaces without a number.
+    pub num: i32,
+    pub output: String,
+    pub previous_output: Option<String>,
+    /// EDID fingerprint of the monitor of the previous output, or of the output while it is
+    /// active, to find the monitor back when it comes back on another connector.
+    #[serde(default)]
+    pub monitor: Option<String>,
+    /// Whether it was visible on its previous output, to show it again when the output comes back.
+    pub was_visible: bool,
+    /// Whether it had the focus when its output was disconnected. Only used by the next
+    /// restoration, to give it the focus back.
+    pub was_focused: bool,
+    /// i3 destroys the empty workspaces when they are not visible anymore.
+    pub present: bool,
+}
+
+/// A window or split container of the tree.
+pub struct Container {
+    pub id: usize,
+    /// Name of the workspace it is on.
+    pub workspace: String,
+    pub output: String,
+    /// Geometry of the output.
+    pub output_rect: Rect,
+    pub focused: bool,
+    pub fullscreen_mode: FullscreenMode,
+    pub floating: bool,
+    /// For a floating window, the geometry of the floating container around it.
+    pub rect: Rect,
+}
+
+/// State of the window manager before the reconfiguration.
+///
+/// Since i3 creates empty workspaces, it lists the existing workspaces to avoid focusing
+/// unexisting workspaces later.
+pub struct Snapshot {
+    pub existing_workspaces: Vec<String>,
+    pub focused_workspace: Option<String>,
+    /// Workspace focused before the focused one, for back_and_forth, when it is known.
+    pub previous_workspace: Option<String>,
+    /// Workspace visible on each output.
+    pub visible_workspaces: BTreeMap<String, String>,
+    /// Id of the container that had the keyboard focus, and its workspace.
+    pub focused_container: Option<(usize, String)>,
+    pub fullscreen_containers: Vec<Container>,
+    pub floating_containers: Vec<Container>,
+}
+
+/// The state of the window manager once the outputs are configured.
+pub struct Current<'a> {
+    pub containers: &'a [Container],
+    /// Geometry of each active output.
+    pub outputs: &'a BTreeMap<String, Rect>,
+    /// The active outputs that are not ignored by the configuration.
+    pub active: &'a [String],
+    /// Output picked by the configuration for each workspace assigned to an output.
+    pub assigned: &'a BTreeMap<String, String>,
+    /// There is nowhere to redistribute the workspaces while mirroring.
+    pub mirroring: bool,
+}
+
+/// What restoring the workspaces does.
+pub struct Plan {
+    /// Commands to run, in order.
+    pub commands: Vec<String>,
+    /// Output each workspace is moved to.
+    pub moves: BTreeMap<String, String>,
+}
+
+/// Updates the history of a workspace reported by the window manager, from its entry in the
+/// history, if any. `active` are the active outputs and `fingerprints` the connected outputs with
+/// the fingerprint of their monitor.
+pub fn track(old: Option<&Workspace>, workspace: &reply::Workspace, active: &[String], fingerprints: &BTreeMap<String, Option<String>>)
+    -> Workspace
+{
+    let mut previous_output = None;
+    let mut monitor = None;
+    let mut was_visible = false;
+    let mut was_focused = false;
+    if let Some(old) = old {
+        // If there was no change, keep the old data.
+        if workspace.output == old.output {
+            previous_output = old.previous_output.clone();
+            monitor = old.monitor.clone();
+            was_visible = old.was_visible;
+            was_focused = old.was_focused;
+        }
+        // If there was a change after the monitor was disconnected.
+        else if !active.contains(&old.output) {
+            previous_output = Some(old.output.clone());
+            monitor = old.monitor.clone();
+            was_visible = old.visible;
+            was_focused = old.focused;
+        }
+    }
+    if previous_output.is_none() {
+        monitor = monitor_id(fingerprints, &workspace.output);
+    }
+
+    Workspace {
+        focused: workspace.focused,
+        visible: workspace.visible,
+        name: workspace.name.clone(),
+        num: workspace.num,
+        output: workspace.output.clone(),
+        previous_output,
+        monitor,
+        was_visible,
+        was_focused,
+        present: true,
+    }
+}
+
+/// Updates the history of a workspace that the window manager does not report anymore, and
+/// returns whether to keep it: the empty workspaces that disappeared with their monitor are kept
+/// to recreate them when it comes back, the others were simply left by the user.
+pub fn vanish(workspace: &mut Workspace, active: &[String]) -> bool {
+    workspace.present = false;
+    if workspace.previous_output.is_none() && !active.contains(&workspace.output) {
+        workspace.previous_output = Some(workspace.output.clone());
+        workspace.was_visible = workspace.visible;
+        workspace.was_focused = workspace.focused;
+    }
+    workspace.previous_output.is_some()
+}
+
+/// Docks renumber their outputs: returns the output that replaces the previous output of the
+/// workspace, the one its monitor is now connected to, or another branch of the same MST port.
+pub fn remap(workspace: &Workspace, active: &[String], fingerprints: &BTreeMap<String, Option<String>>) -> Option<String> {
+    let output = workspace.previous_output.as_ref()?;
+    let by_monitor = workspace.monitor.as_ref().and_then(|monitor| {
+        active.iter().find(|name| monitor_id(fingerprints, name).as_ref() == Some(monitor))
+    });
+    let (port, part) = split_part(output);
+    match by_monitor {
+        Some(name) => Some(name.clone()),
+        None if !active.contains(output) => active.iter()
+            .find(|name| {
+                let (other_port, other_part) = split_part(name);
+                *name != output && mst_port(other_port) == mst_port(port) && other_part == part
+            })
+            .cloned(),
+        None => None,
+    }
+}
+
+/// Plans the restoration: moves the workspaces back to their previous output, unless the
+/// configuration assigns them to another one, then shows again the workspaces that were visible
+/// and gives the focus back.
+pub fn plan(workspaces: &BTreeMap<String, Workspace>, snapshot: &Snapshot, current: &Current) -> Plan {
+    let mut commands = vec![];
+
+    let mut targets = BTreeMap::new();
+    if !current.mirroring {
+        for workspace in workspaces.values() {
+            if let Some(ref output) = workspace.previous_output {
+                if current.active.contains(output) {
+                    targets.insert(workspace.name.clone(), output.clone());
+                }
+            }
+        }
+        for (name, output) in current.assigned {
+            if workspaces.contains_key(name) {
+                targets.insert(name.clone(), output.clone());
+            }
+        }
+    }
+
+    let mut moves = BTreeMap::new();
+    for (name, output) in targets {
+        let command =
+            match workspaces.get(&name) {
+                Some(workspace) if workspace.present && workspace.output == output => continue,
+                // Criteria do not match the empty workspaces, so they need to be handled differently.
+                Some(_) if current.containers.iter().any(|container| container.workspace == name) => {
+                    format!("{} move workspace to output {}", workspace_criterion(&name), quote(&output))
+                },
+                // An empty workspace only survives when it is visible, so only bring back those
+                // that were: focusing it recreates it if needed.
+                Some(workspace) if workspace.was_visible || (workspace.present && workspace.visible) => {
+                    format!("{}; move workspace to output {}", focus_command(&name), quote(&output))
+                },
+                _ => continue,
+            };
+        commands.push(command);
+        moves.insert(name, output);
+    }
+    // Where each workspace is once moved.
+    let output_of = |name: &String| {
+        moves.get(name).cloned()
+            .or_else(|| workspaces.get(name).filter(|workspace| workspace.present).map(|workspace| workspace.output.clone()))
+    };
+
+    // Moving a workspace to another output can drop or mis-size its fullscreen window, and leave its
+    // floating windows off-screen when the resolution differs.
+    for container in &snapshot.fullscreen_containers {
+        let reassert = current.containers.iter()
+            .find(|current| current.id == container.id)
+            .is_some_and(|current| moves.contains_key(&current.workspace) || current.fullscreen_mode == FullscreenMode::None);
+        if reassert {
+            let global = if container.fullscreen_mode == FullscreenMode::Global { " global" } else { "" };
+            commands.push(format!("[con_id={}] fullscreen disable, fullscreen enable{}", container.id, global));
+        }
+    }
+    for container in &snapshot.floating_containers {
+        let new_output = current.containers.iter()
+            .find(|current| current.id == container.id && current.floating)
+            .and_then(|current| output_of(&current.workspace))
+            .and_then(|name| current.outputs.get_key_value(&name));
+        if let Some((name, rect)) = new_output {
+            commands.extend(rescale_floating(container, name, rect));
+        }
+    }
+
+    let exists = |workspace: &Workspace| {
+        snapshot.existing_workspaces.contains(&workspace.name) || !workspace.present
+    };
+
+    // Show again on every output the workspace it showed: the one that was visible when the output
+    // was disconnected, otherwise the one from before the reconfiguration.
+    let mut visible = snapshot.visible_workspaces.clone();
+    let mut came_back: Vec<_> = workspaces.values()
+        .filter(|workspace| workspace.was_visible && exists(workspace))
+        .filter_map(|workspace| Some((workspace_order(workspace), workspace.previous_output.clone()?)))
+        .collect();
+    came_back.sort_unstable();
+    for ((_, _, name), output) in came_back.into_iter().rev() {
+        visible.insert(output, name);
+    }
+    for (output, name) in visible {
+        // The workspace command would bring it to the focused output otherwise.
+        if output_of(&name).as_ref() == Some(&output) {
+            commands.push(format!("focus output {}; {}", quote(&output), focus_command(&name)));
+        }
+    }
+
+    // Then give the focus back, last so that nothing steals it: to the workspace that had it when
+    // its output was disconnected, otherwise to the one that had it before the reconfiguration.
+    let focused = workspaces.values()
+        .filter(|workspace| workspace.was_focused && exists(workspace))
+        .map(workspace_order)
+        .min()
+        .map(|(_, _, name)| name)
+        .or_else(|| snapshot.focused_workspace.clone().filter(|name| snapshot.existing_workspaces.contains(name)));
+    if let Some(name) = focused {
+        // Focus the workspace that was focused before it first, so that back_and_forth still goes
+        // there.
+        if let Some(ref previous) = snapshot.previous_workspace {
+            if previous != &name && snapshot.existing_workspaces.contains(previous) {
+                commands.push(focus_command(previous));
+            }
+        }
+        commands.push(focus_command(&name));
+
+        // And to the window that had it, if it is still there.
+        if let Some((id, ref workspace)) = snapshot.focused_container {
+            let still_there = current.containers.iter()
+                .any(|container| container.id == id && &container.workspace == workspace);
+            if workspace == &name && still_there {
+                commands.push(format!("[con_id={}] focus", id));
+            }
+        }
+    }
+
+    Plan {
+        commands,
+        moves,
+    }
+}
+
+/// The output that each present workspace goes to once the profile is applied, like in [`plan`]:
+/// the one the configuration assigns it to, otherwise its previous output, otherwise the one it is
+/// on, among the `enabled` outputs of the profile. None when its output is turned off and the window
+/// manager picks another one.
+pub fn planned_outputs(config: &Config, profile: &Profile, enabled: &[String], workspaces: &[Workspace]) -> BTreeMap<String, Option<String>> {
+    let active: Vec<_> = enabled.iter()
+        .filter(|name| !config.ignores(name))
+        .cloned()
+        .collect();
+    workspaces.iter()
+        .filter(|workspace| workspace.present)
+        .map(|workspace| {
+            let assigned = config.workspaces.get(&workspace.name)
+                .and_then(|chain| chain.pick(&active, profile.primary.as_ref()));
+            let previous = workspace.previous_output.as_ref().filter(|output| active.contains(output));
+            let current = Some(&workspace.output).filter(|output| active.contains(output));
+            (workspace.name.clone(), assigned.or(previous).or(current).cloned())
+        })
+        .collect()
+}
+
+/// Sort key of the workspaces: the numbered workspaces come first, in order, then the named ones.
+pub fn workspace_order(workspace: &Workspace) -> (bool, i32, String) {
+    (workspace.num < 0, workspace.num, workspace.name.clone())
+}
+
+/// Quotes a string for an i3 command.
+pub fn quote(string: &str) -> String {
+    format!("\"{}\"", string.replace('"', "\\\""))
+}
+
+/// Criterion matching exactly the workspace, since its value is a regular expression.
+pub fn workspace_criterion(name: &str) -> String {
+    let mut regex = String::from("^");
+    for character in name.chars() {
+        if "\\^$.|?*+()[]{}".contains(character) {
+            regex.push('\\');
+        }
+        regex.push(character);
+    }
+    regex.push('$');
+    format!("[workspace={}]", quote(&regex))
+}
+
+/// Command focusing the workspace, without auto_back_and_forth, which would switch to another
+/// workspace when this one is already focused.
+pub fn focus_command(name: &str) -> String {
+    format!("workspace --no-auto-back-and-forth {}", quote(name))
+}
+
+/// Command moving and resizing a floating window so that it keeps its place relative to its
+/// output.
+pub fn rescale_floating(old: &Container, new_output: &str, new_rect: &Rect) -> Option<String> {
+    let old_rect = &old.output_rect;
+    if (old.output == new_output && old_rect == new_rect) || old_rect.width <= 0 || old_rect.height <= 0 {
+        return None;
+    }
+
+    let scale_x = new_rect.width as f64 / old_rect.width as f64;
+    let scale_y = new_rect.height as f64 / old_rect.height as f64;
+    let x = new_rect.x + ((old.rect.x - old_rect.x) as f64 * scale_x) as isize;
+    let y = new_rect.y + ((old.rect.y - old_rect.y) as f64 * scale_y) as isize;
+    let width = (old.rect.width as f64 * scale_x) as isize;
+    let height = (old.rect.height as f64 * scale_y) as isize;
+    Some(format!("[con_id={}] move absolute position {} px {} px, resize set {} px {} px", old.id, x, y, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use i3ipc_types::reply::{self, FullscreenMode, Rect};
+
+    use crate::config::{Config, Profile};
+
+    use super::{Container, Current, Plan, Snapshot, Workspace, plan, planned_outputs, remap, rescale_floating, track, vanish, workspace_criterion};
+
+    fn rect(x: isize, y: isize, width: isize, height: isize) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    fn workspace(name: &str, output: &str, previous_output: Option<&str>) -> Workspace {
+        Workspace {
+            focused: false,
+            visible: false,
+            name: name.to_string(),
+            num: name.parse().unwrap_or(-1),
+            output: output.to_string(),
+            previous_output: previous_output.map(str::to_string),
+            monitor: None,
+            was_visible: false,
+            was_focused: false,
+            present: true,
+        }
+    }
+
+    fn history(workspaces: Vec<Workspace>) -> BTreeMap<String, Workspace> {
+        workspaces.into_iter()
+            .map(|workspace| (workspace.name.clone(), workspace))
+            .collect()
+    }
+
+    fn container(id: usize, workspace: &str, output: &str) -> Container {
+        Container {
+            id,
+            workspace: workspace.to_string(),
+            output: output.to_string(),
+            output_rect: rect(0, 0, 1920, 1080),
+            focused: false,
+            fullscreen_mode: FullscreenMode::None,
+            floating: false,
+            rect: rect(0, 0, 1920, 1080),
+        }
+    }
+
+    fn snapshot(existing: &[&str], focused: Option<&str>, visible: &[(&str, &str)]) -> Snapshot {
+        Snapshot {
+            existing_workspaces: existing.iter().map(|name| name.to_string()).collect(),
+            focused_workspace: focused.map(str::to_string),
+            previous_workspace: None,
+            visible_workspaces: visible.iter().map(|(output, name)| (output.to_string(), name.to_string())).collect(),
+            focused_container: None,
+            fullscreen_containers: vec![],
+            floating_containers: vec![],
+        }
+    }
+
+    // The laptop panel and an external monitor on its right.
+    fn run(workspaces: &BTreeMap<String, Workspace>, snapshot: &Snapshot, containers: &[Container]) -> Plan {
+        let outputs = BTreeMap::from([
+            ("eDP-1".to_string(), rect(0, 0, 1920, 1080)),
+            ("DP-2".to_string(), rect(1920, 0, 3840, 2160)),
+        ]);
+        let active = ["eDP-1".to_string(), "DP-2".to_string()];
+        plan(workspaces, snapshot, &Current {
+            containers,
+            outputs: &outputs,
+            active: &active,
+            assigned: &BTreeMap::new(),
+            mirroring: false,
+        })
+    }
+
+    fn fingerprints(outputs: &[(&str, &str)]) -> BTreeMap<String, Option<String>> {
+        outputs.iter()
+            .map(|(output, fingerprint)| (output.to_string(), Some(fingerprint.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn moves_workspace_back() {
+        let workspaces = history(vec![
+            Workspace { visible: true, focused: true, ..workspace("1", "eDP-1", None) },
+            Workspace { was_visible: true, ..workspace("2", "eDP-1", Some("DP-2")) },
+        ]);
+        let plan = run(&workspaces, &snapshot(&["1", "2"], Some("1"), &[("eDP-1", "1")]), &[container(5, "2", "eDP-1")]);
+
+        assert_eq!(plan.commands, [
+            r#"[workspace="^2$"] move workspace to output "DP-2""#,
+            r#"focus output "DP-2"; workspace --no-auto-back-and-forth "2""#,
+            r#"focus output "eDP-1"; workspace --no-auto-back-and-forth "1""#,
+            r#"workspace --no-auto-back-and-forth "1""#,
+        ]);
+        assert_eq!(plan.moves, BTreeMap::from([("2".to_string(), "DP-2".to_string())]));
+    }
+
+    #[test]
+    fn recreates_visible_empty_workspace() {
+        let workspaces = history(vec![
+            Workspace { present: false, was_visible: true, ..workspace("3", "DP-2", Some("DP-2")) },
+            Workspace { present: false, ..workspace("4", "DP-2", Some("DP-2")) },
+        ]);
+        let plan = run(&workspaces, &snapshot(&[], None, &[]), &[]);
+
+        assert_eq!(plan.commands, [
+            r#"workspace --no-auto-back-and-forth "3"; move workspace to output "DP-2""#,
+            r#"focus output "DP-2"; workspace --no-auto-back-and-forth "3""#,
+        ]);
+    }
+
+    #[test]
+    fn reasserts_fullscreen() {
+        let workspaces = history(vec![workspace("1", "eDP-1", None), workspace("2", "eDP-1", Some("DP-2"))]);
+        let mut snapshot = snapshot(&["1", "2"], None, &[]);
+        snapshot.fullscreen_containers = vec![
+            Container { fullscreen_mode: FullscreenMode::Output, ..container(10, "2", "eDP-1") },
+            Container { fullscreen_mode: FullscreenMode::Global, ..container(12, "1", "eDP-1") },
+            Container { fullscreen_mode: FullscreenMode::Output, ..container(13, "1", "eDP-1") },
+        ];
+        let containers = [
+            // Moved with its workspace.
+            Container { fullscreen_mode: FullscreenMode::Output, ..container(10, "2", "eDP-1") },
+            // Lost its fullscreen mode.
+            container(12, "1", "eDP-1"),
+            Container { fullscreen_mode: FullscreenMode::Output, ..container(13, "1", "eDP-1") },
+        ];
+        let plan = run(&workspaces, &snapshot, &containers);
+
+        let fullscreen: Vec<_> = plan.commands.iter()
+            .filter(|command| command.contains("fullscreen"))
+            .collect();
+        assert_eq!(fullscreen, [
+            "[con_id=10] fullscreen disable, fullscreen enable",
+            "[con_id=12] fullscreen disable, fullscreen enable global",
+        ]);
+    }
+
+    #[test]
+    fn rescales_floating_window() {
+        let workspaces = history(vec![workspace("2", "eDP-1", Some("DP-2"))]);
+        let floating = Container { floating: true, rect: rect(960, 540, 480, 270), ..container(11, "2", "eDP-1") };
+        let mut snapshot = snapshot(&["2"], None, &[]);
+        snapshot.floating_containers = vec![floating];
+        let plan = run(&workspaces, &snapshot, &[Container { floating: true, ..container(11, "2", "eDP-1") }]);
+
+        assert!(plan.commands.contains(&"[con_id=11] move absolute position 3840 px 1080 px, resize set 960 px 540 px".to_string()),
+            "{:?}", plan.commands);
+    }
+
+    #[test]
+    fn gives_focus_back() {
+        let workspaces = history(vec![
+            workspace("1", "eDP-1", None),
+            Workspace { was_focused: true, ..workspace("2", "eDP-1", Some("DP-2")) },
+        ]);
+        let mut snapshot = snapshot(&["1", "2"], Some("1"), &[]);
+        snapshot.previous_workspace = Some("1".to_string());
+        snapshot.focused_container = Some((7, "2".to_string()));
+        let plan = run(&workspaces, &snapshot, &[container(7, "2", "eDP-1")]);
+
+        assert_eq!(plan.commands[plan.commands.len() - 3..], [
+            r#"workspace --no-auto-back-and-forth "1""#,
+            r#"workspace --no-auto-back-and-forth "2""#,
+            "[con_id=7] focus",
+        ]);
+    }
+
+    #[test]
+    fn criterion() {
+        assert_eq!(workspace_criterion("1"), r#"[workspace="^1$"]"#);
+        assert_eq!(workspace_criterion("1: www (2).*"), r#"[workspace="^1: www \(2\)\.\*$"]"#);
+        assert_eq!(workspace_criterion(r#"say "hi""#), r#"[workspace="^say \"hi\"$"]"#);
+    }
+
+    #[test]
+    fn floating_keeps_its_place() {
+        let old = Container { floating: true, rect: rect(480, 270, 960, 540), ..container(11, "2", "eDP-1") };
+        assert_eq!(rescale_floating(&old, "DP-2", &rect(1920, 0, 3840, 2160)).as_deref(),
+            Some("[con_id=11] move absolute position 2880 px 540 px, resize set 1920 px 1080 px"));
+        assert_eq!(rescale_floating(&old, "eDP-1", &rect(0, 0, 1920, 1080)), None);
+        assert_eq!(rescale_floating(&Container { output_rect: rect(0, 0, 0, 0), ..old }, "DP-2", &rect(1920, 0, 3840, 2160)), None);
+    }
+
+    #[test]
+    fn tracks_disconnected_output() {
+        let fingerprints = fingerprints(&[("eDP-1", "BOE-0A1B-00000000")]);
+        let old = Workspace { visible: true, monitor: Some("DEL-A0F1-ABC123".to_string()), ..workspace("2", "DP-2", None) };
+        let moved = reply::Workspace {
+            id: 1,
+            num: 2,
+            name: "2".to_string(),
+            visible: false,
+            focused: false,
+            urgent: false,
+            rect: rect(0, 0, 1920, 1080),
+            output: "eDP-1".to_string(),
+        };
+
+        let tracked = track(Some(&old), &moved, &["eDP-1".to_string()], &fingerprints);
+        assert_eq!(tracked.previous_output.as_deref(), Some("DP-2"));
+        assert_eq!(tracked.monitor.as_deref(), Some("DEL-A0F1-ABC123"));
+        assert!(tracked.was_visible);
+        assert_eq!(track(Some(&tracked), &moved, &["eDP-1".to_string()], &fingerprints).previous_output.as_deref(), Some("DP-2"));
+
+        // Moved by the user.
+        let tracked = track(Some(&old), &moved, &["eDP-1".to_string(), "DP-2".to_string()], &fingerprints);
+        assert_eq!(tracked.previous_output, None);
+        assert_eq!(tracked.monitor.as_deref(), Some("BOE-0A1B-00000000"));
+    }
+
+    #[test]
+    fn keeps_workspace_of_disconnected_output() {
+        let active = ["eDP-1".to_string()];
+        let mut gone = Workspace { visible: true, ..workspace("3", "DP-2", None) };
+        assert!(vanish(&mut gone, &active));
+        assert_eq!(gone.previous_output.as_deref(), Some("DP-2"));
+        assert!(!gone.present && gone.was_visible);
+        assert!(!vanish(&mut workspace("4", "eDP-1", None), &active));
+    }
+
+    #[test]
+    fn remaps_renumbered_output() {
+        let fingerprints = fingerprints(&[("DP-3", "DEL-A0F1-ABC123"), ("DP-1-2", "DEL-A0F1-ABC124")]);
+        let active = ["DP-3".to_string(), "DP-1-2".to_string()];
+
+        let moved_monitor = Workspace { monitor: Some("DEL-A0F1-ABC123".to_string()), ..workspace("1", "eDP-1", Some("DP-2")) };
+        assert_eq!(remap(&moved_monitor, &active, &fingerprints).as_deref(), Some("DP-3"));
+        let other_branch = workspace("2", "eDP-1", Some("DP-1-1"));
+        assert_eq!(remap(&other_branch, &active, &fingerprints).as_deref(), Some("DP-1-2"));
+        assert_eq!(remap(&workspace("3", "eDP-1", Some("DP-4")), &active, &fingerprints), None);
+        assert_eq!(remap(&workspace("4", "DP-3", None), &active, &fingerprints), None);
+    }
+
+    #[test]
+    fn plans_outputs_of_profile() {
+        let config: Config = toml::from_str(r#"
+            ignore = ["DP-3"]
+
+            [workspaces]
+            "9" = ["DP-3", "primary"]
+        "#).expect("config");
+        let profile: Profile = toml::from_str(r#"
+            primary = "eDP-1"
+            [outputs.eDP-1]
+        "#).expect("profile");
+        let workspaces = [
+            workspace("1", "eDP-1", Some("DP-2")),
+            workspace("2", "DP-2", None),
+            workspace("9", "DP-2", None),
+            Workspace { present: false, ..workspace("4", "DP-2", None) },
+        ];
+        let enabled = ["eDP-1".to_string(), "DP-3".to_string()];
+        assert_eq!(planned_outputs(&config, &profile, &enabled, &workspaces), BTreeMap::from([
+            ("1".to_string(), Some("eDP-1".to_string())),
+            ("2".to_string(), None),
+            ("9".to_string(), Some("eDP-1".to_string())),
+        ]));
+    }
+}
@@ -0,0 +1,232 @@
+//! Choice of the profile for the connected monitors, with the adjustments that depend on the state
+//! of the machine: the battery, the lid, the orientation of the device and the primary output.
+//!
+//! The daemon and the commands that apply, show or replay a profile all go through
+//! [`choose_profile`], so that they agree on what would be applied.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    config::{Config, OutputChain, PrimaryStrategy, Profile, Rotation, TimeOfDay},
+    edid::Edid,
+    outputs::{MonitorData, connected_outputs},
+};
+
+/// What decides the profile besides the connected monitors, gathered by the caller.
+#[derive(Clone, Debug)]
+pub struct Conditions {
+    /// The attached docks among [`Config::docks`].
+    pub docks: BTreeSet<String>,
+    pub now: TimeOfDay,
+    pub on_battery: bool,
+    pub lid_closed: bool,
+    /// Orientation of the device, from its accelerometer. Only used with `auto_rotate`.
+    pub orientation: Option<Rotation>,
+    /// Primary output chosen by the user since the last reconfiguration, kept unless the profile
+    /// names another one.
+    pub user_primary: Option<String>,
+}
+
+/// The profile with this name, even if it does not match the connected outputs, or the profile
+/// matching them, adjusted to the conditions and with its primary output chosen. `monitor_data`
+/// are the outputs managed by the daemon. Returns `None` when no profile has this name.
+pub fn choose_profile(config: &Config, monitor_data: &[MonitorData], name: Option<&str>, conditions: &Conditions) -> Option<Profile> {
+    let connected = connected_outputs(monitor_data);
+    let mut profile =
+        match name {
+            Some(name) => config.named_profile(name, &connected)?,
+            None => config.profile_for(&connected, &conditions.docks, conditions.now),
+        };
+    if profile.primary.is_none() {
+        profile.primary = conditions.user_primary.clone();
+    }
+    if conditions.on_battery {
+        profile = profile.on_battery();
+    }
+    if config.lid_switch && conditions.lid_closed {
+        close_lid(&mut profile);
+    }
+    if let (true, Some(rotation)) = (config.auto_rotate, conditions.orientation) {
+        rotate_internal(&mut profile, rotation);
+    }
+    Some(select_primary(config, profile, monitor_data))
+}
+
+/// Whether the connector is the internal panel of a laptop.
+pub fn is_internal(name: &str) -> bool {
+    ["eDP", "LVDS", "DSI"].iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+// Disables the internal panel, unless it is the only enabled output.
+fn close_lid(profile: &mut Profile) {
+    let external_enabled = profile.outputs.iter()
+        .any(|(name, output_config)| output_config.enabled && !is_internal(name));
+    if !external_enabled {
+        return;
+    }
+    for (name, output_config) in &mut profile.outputs {
+        if is_internal(name) {
+            output_config.enabled = false;
+        }
+    }
+}
+
+// The touchscreens follow, since they are mapped to their output after every reconfiguration.
+fn rotate_internal(profile: &mut Profile, rotation: Rotation) {
+    for (name, output_config) in &mut profile.outputs {
+        if is_internal(name) {
+            output_config.rotate = Some(rotation);
+        }
+    }
+}
+
+// When the profile does not name a connected primary output, picks the first connected one of the
+// configuration, or one chosen with the strategy.
+fn select_primary(config: &Config, mut profile: Profile, monitor_data: &[MonitorData]) -> Profile {
+    let enabled: Vec<_> = monitor_data.iter()
+        .filter(|monitor| monitor.connected)
+        .filter(|monitor| profile.outputs.get(&monitor.name).is_some_and(|output_config| output_config.enabled))
+        .collect();
+    let primary_enabled = profile.primary.as_ref()
+        .is_some_and(|primary| enabled.iter().any(|monitor| &monitor.name == primary));
+    if primary_enabled {
+        return profile;
+    }
+
+    let preferred = config.primary.iter()
+        .flat_map(OutputChain::outputs)
+        .find(|name| enabled.iter().any(|monitor| &&monitor.name == name));
+    if let Some(preferred) = preferred {
+        profile.primary = Some(preferred.clone());
+        return profile;
+    }
+
+    let edid_size = |monitor: &MonitorData, size: fn(&Edid) -> Option<(u32, u32)>| {
+        monitor.edid.as_ref()
+            .and_then(size)
+            .map(|(width, height)| width * height)
+            .unwrap_or_default()
+    };
+    // The first one by name among the best ones.
+    let mut candidates = enabled;
+    candidates.sort_by(|monitor1, monitor2| monitor1.name.cmp(&monitor2.name));
+    let primary =
+        match config.primary_strategy {
+            PrimaryStrategy::First => candidates.first(),
+            PrimaryStrategy::LargestResolution => candidates.iter()
+                .rev()
+                .max_by_key(|monitor| edid_size(monitor, |edid| edid.preferred_size)),
+            PrimaryStrategy::LargestSize => candidates.iter()
+                .rev()
+                .max_by_key(|monitor| edid_size(monitor, |edid| edid.size_mm)),
+            PrimaryStrategy::InternalLast => candidates.iter()
+                .find(|monitor| !is_internal(&monitor.name))
+                .or(candidates.first()),
+        };
+    profile.primary = primary.map(|monitor| monitor.name.clone());
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        config::{Config, Rotation, TimeOfDay},
+        outputs::MonitorData,
+    };
+
+    use super::{Conditions, choose_profile};
+
+    fn monitor(name: &str) -> MonitorData {
+        MonitorData {
+            name: name.to_string(),
+            connected: true,
+            edid: None,
+            mode: None,
+        }
+    }
+
+    fn conditions() -> Conditions {
+        Conditions {
+            docks: BTreeSet::new(),
+            now: TimeOfDay::try_from("12:00".to_string()).expect("time"),
+            on_battery: false,
+            lid_closed: false,
+            orientation: None,
+            user_primary: None,
+        }
+    }
+
+    fn config(content: &str) -> Config {
+        toml::from_str(content).expect("config")
+    }
+
+    const DOCKED: &str = r#"
+        lid_switch = true
+        auto_rotate = true
+
+        [[profiles]]
+        name = "docked"
+        [profiles.outputs.eDP-1]
+        [profiles.outputs.DP-1]
+        [profiles.battery.DP-1]
+        enabled = false
+    "#;
+
+    #[test]
+    fn matching_profile() {
+        let config = config(DOCKED);
+        let monitors = [monitor("eDP-1"), monitor("DP-1")];
+        let profile = choose_profile(&config, &monitors, None, &conditions()).expect("profile");
+        assert_eq!(profile.name, "docked");
+        assert!(profile.outputs.values().all(|output_config| output_config.enabled));
+        // The first enabled output by name.
+        assert_eq!(profile.primary.as_deref(), Some("DP-1"));
+
+        let profile = choose_profile(&config, &[monitor("eDP-1")], None, &conditions()).expect("profile");
+        assert_eq!(profile.name, "default");
+        assert!(choose_profile(&config, &monitors, Some("undocked"), &conditions()).is_none());
+    }
+
+    #[test]
+    fn conditions_adjust_the_profile() {
+        let config = config(DOCKED);
+        let monitors = [monitor("eDP-1"), monitor("DP-1")];
+        let profile = choose_profile(&config, &monitors, None, &Conditions {
+            lid_closed: true,
+            orientation: Some(Rotation::Left),
+            user_primary: Some("eDP-1".to_string()),
+            ..conditions()
+        }).expect("profile");
+        assert!(!profile.outputs["eDP-1"].enabled);
+        assert!(matches!(profile.outputs["eDP-1"].rotate, Some(Rotation::Left)));
+        // The primary output of the user is disabled with the lid.
+        assert_eq!(profile.primary.as_deref(), Some("DP-1"));
+
+        // The internal panel stays on when it is the only enabled output.
+        let profile = choose_profile(&config, &monitors, Some("docked"), &Conditions {
+            on_battery: true,
+            lid_closed: true,
+            user_primary: Some("eDP-1".to_string()),
+            ..conditions()
+        }).expect("profile");
+        assert!(profile.outputs["eDP-1"].enabled);
+        assert!(!profile.outputs["DP-1"].enabled);
+        assert_eq!(profile.primary.as_deref(), Some("eDP-1"));
+    }
+
+    #[test]
+    fn disabled_adjustments() {
+        let config = config(&DOCKED.replace("lid_switch = true", "").replace("auto_rotate = true", ""));
+        let monitors = [monitor("eDP-1"), monitor("DP-1")];
+        let profile = choose_profile(&config, &monitors, None, &Conditions {
+            lid_closed: true,
+            orientation: Some(Rotation::Left),
+            ..conditions()
+        }).expect("profile");
+        assert!(profile.outputs["eDP-1"].enabled);
+        assert!(profile.outputs["eDP-1"].rotate.is_none());
+    }
+}
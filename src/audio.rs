@@ -0,0 +1,55 @@
+// Default audio sink, through pactl, which talks to both PulseAudio and PipeWire. The HDMI and
+// DisplayPort sinks are found by the name of the monitor, which their ports describe.
+
+use std::{
+    io,
+    process::Command,
+};
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Sink {
+    name: String,
+    description: String,
+    #[serde(default)]
+    ports: Vec<Port>,
+}
+
+#[derive(Deserialize)]
+struct Port {
+    description: String,
+    availability: String,
+}
+
+// The sink playing on the monitor with this model name, if it has speakers.
+pub fn monitor_sink(model: &str) -> io::Result<Option<String>> {
+    let output = pactl(&["--format=json", "list", "sinks"])?;
+    let sinks: Vec<Sink> = serde_json::from_str(&output)?;
+    let sink = sinks.into_iter()
+        .find(|sink| {
+            sink.description.contains(model) || sink.ports.iter()
+                .any(|port| port.availability != "not available" && port.description.contains(model))
+        })
+        .map(|sink| sink.name);
+    Ok(sink)
+}
+
+pub fn default_sink() -> io::Result<String> {
+    Ok(pactl(&["get-default-sink"])?.trim().to_string())
+}
+
+pub fn set_default_sink(name: &str) -> io::Result<()> {
+    pactl(&["set-default-sink", name])?;
+    Ok(())
+}
+
+fn pactl(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("pactl").args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("pactl {} failed: {}", args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
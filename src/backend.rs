@@ -0,0 +1,231 @@
+// The operations on the display server and on the window manager that the reconfiguration and the
+// restoration of the workspaces rely on, behind traits so that other backends, or mocks, can be
+// swapped in: the ones in use are installed when the program starts.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, RwLock},
+};
+
+use i3_aww_core::{edid::Edid, layout, outputs::MonitorData};
+use i3_ipc::{
+    msg::Msg,
+    reply::{Node, Output, Success, Workspace},
+    Connect,
+    I3,
+    I3Stream,
+};
+use serde_json::Value;
+use xrandr::{Output as XOutput, XHandle};
+
+use crate::{
+    config::{ModePreference, Profile},
+    dry_run,
+    randr,
+    sway,
+    xrandr_profile,
+};
+
+static BACKENDS: RwLock<Option<Backends>> = RwLock::new(None);
+
+pub trait DisplayBackend: Send + Sync {
+    // The outputs, connected or not, with their monitor.
+    fn monitor_data(&self) -> Vec<MonitorData>;
+    fn apply(&self, profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()>;
+    // The current configuration of the outputs, as a profile with this name.
+    fn current_profile(&self, name: &str) -> io::Result<Profile>;
+}
+
+pub trait WmBackend {
+    fn workspaces(&mut self) -> io::Result<Vec<Workspace>>;
+    fn outputs(&mut self) -> io::Result<Vec<Output>>;
+    // The outputs as the window manager sends them, with the fields that only sway has, like the
+    // modes.
+    fn outputs_json(&mut self) -> io::Result<Value>;
+    fn tree(&mut self) -> io::Result<Node>;
+    // Runs the commands as a single message, returning the result of each one.
+    fn run_commands(&mut self, commands: &[String]) -> io::Result<Vec<Success>>;
+}
+
+// Opens a connection to the window manager, for a series of requests.
+pub trait WmConnector: Send + Sync {
+    fn connect(&self) -> io::Result<Box<dyn WmBackend>>;
+}
+
+#[derive(Clone)]
+struct Backends {
+    display: Arc<dyn DisplayBackend>,
+    wm: Arc<dyn WmConnector>,
+}
+
+// Replaces the backends used from now on.
+pub fn install(display: Arc<dyn DisplayBackend>, wm: Arc<dyn WmConnector>) {
+    *BACKENDS.write().expect("backends lock") = Some(Backends { display, wm });
+}
+
+fn backends() -> Backends {
+    BACKENDS.read().expect("backends lock").clone()
+        .expect("backends installed")
+}
+
+pub fn display() -> Arc<dyn DisplayBackend> {
+    backends().display
+}
+
+pub fn wm() -> io::Result<Box<dyn WmBackend>> {
+    backends().wm.connect()
+}
+
+// The outputs configured through RandR.
+pub struct Xrandr;
+
+impl DisplayBackend for Xrandr {
+    fn monitor_data(&self) -> Vec<MonitorData> {
+        xrandr_monitor_data()
+    }
+
+    fn apply(&self, profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()> {
+        apply_randr(profile, monitor_data)
+    }
+
+    fn current_profile(&self, name: &str) -> io::Result<Profile> {
+        xrandr_profile(name)
+    }
+}
+
+// The outputs configured through the IPC of sway.
+pub struct SwayOutputs;
+
+impl DisplayBackend for SwayOutputs {
+    fn monitor_data(&self) -> Vec<MonitorData> {
+        sway::monitor_data()
+    }
+
+    fn apply(&self, profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()> {
+        sway::apply(&mut *wm()?, profile, monitor_data)
+    }
+
+    fn current_profile(&self, name: &str) -> io::Result<Profile> {
+        sway::current_profile(&mut *wm()?, name)
+    }
+}
+
+// i3 and sway, through the socket of I3SOCK.
+pub struct I3Ipc;
+
+impl WmConnector for I3Ipc {
+    fn connect(&self) -> io::Result<Box<dyn WmBackend>> {
+        Ok(Box::new(I3::connect()?))
+    }
+}
+
+// i3 and sway share the same IPC.
+impl WmBackend for I3Stream {
+    fn workspaces(&mut self) -> io::Result<Vec<Workspace>> {
+        self.get_workspaces()
+    }
+
+    fn outputs(&mut self) -> io::Result<Vec<Output>> {
+        self.get_outputs()
+    }
+
+    fn outputs_json(&mut self) -> io::Result<Value> {
+        Ok(self.send_receive(Msg::Outputs, "")?.body)
+    }
+
+    fn tree(&mut self) -> io::Result<Node> {
+        self.get_tree()
+    }
+
+    fn run_commands(&mut self, commands: &[String]) -> io::Result<Vec<Success>> {
+        self.run_command(commands.join("; "))
+    }
+}
+
+fn xrandr_monitor_data() -> Vec<MonitorData> {
+    let modes: BTreeMap<_, _> = randr::query().unwrap_or_default().into_iter()
+        .filter_map(|output| {
+            let mode = format!("{}@{}", output.mode?, output.rate.unwrap_or_default());
+            Some((output.name, mode))
+        })
+        .collect();
+
+    let outputs = xrandr_outputs();
+    let mut monitor_data = vec![];
+    for output in outputs {
+        let edid = output.edid();
+        monitor_data.push(MonitorData {
+            mode: modes.get(&output.name).cloned(),
+            name: output.name,
+            connected: edid.is_some(),
+            edid: edid.and_then(|edid| Edid::parse(&edid)),
+        });
+    }
+    monitor_data
+}
+
+// Headless outputs have no mode until one is created for them.
+fn add_headless_mode(name: &str, mode: Option<&ModePreference>) -> io::Result<()> {
+    let (width, height, rate) =
+        match mode {
+            Some(ModePreference::Exact { mode, rate }) => {
+                let (width, height) = layout::mode_size(mode)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid mode {}", mode)))?;
+                (width as u32, height as u32, rate.unwrap_or(60.0))
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("the headless output {} needs an exact mode, like 1920x1080", name))),
+        };
+    randr::add_mode(name, width, height, rate)
+}
+
+fn apply_randr(profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()> {
+    if !dry_run() {
+        for monitor in monitor_data {
+            let headless = profile.outputs.get(&monitor.name)
+                .filter(|output_config| output_config.headless && output_config.enabled);
+            if let Some(output_config) = headless {
+                add_headless_mode(&monitor.name, output_config.mode.as_ref())?;
+            }
+        }
+    }
+
+    let current = randr::query();
+    let modes = randr::output_modes(current.as_deref().unwrap_or_default());
+    let (mut desired, dpi) = layout::desired_outputs(profile, monitor_data, &modes);
+
+    // Only touch the outputs that change, to avoid flicker and needless mode sets.
+    let changed =
+        match current {
+            Ok(current) => randr::changed_outputs(&desired, &current),
+            Err(_) => desired.iter().map(|output| output.name.clone()).collect(),
+        };
+    if changed.is_empty() {
+        if dry_run() {
+            tracing::info!(profile = %profile.name, "The outputs would not change");
+        }
+        return Ok(());
+    }
+
+    desired.retain(|output| changed.contains(&output.name));
+    let _span = tracing::info_span!("xrandr", profile = %profile.name).entered();
+    for output in &desired {
+        tracing::debug!(output = %output.name, "{} {}", output.name,
+            if output.enabled { output.args.join(" ") } else { "--off".to_string() });
+    }
+    if dry_run() {
+        let args = layout::xrandr_args(&desired, dpi);
+        tracing::info!(profile = %profile.name, command = %args, "Would run xrandr {}", args);
+        return Ok(());
+    }
+    randr::apply(&desired, dpi)
+}
+
+fn xrandr_outputs() -> Vec<XOutput> {
+    let outputs = (|| {
+        let mut handle = XHandle::open()?;
+        handle.all_outputs()
+    })();
+    outputs.unwrap_or(vec![])
+}
@@ -0,0 +1,30 @@
+// Status bars started by the daemon, one per output, like polybar: they are the most common
+// casualty of hotplug, since they are sized for the outputs they were started on.
+
+use std::process::Child;
+
+use crate::child;
+
+// Replaces the running bars by one per output, run by the shell with the output in $MONITOR.
+pub fn restart(command: &str, outputs: &[String], bars: &mut Vec<Child>) {
+    stop(bars);
+
+    for output in outputs {
+        let bar = child::shell(command)
+            .env("MONITOR", output)
+            .spawn();
+        match bar {
+            Ok(bar) => bars.push(bar),
+            Err(error) => tracing::error!(output = %output, "Cannot start the bar on {}: {}", output, error),
+        }
+    }
+}
+
+// Stops the bars with everything that their commands started.
+pub fn stop(bars: &mut Vec<Child>) {
+    for mut bar in bars.drain(..) {
+        if let Err(error) = child::stop_group(&mut bar) {
+            tracing::error!("Cannot stop the bar: {}", error);
+        }
+    }
+}
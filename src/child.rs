@@ -0,0 +1,71 @@
+// Long-running commands: the ones whose output is read line by line from the main loop, for the
+// services only reachable through D-Bus, and the shell commands started for the user.
+
+use std::{
+    io::{self, BufRead, BufReader},
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    process::{Child, Command, Stdio},
+};
+
+use glib::{Continue, IOCondition, unix_fd_add_local};
+
+// Calls `callback` with every line that the command prints. `name` describes the command in the
+// errors.
+pub fn watch_lines<F: FnMut(&str) + 'static>(command: &mut Command, name: &'static str, mut callback: F) -> io::Result<()> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take()
+        .ok_or_else(|| io::Error::other(format!("cannot read the output of {}", name)))?;
+    let fd = stdout.as_raw_fd();
+    let mut reader = BufReader::new(stdout);
+
+    unix_fd_add_local(fd, IOCondition::IN | IOCondition::HUP | IOCondition::ERR, move |_fd, condition| {
+        if !condition.contains(IOCondition::IN) {
+            tracing::error!("Lost the connection to {}", name);
+            let _ = child.wait();
+            return Continue(false);
+        }
+
+        // Handle every line already read, since the descriptor is not readable anymore for them.
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => return Continue(false),
+                Ok(_) => callback(&line),
+                Err(error) => {
+                    tracing::error!("Cannot read the output of {}: {}", name, error);
+                    return Continue(false);
+                },
+            }
+            if reader.buffer().is_empty() {
+                break;
+            }
+        }
+        Continue(true)
+    });
+
+    Ok(())
+}
+
+// Runs the shell command in its own process group, so that stop_group also stops what it started
+// when the shell does not exec it, like with "polybar left & polybar right".
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command])
+        .process_group(0);
+    shell
+}
+
+// Stops every process of the group of a command started with shell, and reaps the shell.
+pub fn stop_group(child: &mut Child) -> io::Result<()> {
+    if unsafe { libc::killpg(child.id() as i32, libc::SIGTERM) } == -1 {
+        let error = io::Error::last_os_error();
+        // Everything already exited.
+        if error.raw_os_error() != Some(libc::ESRCH) {
+            return Err(error);
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
@@ -0,0 +1,218 @@
+// Colors of the outputs: the gamma ramps of the CRTCs are reset when the outputs are enabled, which
+// loses the gamma and the calibration loaded from the ICC profile of the monitors, like xcalib
+// does.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::randr;
+
+// Offset of the tag table, after the header of the ICC profile.
+const TAG_TABLE: usize = 128;
+
+// Calibration curves from the vcgt (video card gamma table) tag of an ICC profile.
+enum Calibration {
+    // Levels of the red, green and blue channels, evenly spaced.
+    Table([Vec<f64>; 3]),
+    // Gamma, minimum and maximum of the red, green and blue channels.
+    Formula([(f64, f64, f64); 3]),
+}
+
+impl Calibration {
+    fn level(&self, channel: usize, input: f64) -> f64 {
+        match *self {
+            Calibration::Table(ref tables) => {
+                let table = &tables[channel];
+                let position = input * (table.len() - 1) as f64;
+                let index = position.floor() as usize;
+                match table.get(index + 1) {
+                    Some(next) => table[index] + (next - table[index]) * position.fract(),
+                    None => table[index],
+                }
+            },
+            Calibration::Formula(ref formulas) => {
+                let (gamma, min, max) = formulas[channel];
+                min + (max - min) * input.powf(gamma)
+            },
+        }
+    }
+}
+
+// Sets the colors of the output: the calibration of the ICC profile, if any, is corrected by
+// `gamma` and multiplied by `brightness`, like xrandr --gamma and --brightness.
+pub fn apply(output: &str, icc_profile: Option<&Path>, gamma: [f64; 3], brightness: f64) -> io::Result<()> {
+    let mut calibration = None;
+    if let Some(path) = icc_profile {
+        let data = fs::read(path)?;
+        calibration = parse_vcgt(&data)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("invalid ICC profile {}", path.display())))?;
+        randr::set_icc_profile(output, &data)?;
+    }
+
+    randr::set_gamma(output, |channel, input| {
+        let level = calibration.as_ref().map_or(input, |calibration| calibration.level(channel, input));
+        level.powf(1.0 / gamma[channel]) * brightness
+    })
+}
+
+// The gamma ramps of the enabled outputs, e.g. to keep the color temperature set by redshift or
+// gammastep across a reconfiguration.
+pub fn save() -> io::Result<BTreeMap<String, [Vec<u16>; 3]>> {
+    randr::gamma_ramps()
+}
+
+// Sets gamma ramps saved from the output, which are resampled if the size of the ramps of its CRTC
+// changed.
+pub fn restore(output: &str, ramps: &[Vec<u16>; 3]) -> io::Result<()> {
+    let tables = ramps.clone().map(|ramp| ramp.into_iter().map(|value| value as f64 / u16::MAX as f64).collect());
+    let calibration = Calibration::Table(tables);
+    randr::set_gamma(output, |channel, input| calibration.level(channel, input))
+}
+
+// Returns Some(None) for the valid profiles that have no calibration.
+fn parse_vcgt(data: &[u8]) -> Option<Option<Calibration>> {
+    let tag_count = read_u32(data, TAG_TABLE)? as usize;
+    for index in 0..tag_count {
+        let entry = TAG_TABLE + 4 + index * 12;
+        if data.get(entry..entry + 4)? == b"vcgt" {
+            let offset = read_u32(data, entry + 4)? as usize;
+            return parse_calibration(data, offset).map(Some);
+        }
+    }
+    Some(None)
+}
+
+fn parse_calibration(data: &[u8], offset: usize) -> Option<Calibration> {
+    if data.get(offset..offset + 4)? != b"vcgt" {
+        return None;
+    }
+    match read_u32(data, offset + 8)? {
+        0 => {
+            let channels = read_u16(data, offset + 12)? as usize;
+            let entries = read_u16(data, offset + 14)? as usize;
+            let entry_size = read_u16(data, offset + 16)? as usize;
+            if entries < 2 || !matches!(channels, 1 | 3) || !matches!(entry_size, 1 | 2) {
+                return None;
+            }
+            let max = if entry_size == 1 { u8::MAX as f64 } else { u16::MAX as f64 };
+            let mut tables: [Vec<f64>; 3] = Default::default();
+            for (channel, table) in tables.iter_mut().enumerate().take(channels) {
+                for entry in 0..entries {
+                    let position = offset + 18 + (channel * entries + entry) * entry_size;
+                    let value =
+                        if entry_size == 1 {
+                            *data.get(position)? as f64
+                        }
+                        else {
+                            read_u16(data, position)? as f64
+                        };
+                    table.push(value / max);
+                }
+            }
+            // A single table applies to every channel.
+            if channels == 1 {
+                tables[1] = tables[0].clone();
+                tables[2] = tables[0].clone();
+            }
+            Some(Calibration::Table(tables))
+        },
+        1 => {
+            let mut formulas = [(1.0, 0.0, 1.0); 3];
+            for (channel, formula) in formulas.iter_mut().enumerate() {
+                let position = offset + 12 + channel * 12;
+                *formula = (read_fixed(data, position)?, read_fixed(data, position + 4)?, read_fixed(data, position + 8)?);
+            }
+            Some(Calibration::Formula(formulas))
+        },
+        _ => None,
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+// The s15Fixed16Number of the ICC format.
+fn read_fixed(data: &[u8], offset: usize) -> Option<f64> {
+    Some(read_u32(data, offset)? as i32 as f64 / 65536.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Calibration, TAG_TABLE, parse_vcgt};
+
+    // An ICC profile with only a vcgt tag of this type, followed by `data`.
+    fn icc_profile(calibration_type: u32, data: &[u8]) -> Vec<u8> {
+        let offset = TAG_TABLE as u32 + 16;
+        let mut profile = vec![0; TAG_TABLE];
+        profile.extend_from_slice(&1u32.to_be_bytes());
+        profile.extend_from_slice(b"vcgt");
+        profile.extend_from_slice(&offset.to_be_bytes());
+        profile.extend_from_slice(&(12 + data.len() as u32).to_be_bytes());
+        profile.extend_from_slice(b"vcgt");
+        profile.extend_from_slice(&[0; 4]);
+        profile.extend_from_slice(&calibration_type.to_be_bytes());
+        profile.extend_from_slice(data);
+        profile
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-4, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn table() {
+        // A single channel of 3 entries of 2 bytes.
+        let data: Vec<u8> = [1u16, 3, 2, 0x0000, 0x8000, 0xFFFF].iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let calibration = parse_vcgt(&icc_profile(0, &data)).expect("profile").expect("vcgt");
+        assert!(matches!(calibration, Calibration::Table(_)));
+        for channel in 0..3 {
+            assert_close(calibration.level(channel, 0.0), 0.0);
+            assert_close(calibration.level(channel, 0.5), 0x8000 as f64 / 0xFFFF as f64);
+            assert_close(calibration.level(channel, 1.0), 1.0);
+        }
+        // Interpolated between the entries.
+        assert_close(calibration.level(0, 0.25), 0x4000 as f64 / 0xFFFF as f64);
+    }
+
+    #[test]
+    fn formula() {
+        let mut data = vec![];
+        // Gamma, minimum and maximum of the red, green and blue channels, in 16.16 fixed point.
+        for (gamma, min, max) in [(2.0, 0.0, 1.0), (1.0, 0.0, 0.5), (1.0, 0.25, 1.0)] {
+            for value in [gamma, min, max] {
+                data.extend_from_slice(&((value * 65536.0) as u32).to_be_bytes());
+            }
+        }
+        let calibration = parse_vcgt(&icc_profile(1, &data)).expect("profile").expect("vcgt");
+        assert_close(calibration.level(0, 0.5), 0.25);
+        assert_close(calibration.level(1, 1.0), 0.5);
+        assert_close(calibration.level(2, 0.0), 0.25);
+    }
+
+    #[test]
+    fn invalid_profiles() {
+        // No vcgt tag.
+        let mut profile = vec![0; TAG_TABLE];
+        profile.extend_from_slice(&0u32.to_be_bytes());
+        assert!(matches!(parse_vcgt(&profile), Some(None)));
+
+        assert!(parse_vcgt(&[0; 16]).is_none());
+        assert!(parse_vcgt(&icc_profile(2, &[])).is_none());
+        // Truncated table.
+        let data: Vec<u8> = [3u16, 256, 2].iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        assert!(parse_vcgt(&icc_profile(0, &data)).is_none());
+    }
+}
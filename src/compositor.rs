@@ -0,0 +1,25 @@
+// Compositor started by the daemon, like picom: compositors often leave artifacts or blank areas
+// after the size of the X screen changes, until they are restarted.
+
+use std::process::Child;
+
+use crate::child;
+
+// Replaces the running compositor by a new one, run by the shell.
+pub fn restart(command: &str, compositor: &mut Option<Child>) {
+    stop(compositor);
+
+    match child::shell(command).spawn() {
+        Ok(child) => *compositor = Some(child),
+        Err(error) => tracing::error!("Cannot start the compositor: {}", error),
+    }
+}
+
+// Stops the compositor with everything that its command started.
+pub fn stop(compositor: &mut Option<Child>) {
+    if let Some(mut child) = compositor.take() {
+        if let Err(error) = child::stop_group(&mut child) {
+            tracing::error!("Cannot stop the compositor: {}", error);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// The parts of the configuration that depend on the backend or on the devices of the machine. The
+// configuration itself lives in i3-aww-core.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, ErrorKind},
+};
+
+pub use i3_aww_core::config::*;
+
+use crate::{Backend, randr, usb};
+
+// The subcommands of the sway `output` command that the arguments of an output can start with.
+const SWAY_OUTPUT_COMMANDS: [&str; 22] = [
+    "mode", "resolution", "res", "modeline", "position", "pos", "scale", "scale_filter", "subpixel", "background", "bg",
+    "transform", "disable", "enable", "toggle", "power", "dpms", "max_render_time", "adaptive_sync", "render_bit_depth",
+    "color_profile", "allow_tearing",
+];
+
+// The arguments are xrandr arguments with i3 and sway `output` arguments with sway. Those whose
+// values are not checked by the X server or sway are checked here, to report them when the config
+// is loaded rather than when the outputs change.
+pub fn check_args(config: &Config, backend: Backend) -> io::Result<()> {
+    let positions = config.positions.iter()
+        .map(|monitor_pos| (&monitor_pos.name, &monitor_pos.args));
+    let outputs = config.profiles.iter()
+        .flat_map(|profile| &profile.outputs)
+        .map(|(name, output)| (name, &output.args));
+    for (output, args) in positions.chain(outputs) {
+        match backend {
+            Backend::I3 => check_xrandr_args(output, args)?,
+            Backend::Sway => check_sway_args(output, args)?,
+        }
+    }
+    Ok(())
+}
+
+// The docks required by the profiles that are attached. USB devices are only listed when a profile
+// requires a dock.
+pub fn attached_docks(config: &Config) -> BTreeSet<String> {
+    let docks = config.docks();
+    if docks.is_empty() {
+        return docks;
+    }
+    usb::devices().intersection(&docks).cloned().collect()
+}
+
+fn check_xrandr_args(output: &str, args: &[String]) -> io::Result<()> {
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        let check: fn(&str) -> bool =
+            match arg {
+                "--transform" => |value| randr::parse_transform(value).is_some(),
+                "--filter" => |value| randr::parse_filter(value).is_some(),
+                "--panning" => |value| randr::parse_panning(value).is_some(),
+                _ => continue,
+            };
+        let value = args.next().unwrap_or_default();
+        if !check(value) {
+            return Err(io::Error::new(ErrorKind::InvalidData,
+                format!("invalid xrandr argument for output {}: {} {}", output, arg, value)));
+        }
+    }
+    Ok(())
+}
+
+// The arguments are appended to the `output` command, e.g. ["pos", "1920", "0"]: they must start
+// with one of its subcommands, and the xrandr options are a mistake.
+fn check_sway_args(output: &str, args: &[String]) -> io::Result<()> {
+    let first_invalid = args.first().filter(|arg| !SWAY_OUTPUT_COMMANDS.contains(&arg.as_str()));
+    let xrandr_option = args.iter().find(|arg| arg.starts_with("--") && *arg != "--custom");
+    match first_invalid.or(xrandr_option) {
+        Some(arg) => Err(io::Error::new(ErrorKind::InvalidData,
+            format!("invalid sway output argument for output {}: {}", output, arg))),
+        None => Ok(()),
+    }
+}
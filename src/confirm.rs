@@ -0,0 +1,95 @@
+// Asks the user to keep a new layout and reverts it when they do not answer in time, like the
+// display settings dialogs do: a layout leaving all the outputs unusable cannot be confirmed.
+
+use std::{
+    io,
+    process::{Child, Command},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use glib::{Continue, SourceId, timeout_add_once, unix_signal_add};
+
+// Sent by the button of the nagbar.
+const CONFIRM_SIGNAL: i32 = libc::SIGUSR2;
+
+struct State {
+    nagbar: Child,
+    on_answer: Box<dyn FnOnce(bool) + Send>,
+    timeout: Option<SourceId>,
+    signal: Option<SourceId>,
+}
+
+pub struct Confirmation {
+    state: Arc<Mutex<Option<State>>>,
+}
+
+impl Confirmation {
+    // Stops waiting for the answer, keeping the layout.
+    pub fn cancel(self) {
+        let state = self.state.lock().expect("confirmation lock").take();
+        if let Some(mut state) = state {
+            close(&mut state);
+        }
+    }
+}
+
+// Shows `nagbar` (i3-nagbar or swaynag) and calls `on_answer` with whether the layout is kept.
+pub fn ask<F: FnOnce(bool) + Send + 'static>(nagbar: &str, timeout: Duration, on_answer: F) -> io::Result<Confirmation> {
+    let message = format!("Keep this display configuration? It will be reverted in {} seconds.", timeout.as_secs());
+    let keep = format!("kill -{} {}", CONFIRM_SIGNAL, std::process::id());
+    let nagbar = Command::new(nagbar)
+        .args(["-t", "warning", "-m", &message, "-B", "Keep", &keep])
+        .spawn()?;
+
+    let state: Arc<Mutex<Option<State>>> = Arc::new(Mutex::new(None));
+    let timeout = timeout_add_once(timeout, {
+        let state = Arc::clone(&state);
+        move || {
+            let state = state.lock().expect("confirmation lock").take();
+            if let Some(mut state) = state {
+                // This source is destroyed once this callback returns.
+                state.timeout.take();
+                answer(state, false);
+            }
+        }
+    });
+    let signal = unix_signal_add(CONFIRM_SIGNAL, {
+        let state = Arc::clone(&state);
+        move || {
+            let state = state.lock().expect("confirmation lock").take();
+            if let Some(mut state) = state {
+                state.signal.take();
+                answer(state, true);
+            }
+            Continue(false)
+        }
+    });
+
+    *state.lock().expect("confirmation lock") = Some(State {
+        nagbar,
+        on_answer: Box::new(on_answer),
+        timeout: Some(timeout),
+        signal: Some(signal),
+    });
+    Ok(Confirmation {
+        state,
+    })
+}
+
+fn answer(mut state: State, kept: bool) {
+    close(&mut state);
+    (state.on_answer)(kept);
+}
+
+fn close(state: &mut State) {
+    if let Some(source) = state.timeout.take() {
+        source.remove();
+    }
+    if let Some(source) = state.signal.take() {
+        source.remove();
+    }
+    if let Err(error) = state.nagbar.kill().and_then(|()| state.nagbar.wait()) {
+        tracing::error!("Cannot close the nagbar: {}", error);
+    }
+}
@@ -0,0 +1,153 @@
+// Control socket of the daemon: the commands of i3-aww are sent to the running daemon, which knows
+// the workspace history and the applied profile, instead of detecting everything again.
+
+use std::{
+    env,
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+
+use glib::{Continue, IOCondition, unix_fd_add_local};
+
+use crate::Request;
+
+// A client that does not send its request in time is dropped, so that it cannot block the daemon.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Clients that sent a watch request, which get a reply for every change.
+static WATCHERS: Mutex<Vec<UnixStream>> = Mutex::new(vec![]);
+
+// The reply is the string returned by `handler`, if any, or its error.
+type Response = Result<Option<String>, String>;
+
+// Calls `handler` for every request received on the socket.
+pub fn listen<F: Fn(Request) -> io::Result<Option<String>> + 'static>(handler: F) -> io::Result<()> {
+    let path = socket_path();
+    // The instance lock ensures that a socket left there belongs to a daemon that exited.
+    match fs::remove_file(&path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+        _ => (),
+    }
+    let listener = UnixListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+
+    let fd = listener.as_raw_fd();
+    unix_fd_add_local(fd, IOCondition::IN, move |_fd, _condition| {
+        match listener.accept() {
+            Ok((stream, _address)) => {
+                if let Err(error) = serve(stream, &handler) {
+                    tracing::error!("Cannot answer the control request: {}", error);
+                }
+            },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => (),
+            Err(error) => tracing::error!("Cannot accept the control connection: {}", error),
+        }
+        Continue(true)
+    });
+    Ok(())
+}
+
+// Returns None when no daemon is running, otherwise its reply.
+pub fn send(request: &Request) -> io::Result<Option<Option<String>>> {
+    let mut stream =
+        match UnixStream::connect(socket_path()) {
+            Ok(stream) => stream,
+            Err(error) if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+    let mut data = serde_json::to_string(request)?;
+    data.push('\n');
+    stream.write_all(data.as_bytes())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response: Response = serde_json::from_str(&line)
+        .map_err(|error| io::Error::new(ErrorKind::InvalidData, format!("invalid reply from the daemon: {}", error)))?;
+    response.map(Some).map_err(io::Error::other)
+}
+
+// Calls `on_reply` with the first reply to the watch request, then with every change, until the
+// daemon exits. Returns false when no daemon is running.
+pub fn watch<F: FnMut(String)>(mut on_reply: F) -> io::Result<bool> {
+    let mut stream =
+        match UnixStream::connect(socket_path()) {
+            Ok(stream) => stream,
+            Err(error) if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => return Ok(false),
+            Err(error) => return Err(error),
+        };
+    let mut data = serde_json::to_string(&Request::Watch)?;
+    data.push('\n');
+    stream.write_all(data.as_bytes())?;
+
+    for line in BufReader::new(&stream).lines() {
+        let response: Response = serde_json::from_str(&line?)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, format!("invalid reply from the daemon: {}", error)))?;
+        if let Some(reply) = response.map_err(io::Error::other)? {
+            on_reply(reply);
+        }
+    }
+    Ok(true)
+}
+
+// Sends `reply` to the watchers, forgetting those that left.
+pub fn notify(reply: String) {
+    let mut watchers = WATCHERS.lock().expect("watchers lock");
+    if watchers.is_empty() {
+        return;
+    }
+    let response: Response = Ok(Some(reply));
+    let mut data =
+        match serde_json::to_string(&response) {
+            Ok(data) => data,
+            Err(error) => {
+                tracing::error!("Cannot send the change to the watchers: {}", error);
+                return;
+            },
+        };
+    data.push('\n');
+    watchers.retain(|mut stream| stream.write_all(data.as_bytes()).is_ok());
+}
+
+pub fn has_watchers() -> bool {
+    !WATCHERS.lock().expect("watchers lock").is_empty()
+}
+
+// A request is a line of JSON, and so is its response.
+fn serve<F: Fn(Request) -> io::Result<Option<String>>>(stream: UnixStream, handler: &F) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    // A watcher that stops reading cannot block the daemon either.
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let mut watch = false;
+    let response: Response =
+        match serde_json::from_str(&line) {
+            Ok(request) => {
+                watch = matches!(request, Request::Watch);
+                handler(request).map_err(|error| error.to_string())
+            },
+            Err(error) => Err(format!("invalid request: {}", error)),
+        };
+    let mut data = serde_json::to_string(&response)?;
+    data.push('\n');
+    (&stream).write_all(data.as_bytes())?;
+    if watch && response.is_ok() {
+        WATCHERS.lock().expect("watchers lock").push(stream);
+    }
+    Ok(())
+}
+
+fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("i3-aww.sock"),
+        _ => env::temp_dir().join(format!("i3-aww-{}.sock", unsafe { libc::getuid() })),
+    }
+}
@@ -0,0 +1,99 @@
+// D-Bus interface of the daemon, on the session bus, so that the desktop tools and the scripts can
+// control it without running i3-aww, and follow the profile changes.
+
+use std::{io, rc::Rc};
+
+use gio::{BusNameOwnerFlags, BusType, DBusConnection, DBusMethodInvocation, DBusNodeInfo, OwnerId};
+use glib::{ToVariant, Variant};
+
+use crate::Request;
+
+const NAME: &str = "org.i3aww.Daemon";
+const PATH: &str = "/org/i3aww/Daemon";
+const FAILED_ERROR: &str = "org.i3aww.Daemon.Error.Failed";
+const INVALID_ARGS_ERROR: &str = "org.freedesktop.DBus.Error.InvalidArgs";
+
+const INTERFACE: &str = r#"
+<node>
+  <interface name="org.i3aww.Daemon">
+    <method name="Apply">
+      <arg name="profile" type="s" direction="in"/>
+    </method>
+    <method name="Save">
+      <arg name="name" type="s" direction="in"/>
+      <arg name="path" type="s" direction="out"/>
+    </method>
+    <method name="Reconcile"/>
+    <method name="GetStatus">
+      <arg name="status" type="s" direction="out"/>
+    </method>
+    <signal name="ProfileChanged">
+      <arg name="profile" type="s"/>
+    </signal>
+  </interface>
+</node>
+"#;
+
+// Calls `handler` for every method call, and replies with the string it returns, if any. The
+// connection is given to `on_connected` once the object is registered, to emit the signals.
+pub fn serve<F, C>(handler: F, on_connected: C) -> OwnerId
+where F: Fn(Request) -> io::Result<Option<String>> + 'static,
+      C: Fn(DBusConnection) + 'static,
+{
+    let handler = Rc::new(handler);
+    gio::bus_own_name(BusType::Session, NAME, BusNameOwnerFlags::NONE,
+        move |connection, _name| {
+            let handler = Rc::clone(&handler);
+            match register(&connection, move |request| handler(request)) {
+                Ok(()) => on_connected(connection),
+                Err(error) => tracing::error!("Cannot register the D-Bus interface: {}", error),
+            }
+        },
+        |_connection, _name| (),
+        |_connection, name| tracing::error!("Cannot own the D-Bus name {}", name),
+    )
+}
+
+pub fn emit_profile_changed(connection: &DBusConnection, profile: &str) -> io::Result<()> {
+    connection.emit_signal(None, PATH, NAME, "ProfileChanged", Some(&(profile,).to_variant()))
+        .map_err(io::Error::other)
+}
+
+fn register<F: Fn(Request) -> io::Result<Option<String>> + 'static>(connection: &DBusConnection, handler: F)
+    -> io::Result<()>
+{
+    let node = DBusNodeInfo::for_xml(INTERFACE).map_err(io::Error::other)?;
+    let interface = node.lookup_interface(NAME)
+        .ok_or_else(|| io::Error::other("missing interface in the introspection data"))?;
+    connection.register_object(PATH, &interface,
+        move |_connection, _sender, _path, _interface, method, parameters, invocation| {
+            match parse_request(method, &parameters) {
+                Some(request) => reply(invocation, handler(request)),
+                None => invocation.return_dbus_error(INVALID_ARGS_ERROR, &format!("invalid call of {}", method)),
+            }
+        },
+        // The interface has no properties.
+        |_connection, _sender, _path, _interface, _property| ().to_variant(),
+        |_connection, _sender, _path, _interface, _property, _value| false,
+    )
+        .map_err(io::Error::other)?;
+    Ok(())
+}
+
+fn parse_request(method: &str, parameters: &Variant) -> Option<Request> {
+    match method {
+        "Apply" => parameters.get::<(String,)>().map(|(profile,)| Request::Apply { profile, confirm: None }),
+        "Save" => parameters.get::<(String,)>().map(|(name,)| Request::Save(name)),
+        "Reconcile" => Some(Request::Reconcile { confirm: None }),
+        "GetStatus" => Some(Request::GetStatus),
+        _ => None,
+    }
+}
+
+fn reply(invocation: DBusMethodInvocation, result: io::Result<Option<String>>) {
+    match result {
+        Ok(Some(value)) => invocation.return_value(Some(&(value,).to_variant())),
+        Ok(None) => invocation.return_value(None),
+        Err(error) => invocation.return_dbus_error(FAILED_ERROR, &error.to_string()),
+    }
+}
@@ -0,0 +1,87 @@
+// Brightness and power of the external monitors over DDC/CI, through ddcutil. The I2C bus of a
+// monitor is found from its EDID, since the connector names of the window manager and of the
+// kernel differ.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    process::Command,
+    sync::Mutex,
+};
+
+use i3_aww_core::edid::Edid;
+
+const DRM_PATH: &str = "/sys/class/drm";
+// VCP feature codes of the brightness and of the power mode.
+const BRIGHTNESS: &str = "10";
+const POWER_MODE: &str = "D6";
+const POWER_ON: &str = "1";
+const POWER_STANDBY: &str = "4";
+
+// EDID fingerprints of the monitors put in standby, which are the only ones to wake up.
+static STANDBY: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+// `value` is a percentage.
+pub fn set_brightness(edid: &Edid, value: u8) -> io::Result<()> {
+    set_vcp(edid, BRIGHTNESS, &value.to_string())
+}
+
+// Unlike turning the output off, standby stops the monitors that keep looking for a signal on
+// their other inputs.
+pub fn standby(edid: &Edid) -> io::Result<()> {
+    let fingerprint = edid.fingerprint();
+    if STANDBY.lock().expect("standby lock").contains(&fingerprint) {
+        return Ok(());
+    }
+    set_vcp(edid, POWER_MODE, POWER_STANDBY)?;
+    STANDBY.lock().expect("standby lock").insert(fingerprint);
+    Ok(())
+}
+
+// Does nothing for the monitors that were not put in standby.
+pub fn wake_up(edid: &Edid) -> io::Result<()> {
+    let fingerprint = edid.fingerprint();
+    if !STANDBY.lock().expect("standby lock").contains(&fingerprint) {
+        return Ok(());
+    }
+    set_vcp(edid, POWER_MODE, POWER_ON)?;
+    STANDBY.lock().expect("standby lock").remove(&fingerprint);
+    Ok(())
+}
+
+fn set_vcp(edid: &Edid, code: &str, value: &str) -> io::Result<()> {
+    let bus = i2c_bus(&edid.fingerprint())
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("no DDC/CI bus for monitor {}", edid.fingerprint())))?;
+    let output = Command::new("ddcutil")
+        .args(["--bus", &bus.to_string(), "setvcp", code, value])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("ddcutil failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(())
+}
+
+fn i2c_bus(fingerprint: &str) -> Option<u32> {
+    fs::read_dir(DRM_PATH).ok()?
+        .flatten()
+        .find(|entry| {
+            fs::read(entry.path().join("edid")).ok()
+                .and_then(|edid| Edid::parse(&edid))
+                .is_some_and(|edid| edid.fingerprint() == fingerprint)
+        })
+        .and_then(|entry| connector_bus(&entry.path()))
+}
+
+// The bus is either linked as ddc, or a child of the connector, like for the DisplayPort AUX
+// channels.
+fn connector_bus(connector: &Path) -> Option<u32> {
+    let bus_number = |name: &str| name.strip_prefix("i2c-")?.parse().ok();
+    if let Ok(target) = fs::read_link(connector.join("ddc")) {
+        return target.file_name().and_then(|name| bus_number(&name.to_string_lossy()));
+    }
+    fs::read_dir(connector).ok()?
+        .flatten()
+        .find_map(|entry| bus_number(&entry.file_name().to_string_lossy()))
+}
@@ -0,0 +1,154 @@
+// Changes that applying a profile would make to the outputs and the workspaces, to check a
+// configuration before it is applied.
+
+use std::collections::BTreeMap;
+
+use i3_aww_core::restore::Workspace;
+
+// What the user sees of an output, now or once the profile is applied.
+#[derive(Clone, Default)]
+pub struct OutputLayout {
+    pub enabled: bool,
+    pub primary: bool,
+    // Unknown with sway.
+    pub mode: Option<String>,
+    // Unknown with sway, and left as is when the profile does not set it.
+    pub rotation: Option<String>,
+}
+
+// Like "DP-1: 1920x1080 → 2560x1440".
+pub fn output_changes(current: &BTreeMap<String, OutputLayout>, planned: &BTreeMap<String, OutputLayout>) -> Vec<String> {
+    let mut changes = vec![];
+    for (name, planned) in planned {
+        let current = current.get(name).cloned().unwrap_or_default();
+        match (current.enabled, planned.enabled) {
+            (false, false) => continue,
+            (false, true) => changes.push(format!("{}: off \u{2192} {}", name, planned.mode.as_deref().unwrap_or("on"))),
+            (true, false) => changes.push(format!("{}: {} \u{2192} off", name, current.mode.as_deref().unwrap_or("on"))),
+            (true, true) => {
+                if let (Some(mode), Some(planned_mode)) = (&current.mode, &planned.mode) {
+                    if mode != planned_mode {
+                        changes.push(format!("{}: {} \u{2192} {}", name, mode, planned_mode));
+                    }
+                }
+                if let (Some(rotation), Some(planned_rotation)) = (&current.rotation, &planned.rotation) {
+                    if rotation != planned_rotation {
+                        changes.push(format!("{}: rotation {} \u{2192} {}", name, rotation, planned_rotation));
+                    }
+                }
+            },
+        }
+        if planned.enabled && planned.primary && !current.primary {
+            changes.push(format!("{}: becomes primary", name));
+        }
+    }
+    changes
+}
+
+// Like "workspace 5: move HDMI-A-0 → DP-1". The planned output is None when the output of the
+// workspace is turned off and the window manager picks another one.
+pub fn workspace_changes(workspaces: &[Workspace], planned: &BTreeMap<String, Option<String>>) -> Vec<String> {
+    let mut changes = vec![];
+    for workspace in workspaces.iter().filter(|workspace| workspace.present) {
+        let (name, output) = (&workspace.name, &workspace.output);
+        match planned.get(name) {
+            Some(Some(planned_output)) if planned_output != output => {
+                changes.push(format!("workspace {}: move {} \u{2192} {}", name, output, planned_output));
+            },
+            Some(None) => changes.push(format!("workspace {}: move {} \u{2192} another output", name, output)),
+            _ => (),
+        }
+    }
+    changes
+}
+
+pub fn print(profile: &str, changes: &[String]) {
+    if changes.is_empty() {
+        println!("Profile {}: no changes", profile);
+        return;
+    }
+    println!("Profile {}:", profile);
+    for change in changes {
+        println!("  {}", change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use i3_aww_core::restore::Workspace;
+
+    use super::{OutputLayout, output_changes, workspace_changes};
+
+    fn layout(mode: &str, primary: bool) -> OutputLayout {
+        OutputLayout {
+            enabled: true,
+            primary,
+            mode: Some(mode.to_string()),
+            rotation: Some("normal".to_string()),
+        }
+    }
+
+    fn workspace(name: &str, output: &str, present: bool) -> Workspace {
+        Workspace {
+            focused: false,
+            visible: false,
+            name: name.to_string(),
+            num: name.parse().unwrap_or(-1),
+            output: output.to_string(),
+            previous_output: None,
+            monitor: None,
+            was_visible: false,
+            was_focused: false,
+            present,
+        }
+    }
+
+    #[test]
+    fn outputs() {
+        let current = BTreeMap::from([
+            ("DP-1".to_string(), layout("1920x1080", false)),
+            ("HDMI-A-0".to_string(), layout("1920x1080", false)),
+            ("eDP-1".to_string(), layout("2560x1600", true)),
+        ]);
+        let planned = BTreeMap::from([
+            ("DP-1".to_string(), OutputLayout { rotation: Some("left".to_string()), ..layout("2560x1440", true) }),
+            ("DP-2".to_string(), layout("1920x1080", false)),
+            ("HDMI-A-0".to_string(), OutputLayout::default()),
+            ("VGA-1".to_string(), OutputLayout::default()),
+            // Unknown with sway.
+            ("eDP-1".to_string(), OutputLayout { enabled: true, ..OutputLayout::default() }),
+        ]);
+        assert_eq!(output_changes(&current, &planned), [
+            "DP-1: 1920x1080 \u{2192} 2560x1440",
+            "DP-1: rotation normal \u{2192} left",
+            "DP-1: becomes primary",
+            "DP-2: off \u{2192} 1920x1080",
+            "HDMI-A-0: 1920x1080 \u{2192} off",
+        ]);
+        assert!(output_changes(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn workspaces() {
+        let workspaces = [
+            workspace("2", "HDMI-A-0", true),
+            workspace("1", "eDP-1", true),
+            workspace("mail", "DP-1", true),
+            workspace("3", "HDMI-A-0", false),
+            workspace("4", "DP-1", true),
+        ];
+        let planned = BTreeMap::from([
+            ("1".to_string(), Some("eDP-1".to_string())),
+            ("2".to_string(), Some("DP-1".to_string())),
+            ("3".to_string(), Some("DP-1".to_string())),
+            ("mail".to_string(), None),
+        ]);
+        // In the order of the workspaces, without the ones that are gone.
+        assert_eq!(workspace_changes(&workspaces, &planned), [
+            "workspace 2: move HDMI-A-0 \u{2192} DP-1",
+            "workspace mail: move DP-1 \u{2192} another output",
+        ]);
+    }
+}
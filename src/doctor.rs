@@ -0,0 +1,163 @@
+// Checks of the environment, for `i3-aww doctor`: most setup problems otherwise only show as a
+// daemon that never reacts to the hotplug events.
+
+use std::{
+    env,
+    fs,
+    io,
+    mem,
+    path::Path,
+};
+
+use i3_ipc::{I3, Connect};
+
+use crate::randr;
+
+// Group of the netlink socket where udev forwards the uevents it processed.
+const UDEV_MONITOR_GROUP: u32 = 2;
+const UDEV_CONTROL: &str = "/run/udev/control";
+// RRSetMonitor, used to split the outputs, is only available since RandR 1.5.
+const RANDR_MONITORS_VERSION: (i32, i32) = (1, 5);
+// Tools that also reconfigure the outputs when they change.
+const CONFLICTING_DAEMONS: [&str; 5] = ["autorandr", "kanshi", "srandrd", "grobi", "mons"];
+
+pub enum Outcome {
+    Ok(String),
+    Warning(String),
+    Error(String),
+}
+
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+impl Check {
+    pub fn new(name: &'static str, outcome: Outcome) -> Self {
+        Self {
+            name,
+            outcome,
+        }
+    }
+}
+
+pub fn udev() -> Check {
+    let outcome =
+        if !Path::new(UDEV_CONTROL).exists() {
+            Outcome::Error(format!("{} is missing: udevd does not seem to run, use --trigger randr or --poll", UDEV_CONTROL))
+        }
+        else {
+            match listen_uevents() {
+                Ok(()) => Outcome::Ok("the uevents can be received".to_string()),
+                Err(error) => Outcome::Error(format!("cannot listen to the uevents ({}), use --trigger randr or --poll", error)),
+            }
+        };
+    Check::new("udev", outcome)
+}
+
+pub fn x_server() -> Check {
+    let outcome =
+        match env::var_os("DISPLAY") {
+            Some(display) if !display.is_empty() => {
+                match randr::version() {
+                    Ok(_) => Outcome::Ok(format!("connected to {}", display.to_string_lossy())),
+                    Err(error) => Outcome::Error(format!("{}: check DISPLAY and XAUTHORITY", error)),
+                }
+            },
+            _ => Outcome::Error("DISPLAY is not set: start i3-aww from the i3 session, e.g. with exec in its configuration".to_string()),
+        };
+    Check::new("X server", outcome)
+}
+
+pub fn randr() -> Check {
+    let outcome =
+        match randr::version() {
+            Ok(version) if version >= RANDR_MONITORS_VERSION => Outcome::Ok(format!("version {}.{}", version.0, version.1)),
+            Ok((major, minor)) => Outcome::Warning(format!("version {}.{}: the outputs cannot be split before {}.{}", major, minor,
+                RANDR_MONITORS_VERSION.0, RANDR_MONITORS_VERSION.1)),
+            Err(error) => Outcome::Error(error.to_string()),
+        };
+    Check::new("RandR", outcome)
+}
+
+pub fn window_manager() -> Check {
+    let outcome =
+        match I3::connect().and_then(|mut i3| i3.get_version()) {
+            Ok(version) => Outcome::Ok(version.human_readable),
+            Err(error) => {
+                let socket = i3_ipc::socket_path()
+                    .unwrap_or_else(|_| "unknown".to_string());
+                Outcome::Error(format!("cannot connect to the IPC socket {} ({}): check I3SOCK or SWAYSOCK", socket, error))
+            },
+        };
+    Check::new("IPC socket", outcome)
+}
+
+pub fn conflicting_daemons() -> Check {
+    let mut running = vec![];
+    if let Ok(entries) = fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let cmdline =
+                match fs::read(entry.path().join("cmdline")) {
+                    Ok(cmdline) => cmdline,
+                    Err(_) => continue,
+                };
+            // Scripts show as their interpreter followed by their path.
+            let name = cmdline.split(|&byte| byte == 0)
+                .take(2)
+                .filter_map(|arg| Path::new(&*String::from_utf8_lossy(arg)).file_name().map(|name| name.to_string_lossy().into_owned()))
+                .find(|name| CONFLICTING_DAEMONS.contains(&name.as_str()));
+            if let Some(name) = name {
+                running.push(format!("{} (pid {})", name, entry.file_name().to_string_lossy()));
+            }
+        }
+    }
+    let outcome =
+        if running.is_empty() {
+            Outcome::Ok("none".to_string())
+        }
+        else {
+            Outcome::Warning(format!("{} also reconfigure the outputs: stop them", running.join(", ")))
+        };
+    Check::new("other daemons", outcome)
+}
+
+// Returns whether every check passed, warnings aside.
+pub fn print(checks: &[Check]) -> bool {
+    let mut passed = true;
+    for check in checks {
+        let (status, message) =
+            match check.outcome {
+                Outcome::Ok(ref message) => ("ok", message),
+                Outcome::Warning(ref message) => ("warning", message),
+                Outcome::Error(ref message) => {
+                    passed = false;
+                    ("error", message)
+                },
+            };
+        println!("{:>8}  {}: {}", status, check.name, message);
+    }
+    passed
+}
+
+// Binds a netlink socket to the group of udev, like the udev client of the daemon.
+fn listen_uevents() -> io::Result<()> {
+    let socket = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_KOBJECT_UEVENT) };
+    if socket < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    address.nl_groups = UDEV_MONITOR_GROUP;
+    let result = unsafe {
+        libc::bind(socket, &address as *const libc::sockaddr_nl as *const libc::sockaddr, mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t)
+    };
+    let error = io::Error::last_os_error();
+    unsafe {
+        libc::close(socket);
+    }
+    if result < 0 {
+        return Err(error);
+    }
+    Ok(())
+}
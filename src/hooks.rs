@@ -0,0 +1,29 @@
+// Commands run around the reconfigurations, like the postswitch scripts of autorandr, e.g. to
+// restart a bar or reset the wallpaper.
+
+use std::process::Command;
+
+// Described to the hooks through the environment.
+pub struct Switch {
+    pub profile: String,
+    // Enabled outputs, before and after the reconfiguration.
+    pub old_outputs: Vec<String>,
+    pub new_outputs: Vec<String>,
+}
+
+// Runs the commands with the shell, one after the other. `stage` names them in the errors.
+pub fn run(stage: &str, commands: &[String], switch: &Switch) {
+    for command in commands {
+        let status = Command::new("sh")
+            .args(["-c", command])
+            .env("I3_AWW_PROFILE", &switch.profile)
+            .env("I3_AWW_OLD_OUTPUTS", switch.old_outputs.join(" "))
+            .env("I3_AWW_NEW_OUTPUTS", switch.new_outputs.join(" "))
+            .status();
+        match status {
+            Ok(status) if status.success() => tracing::info!("Ran the {} hook \"{}\"", stage, command),
+            Ok(status) => tracing::error!("The {} hook \"{}\" failed: {}", stage, command, status),
+            Err(error) => tracing::error!("Cannot run the {} hook \"{}\": {}", stage, command, error),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Inactivity of the user, from the idle time of the X server (the MIT-SCREEN-SAVER extension), to
+// put the external monitors in standby when nobody is in front of them.
+
+use std::{io, ptr, time::Duration};
+
+use glib::{Continue, timeout_add_local};
+use x11::{xlib, xss};
+
+// Calls `callback` with the time since the last input every `interval`.
+pub fn watch<F: Fn(Duration) + 'static>(interval: Duration, callback: F) -> io::Result<()> {
+    let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+    if display.is_null() {
+        return Err(io::Error::other("cannot open the X display"));
+    }
+
+    let mut event_base = 0;
+    let mut error_base = 0;
+    let info = unsafe {
+        if xss::XScreenSaverQueryExtension(display, &mut event_base, &mut error_base) == 0 {
+            xlib::XCloseDisplay(display);
+            return Err(io::Error::other("the X server does not support MIT-SCREEN-SAVER"));
+        }
+        xss::XScreenSaverAllocInfo()
+    };
+    if info.is_null() {
+        unsafe {
+            xlib::XCloseDisplay(display);
+        }
+        return Err(io::Error::other("cannot allocate the screen saver info"));
+    }
+
+    // The display is kept open for the lifetime of the daemon.
+    timeout_add_local(interval, move || {
+        let idle = unsafe {
+            let root = xlib::XDefaultRootWindow(display);
+            (xss::XScreenSaverQueryInfo(display, root, info) != 0).then(|| (*info).idle)
+        };
+        match idle {
+            Some(idle) => callback(Duration::from_millis(idle)),
+            None => tracing::error!("Cannot get the idle time"),
+        }
+        Continue(true)
+    });
+    Ok(())
+}
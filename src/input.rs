@@ -0,0 +1,71 @@
+// Input devices of the X server: touchscreens and drawing tablets track the whole screen unless
+// they are mapped to the output they are attached to, and the profiles can change the settings of
+// the keyboards and pointers.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    process::{Command, Stdio},
+};
+
+use crate::config::InputConfig;
+
+// `mappings` maps the names of the devices, as listed by `xinput list`, to their output. The
+// rotation of the output is taken into account by xinput.
+pub fn map_to_outputs(mappings: &BTreeMap<String, String>) -> io::Result<()> {
+    let mut errors = vec![];
+    for (device, output) in mappings {
+        let status = Command::new("xinput")
+            .args(["map-to-output", device, output])
+            .status()?;
+        if !status.success() {
+            errors.push(format!("cannot map {} to {}", device, output));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(io::Error::other(errors.join(", ")))
+    }
+}
+
+// The pointer settings are libinput properties, set on every device having them.
+pub fn configure(input: &InputConfig) -> io::Result<()> {
+    if input.keyboard_layout.is_some() || input.keyboard_variant.is_some() {
+        let mut command = Command::new("setxkbmap");
+        if let Some(ref layout) = input.keyboard_layout {
+            command.args(["-layout", layout]);
+        }
+        if let Some(ref variant) = input.keyboard_variant {
+            command.args(["-variant", variant]);
+        }
+        let status = command.status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("setxkbmap failed: {}", status)));
+        }
+    }
+
+    let mut properties = vec![];
+    if let Some(natural_scrolling) = input.natural_scrolling {
+        properties.push(("libinput Natural Scrolling Enabled", if natural_scrolling { "1" } else { "0" }.to_string()));
+    }
+    if let Some(acceleration) = input.pointer_acceleration {
+        properties.push(("libinput Accel Speed", acceleration.to_string()));
+    }
+    if properties.is_empty() {
+        return Ok(());
+    }
+
+    let output = Command::new("xinput").args(["list", "--id-only"]).output()?;
+    for id in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+        for (property, value) in &properties {
+            // Fails for the devices without this property, like the keyboards.
+            Command::new("xinput")
+                .args(["set-prop", id, property, value])
+                .stderr(Stdio::null())
+                .status()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+// Time taken by each phase of a reconfiguration, from the first hotplug event to the restored
+// workspaces, to tune the delays and notice when one of the phases gets slower.
+
+use std::time::Instant;
+
+pub struct Latency {
+    // First hotplug event of the burst, or the start of the reconfiguration when it was requested.
+    event: Instant,
+    apply: Option<Instant>,
+    // Once the outputs are configured and the hooks are run.
+    applied: Option<Instant>,
+    restore: Option<Instant>,
+}
+
+impl Latency {
+    pub fn new() -> Self {
+        Self {
+            event: Instant::now(),
+            apply: None,
+            applied: None,
+            restore: None,
+        }
+    }
+
+    pub fn start_apply(&mut self) {
+        self.apply = Some(Instant::now());
+    }
+
+    pub fn applied(&mut self) {
+        self.applied = Some(Instant::now());
+    }
+
+    // The restorations for the changes made by other tools are not measured.
+    pub fn start_restore(&mut self) {
+        if self.applied.is_some() {
+            self.restore = Some(Instant::now());
+        }
+    }
+
+    pub fn is_restoring(&self) -> bool {
+        self.restore.is_some()
+    }
+
+    // Called once the workspaces are restored.
+    pub fn log(&self) {
+        let (apply, applied, restore) =
+            match (self.apply, self.applied, self.restore) {
+                (Some(apply), Some(applied), Some(restore)) => (apply, applied, restore),
+                _ => return,
+            };
+        let restored = Instant::now();
+        let settle = apply - self.event;
+        let configure = applied - apply;
+        let wait = restore - applied;
+        let restoration = restored - restore;
+        let total = restored - self.event;
+        tracing::info!(settle_ms = settle.as_millis(), apply_ms = configure.as_millis(), wait_ms = wait.as_millis(),
+            restore_ms = restoration.as_millis(), total_ms = total.as_millis(),
+            "Reconfigured in {:?}: {:?} for the outputs to settle, {:?} to apply the profile, {:?} for the window manager, {:?} to restore the workspaces",
+            total, settle, configure, wait, restoration);
+    }
+}
@@ -0,0 +1,39 @@
+// State of the lid of a laptop, from the ACPI button driver. The switch only produces input events,
+// not uevents, so its state is polled.
+
+use std::{fs, time::Duration};
+
+use glib::{Continue, timeout_add_local};
+
+const LID_PATH: &str = "/proc/acpi/button/lid";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// False when there is no lid.
+pub fn closed() -> bool {
+    let entries =
+        match fs::read_dir(LID_PATH) {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+    // The file contains something like "state:      closed".
+    entries.flatten()
+        .filter_map(|entry| fs::read_to_string(entry.path().join("state")).ok())
+        .any(|state| state.split_whitespace().last() == Some("closed"))
+}
+
+// Calls `callback` with the new state every time the lid is opened or closed.
+pub fn watch<F: Fn(bool) + 'static>(callback: F) {
+    if fs::read_dir(LID_PATH).is_err() {
+        return;
+    }
+
+    let mut was_closed = closed();
+    timeout_add_local(POLL_INTERVAL, move || {
+        let is_closed = closed();
+        if is_closed != was_closed {
+            was_closed = is_closed;
+            callback(is_closed);
+        }
+        Continue(true)
+    });
+}
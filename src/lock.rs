@@ -0,0 +1,86 @@
+// Ensures that a single daemon runs per user, since several instances fight over the outputs.
+
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Read, Seek, Write},
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+const REPLACE_ATTEMPTS: u32 = 50;
+const REPLACE_INTERVAL: Duration = Duration::from_millis(100);
+
+// The lock is released when this is dropped.
+pub struct InstanceLock {
+    _file: File,
+}
+
+pub fn acquire(replace: bool) -> io::Result<InstanceLock> {
+    let path = lock_path();
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+
+    if !try_lock(&file)? {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let pid = content.trim().parse::<libc::pid_t>().ok();
+
+        if !replace {
+            let pid = pid.map(|pid| pid.to_string()).unwrap_or_else(|| "unknown".to_string());
+            return Err(io::Error::new(ErrorKind::AlreadyExists,
+                format!("i3-aww is already running (pid {}), use --replace to replace it", pid)));
+        }
+
+        if let Some(pid) = pid {
+            if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let mut locked = false;
+        for _ in 0..REPLACE_ATTEMPTS {
+            thread::sleep(REPLACE_INTERVAL);
+            if try_lock(&file)? {
+                locked = true;
+                break;
+            }
+        }
+        if !locked {
+            return Err(io::Error::new(ErrorKind::TimedOut, "the running instance of i3-aww did not exit"));
+        }
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+    Ok(InstanceLock {
+        _file: file,
+    })
+}
+
+fn try_lock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = io::Error::last_os_error();
+    if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(false)
+    }
+    else {
+        Err(error)
+    }
+}
+
+fn lock_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("i3-aww.lock"),
+        _ => env::temp_dir().join(format!("i3-aww-{}.lock", unsafe { libc::getuid() })),
+    }
+}
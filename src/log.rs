@@ -0,0 +1,181 @@
+// Setup of the `tracing` subscriber writing the messages of i3-aww to stderr, or to journald or
+// syslog for a daemon started by a session manager that discards stderr. Journald also gets the
+// fields of the messages (e.g. the output or the profile), to filter them with
+// `journalctl OUTPUT=HDMI-A-0`.
+//
+// The messages are grouped in spans, like the handling of a hotplug event, and the debug and trace
+// messages are only written with -v, -vv or RUST_LOG, to get a useful trace when a layout goes
+// wrong.
+
+use std::{
+    io::{self, Write},
+    os::unix::net::UnixDatagram,
+    process,
+    sync::OnceLock,
+};
+
+use tracing::{Level, Metadata};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::{self, MakeWriter, format::FmtSpan},
+    layer::{Layered, SubscriberExt},
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter,
+    Layer,
+    Registry,
+};
+
+use crate::config::{LogFormat, LogTarget};
+
+const IDENTIFIER: &str = "i3-aww";
+const SYSLOG_SOCKET: &str = "/dev/log";
+// The user-level messages facility.
+const SYSLOG_FACILITY: u8 = 1 << 3;
+
+type Filtered = Layered<EnvFilter, Registry>;
+type Output = Box<dyn Layer<Filtered> + Send + Sync>;
+
+static OUTPUT: OnceLock<(reload::Handle<Output, Filtered>, FmtSpan)> = OnceLock::new();
+
+// Sends every message to syslog in its own datagram, with its priority.
+struct Syslog(UnixDatagram);
+
+struct SyslogMessage<'a> {
+    socket: &'a UnixDatagram,
+    priority: u8,
+    data: Vec<u8>,
+}
+
+impl Syslog {
+    fn message(&self, level: Level) -> SyslogMessage<'_> {
+        let priority =
+            match level {
+                Level::ERROR => 3,
+                Level::WARN => 4,
+                Level::INFO => 6,
+                _ => 7,
+            };
+        SyslogMessage {
+            socket: &self.0,
+            priority,
+            data: vec![],
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for Syslog {
+    type Writer = SyslogMessage<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.message(Level::INFO)
+    }
+
+    fn make_writer_for(&'a self, metadata: &Metadata<'_>) -> Self::Writer {
+        self.message(*metadata.level())
+    }
+}
+
+impl Write for SyslogMessage<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogMessage<'_> {
+    fn drop(&mut self) {
+        let message = String::from_utf8_lossy(&self.data);
+        let message = message.trim_end();
+        if message.is_empty() {
+            return;
+        }
+        let line = format!("<{}>{}[{}]: {}", SYSLOG_FACILITY | self.priority, IDENTIFIER, process::id(), message);
+        let _ = self.socket.send(line.as_bytes());
+    }
+}
+
+// Each -v shows one more level, otherwise RUST_LOG is used, either as a level, like "debug", or as
+// a list of directives, like "i3_aww=trace". The beginning and the end of the spans are only shown
+// with the trace messages.
+pub fn init(verbose: u8, target: LogTarget, format: LogFormat) {
+    let filter =
+        match verbose {
+            0 => EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+            1 => EnvFilter::new("info,i3_aww=debug"),
+            _ => EnvFilter::new("info,i3_aww=trace"),
+        };
+    let span_events =
+        if filter.max_level_hint() == Some(LevelFilter::TRACE) {
+            FmtSpan::NEW | FmtSpan::CLOSE
+        }
+        else {
+            FmtSpan::NONE
+        };
+    let (output, handle) = reload::Layer::new(output(target, format, span_events.clone()));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .init();
+    let _ = OUTPUT.set((handle, span_events));
+}
+
+// The target and the format can also come from the configuration, once it is loaded.
+pub fn set_output(target: LogTarget, format: LogFormat) {
+    if let Some((handle, span_events)) = OUTPUT.get() {
+        if let Err(error) = handle.reload(output(target, format, span_events.clone())) {
+            eprintln!("Cannot change the log output: {}", error);
+        }
+    }
+}
+
+// Falls back to stderr when journald or syslog cannot be reached.
+fn output(target: LogTarget, format: LogFormat, span_events: FmtSpan) -> Output {
+    match target {
+        LogTarget::Stderr => (),
+        LogTarget::Journald => {
+            match tracing_journald::layer() {
+                Ok(layer) => return layer
+                    .with_field_prefix(None)
+                    .with_syslog_identifier(IDENTIFIER.to_string())
+                    .boxed(),
+                Err(error) => eprintln!("Cannot connect to journald: {}", error),
+            }
+        },
+        LogTarget::Syslog => {
+            let socket = UnixDatagram::unbound().and_then(|socket| {
+                socket.connect(SYSLOG_SOCKET)?;
+                Ok(socket)
+            });
+            match socket {
+                Ok(socket) => return fmt::layer()
+                    .with_writer(Syslog(socket))
+                    .with_ansi(false)
+                    .without_time()
+                    .with_level(false)
+                    .with_target(false)
+                    .with_span_events(span_events)
+                    .boxed(),
+                Err(error) => eprintln!("Cannot connect to syslog: {}", error),
+            }
+        },
+    }
+
+    let layer = fmt::layer()
+        .with_writer(io::stderr)
+        .with_target(false)
+        .with_span_events(span_events);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
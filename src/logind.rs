@@ -0,0 +1,70 @@
+// Signals of logind, read from `gdbus monitor`: the outputs can change while the machine is
+// asleep, with no uevent guaranteed after waking up, and the screen lockers render badly when the
+// outputs change under them.
+
+use std::{
+    env,
+    io,
+    process::Command,
+};
+
+use crate::child;
+
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/";
+
+pub enum Event {
+    Resume,
+    Lock,
+    Unlock,
+}
+
+// Calls `callback` every time the machine wakes up or the session is locked or unlocked.
+pub fn watch<F: Fn(Event) + 'static>(callback: F) -> io::Result<()> {
+    let session = session_path();
+    let mut command = Command::new("gdbus");
+    command.args(["monitor", "--system", "--dest", "org.freedesktop.login1"]);
+    child::watch_lines(&mut command, "logind", move |line| {
+        if let Some(event) = parse_event(line, session.as_deref()) {
+            callback(event);
+        }
+    })
+}
+
+// The lines look like "/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (false,)".
+fn parse_event(line: &str, session: Option<&str>) -> Option<Event> {
+    let (path, signal) = line.trim().split_once(": ")?;
+    // PrepareForSleep is sent with true before going to sleep and with false after waking up.
+    if signal.starts_with("org.freedesktop.login1.Manager.PrepareForSleep (false") {
+        return Some(Event::Resume);
+    }
+
+    // Only the current session matters, when it is known.
+    if !path.starts_with(SESSION_PATH) || session.is_some_and(|session| session != path) {
+        return None;
+    }
+    if signal.starts_with("org.freedesktop.login1.Session.Lock ") {
+        Some(Event::Lock)
+    }
+    else if signal.starts_with("org.freedesktop.login1.Session.Unlock ") {
+        Some(Event::Unlock)
+    }
+    else {
+        None
+    }
+}
+
+// The D-Bus object path of the session, where the characters other than the alphanumeric ones,
+// and a leading digit, are escaped as _XX.
+fn session_path() -> Option<String> {
+    let id = env::var("XDG_SESSION_ID").ok().filter(|id| !id.is_empty())?;
+    let mut path = SESSION_PATH.to_string();
+    for (index, byte) in id.bytes().enumerate() {
+        if byte.is_ascii_alphabetic() || (byte.is_ascii_digit() && index > 0) {
+            path.push(byte as char);
+        }
+        else {
+            path.push_str(&format!("_{:02x}", byte));
+        }
+    }
+    Some(path)
+}
@@ -1,295 +1,2475 @@
-/*
- * FIXME: it doesn't always keep the focused (not only visible) workspace focused and visible when
- * disconnecting a monitor.
- * FIXME: uses 100% CPU (seems to happen when having multiple instances of i3-aww running).
- * FIXME: if a workspace is empty, it won't be put back on the correct monitor.
- * TODO: reset mouse position when plugging back the second monitor.
- * TODO: if pressing on the active button on the KVM switch, it moves all the workspaces on one
- * screen (possibly because we don't handle the case where the config change to the same config).
- */
-
-use std::{io, time::Duration, process::Command, sync::Arc};
+mod audio;
+mod backend;
+mod bars;
+mod compositor;
+mod child;
+mod color;
+mod config;
+mod ddc;
+mod confirm;
+mod control;
+mod dbus;
+mod diff;
+mod doctor;
+mod hooks;
+mod idle;
+mod input;
+mod latency;
+mod lid;
+mod notify;
+mod lock;
+mod log;
+mod logind;
+mod metrics;
+#[cfg(test)]
+mod mock_i3;
+mod pointer;
+mod power;
+mod randr;
+mod record;
+mod sensor;
+mod state;
+mod status;
+mod sway;
+mod systemd;
+#[cfg(test)]
+mod tests;
+mod usb;
+mod wallpaper;
+mod xephyr;
+mod xevents;
+mod xresources;
 
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    io::{self, Write},
+    os::unix::{io::AsRawFd, net::UnixStream},
+    path::{Path, PathBuf},
+    process::{self, Child, Command},
+    slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        Mutex,
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
 use dashmap::DashMap;
-use glib::{MainLoop, timeout_add_once};
+use glib::{Continue, IOCondition, MainLoop, Pid, SourceId, child_watch_add_local, timeout_add_local, timeout_add_local_once, timeout_add_once, unix_fd_add_local, unix_signal_add};
+use gio::DBusConnection;
 use gudev::{Client, traits::{ClientExt, DeviceExt}};
+use i3_aww_core::{
+    edid::REFERENCE_DPI,
+    layout::{self, enabled_outputs, output_scales},
+    outputs::{self, MonitorData, connected_edids, connected_outputs, outputs_state},
+    restore::{self, Container, Snapshot, Workspace, workspace_order},
+    selection::{self, Conditions, is_internal},
+};
 use i3_ipc::{
-    event::{Event, Subscribe},
-    I3Stream, msg::Msg, I3, Connect,
+    event::{Event, ShutdownChange, Subscribe, WindowChange, WorkspaceChange},
+    reply::{FullscreenMode, Node, NodeType, Output as I3Output, Success},
+    msg::Msg, I3IPC, I3Protocol, MsgResponse,
 };
-use xrandr::{XHandle, Output};
+use serde::{Deserialize, Serialize};
+
+use backend::{DisplayBackend, I3Ipc, SwayOutputs, WmBackend, Xrandr};
+use config::{Config, Gamma, InputConfig, LogFormat, LogTarget, MonitorPos, OutputChain, OutputConfig, Profile, Rotation, TimeOfDay};
+use confirm::Confirmation;
+use latency::Latency;
+use notify::Urgency;
+use pointer::PointerPosition;
+use randr::Geometry;
+
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+// Interval between the attempts of bar-status to reach the daemon.
+const BAR_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// How often the idle time is checked when idle_standby is set.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+// Sent by the retry button of the error nagbar, or by the user to detect the outputs again and
+// apply the matching profile.
+const RETRY_SIGNAL: i32 = libc::SIGUSR1;
+// Run in a terminal by the log button of the error nagbar.
+const SHOW_LOG_COMMAND: &str = "journalctl --user --identifier i3-aww --pager-end";
+
+// Prefix of the payload of the i3 ticks meant for i3-aww.
+const TICK_PREFIX: &str = "i3-aww:";
 
-struct MonitorData {
-    name: String,
-    connected: bool,
+// Names of the temporary modes, which are also the names of their profiles.
+const PRESENTATION_MODE: &str = "presentation";
+const EXCLUSIVE_MODE: &str = "exclusive";
+
+// Hidden workspace of i3 holding the scratchpad windows.
+const SCRATCHPAD_WORKSPACE: &str = "__i3_scratch";
+
+// Set by --dry-run: the outputs and the workspaces are left as they are.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Parser)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    action: Option<Action>,
+    /// Source of the hotplug events.
+    #[arg(long, value_enum, default_value_t = Trigger::Udev)]
+    trigger: Trigger,
+    /// Instead of waiting for hotplug events, check the outputs every MILLISECONDS.
+    #[arg(long, value_name = "MILLISECONDS", conflicts_with = "trigger")]
+    poll: Option<u64>,
+    /// Replace the instance of the daemon that is already running.
+    #[arg(long)]
+    replace: bool,
+    /// Window manager and display server to drive.
+    #[arg(long, value_enum, default_value_t = Backend::I3)]
+    backend: Backend,
+    /// Path of the configuration file (default: $XDG_CONFIG_HOME/i3-aww/config.toml).
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Output to make primary when it is connected.
+    #[arg(long)]
+    primary: Option<String>,
+    /// Position of an output, as OUTPUT:XRANDR-ARGS (e.g. "DVI-D-0:--right-of HDMI-A-0").
+    #[arg(long = "position", value_name = "OUTPUT:ARGS", value_parser = parse_monitor_pos)]
+    positions: Vec<MonitorPos>,
+    /// Hotplug events closer than this many milliseconds are merged into one reconfiguration.
+    #[arg(long, default_value_t = 200)]
+    debounce: u64,
+    /// Delay in milliseconds between the hotplug event and the output configuration (default: 500).
+    #[arg(long)]
+    pre_apply_delay: Option<u64>,
+    /// Maximum delay in milliseconds between the output configuration and the workspace restoration (default: 500).
+    #[arg(long)]
+    post_apply_delay: Option<u64>,
+    /// Ask to keep a new layout, reverting it if it is not confirmed within SECONDS.
+    #[arg(long, value_name = "SECONDS")]
+    confirm: Option<u64>,
+    /// Show the debug messages, and the trace ones when repeated (default: from RUST_LOG).
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Where the messages go (default: the log_target of the configuration, or stderr).
+    #[arg(long, value_enum)]
+    log_target: Option<LogTarget>,
+    /// Format of the messages written to stderr (default: the log_format of the configuration, or text).
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+    /// Only log the xrandr arguments and the i3 commands that would be run, to try a new configuration
+    /// with the daemon or the apply command.
+    #[arg(long, conflicts_with = "confirm")]
+    dry_run: bool,
+    /// Record the hotplug events, the outputs and the workspaces seen by the daemon to FILE, for the
+    /// replay command.
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Serve the Prometheus metrics of the daemon on ADDRESS, like 127.0.0.1:9753 (default: the
+    /// metrics_address of the configuration, if any).
+    #[arg(long, value_name = "ADDRESS")]
+    metrics_address: Option<String>,
 }
 
-#[derive(Clone, Debug)]
-struct MonitorPos {
-    name: String,
-    args: Vec<String>,
+#[derive(Clone, Subcommand)]
+enum Action {
+    /// Save the current monitor layout as a profile.
+    Save {
+        name: String,
+    },
+    /// Apply the profile matching the connected outputs (or the given one) and exit.
+    Apply {
+        profile: Option<String>,
+    },
+    /// Print the changes that applying the profile matching the connected outputs (or the given one)
+    /// would make to the outputs and the workspaces.
+    Diff {
+        profile: Option<String>,
+    },
+    /// Check the environment: access to the uevents, X server, window manager, other tools
+    /// reconfiguring the outputs and configuration.
+    Doctor,
+    /// Print the active profile and the number of outputs for a status bar, on every change, as
+    /// long as the daemon runs.
+    BarStatus {
+        #[arg(long, value_enum, default_value_t = status::BarFormat::Waybar)]
+        format: status::BarFormat,
+    },
+    /// Print the detected outputs, the active profile and the workspaces.
+    Status {
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the configured profiles.
+    ListProfiles {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Set the brightness of a monitor over DDC/CI.
+    Brightness {
+        /// Connector name or alias of the monitor.
+        output: String,
+        /// Brightness in percent.
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        value: u8,
+    },
+    /// Mirror the screen on the newest external output, e.g. a projector, or go back to the
+    /// previous layout and workspaces.
+    Present {
+        #[arg(value_enum)]
+        toggle: Toggle,
+    },
+    /// Turn off every output but one monitor, e.g. for games, with all the workspaces on it, or go
+    /// back to the previous layout and workspaces.
+    Exclusive {
+        #[arg(value_enum)]
+        toggle: Toggle,
+        /// Connector name or alias of the monitor to keep.
+        #[arg(required_if_eq("toggle", "on"))]
+        output: Option<String>,
+    },
+    /// Go through the events recorded with --record, printing the profile and the workspace moves
+    /// that the current configuration chooses for them.
+    Replay {
+        file: PathBuf,
+    },
+    /// Make the running daemon handle a pretend hotplug event, to try the workspace restoration
+    /// without unplugging anything. The output stays as pretended until the opposite event.
+    Simulate {
+        #[arg(value_enum)]
+        event: HotplugEvent,
+        /// Connector name of the output.
+        output: String,
+    },
+    /// Check the restoration of the workspaces end to end: start Xephyr with several outputs, i3
+    /// and the daemon in it, then unplug and plug back the outputs with pretend hotplug events.
+    /// Needs Xephyr and i3.
+    TestXephyr {
+        /// Number of the display of Xephyr.
+        #[arg(long, default_value_t = 99)]
+        display: u32,
+    },
 }
 
-#[derive(Debug)]
-struct Workspace {
-    focused: bool,
-    num: i32,
-    output: String,
-    previous_output: Option<String>,
-    was_focused: bool,
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum HotplugEvent {
+    Connect,
+    Disconnect,
 }
 
-impl MonitorPos {
-    fn parse(data: &str) -> Option<Self> {
-        let mut data = data.split(':');
-        let name = data.next()?.to_string();
-        let args_string = data.next()?.to_string();
-        let args = args_string.split_ascii_whitespace()
-            .map(|str| str.to_string())
-            .collect();
-        Some(Self {
-            name,
-            args,
-        })
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Toggle {
+    On,
+    Off,
 }
 
-fn xrandr_outputs() -> Vec<Output> {
-    let outputs = (|| {
-        let mut handle = XHandle::open()?;
-        handle.all_outputs()
-    })();
-    outputs.unwrap_or(vec![])
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// i3 on X11, with the outputs configured through RandR.
+    I3,
+    /// sway, with the outputs configured through its IPC.
+    Sway,
 }
 
-fn monitor_connected(name: &str) -> bool {
-    let outputs = xrandr_outputs();
-    for output in outputs {
-        if output.name == name {
-            let connected = output.edid().is_some();
-            if connected {
-                return true;
-            }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Trigger {
+    /// DRM uevents from udev.
+    Udev,
+    /// RandR notifications from the X server.
+    Randr,
+}
+
+impl Backend {
+    // The display backend of the window manager. The window manager is reached through its IPC.
+    fn install(self) {
+        let display: Arc<dyn DisplayBackend> =
+            match self {
+                Backend::I3 => Arc::new(Xrandr),
+                Backend::Sway => Arc::new(SwayOutputs),
+            };
+        backend::install(display, Arc::new(I3Ipc));
+    }
+
+    fn set_wallpapers(self, wallpapers: &BTreeMap<String, String>) -> io::Result<()> {
+        if wallpapers.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Backend::I3 => wallpaper::xwallpaper(wallpapers),
+            Backend::Sway => sway::set_wallpapers(&mut *backend::wm()?, wallpapers),
+        }
+    }
+
+    fn map_inputs(self, mappings: &BTreeMap<String, String>) -> io::Result<()> {
+        if mappings.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Backend::I3 => input::map_to_outputs(mappings),
+            Backend::Sway => sway::map_inputs(&mut *backend::wm()?, mappings),
+        }
+    }
+
+    fn configure_inputs(self, input: &InputConfig) -> io::Result<()> {
+        match self {
+            Backend::I3 => input::configure(input),
+            Backend::Sway => sway::configure_inputs(&mut *backend::wm()?, input),
+        }
+    }
+
+    fn nagbar(self) -> &'static str {
+        match self {
+            Backend::I3 => "i3-nagbar",
+            Backend::Sway => "swaynag",
         }
     }
-    false
 }
 
-fn get_focused_workspace(i3: &mut I3Stream) -> Option<i32> {
-    if let Ok(i3_workspaces) = i3.get_workspaces() {
-        for workspace in &i3_workspaces {
+type Workspaces = Arc<DashMap<String, Workspace>>;
+
+fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+fn parse_monitor_pos(data: &str) -> Result<MonitorPos, String> {
+    MonitorPos::try_from(data.to_string())
+}
+
+// The command-line arguments take precedence over the configuration file.
+fn load_config(args: &Args) -> io::Result<Config> {
+    let mut config = Config::load(args.config.as_deref())?;
+    if let Some(ref primary) = args.primary {
+        config.primary = Some(OutputChain::Output(primary.clone()));
+    }
+    if !args.positions.is_empty() {
+        config.positions = args.positions.clone();
+    }
+    if args.pre_apply_delay.is_some() {
+        config.pre_apply_delay = args.pre_apply_delay;
+    }
+    if args.post_apply_delay.is_some() {
+        config.post_apply_delay = args.post_apply_delay;
+    }
+    log::set_output(args.log_target.or(config.log_target).unwrap_or_default(),
+        args.log_format.or(config.log_format).unwrap_or_default());
+    config::check_args(&config, args.backend)?;
+    Ok(config)
+}
+
+// The config with the aliases replaced by the connector names of the connected monitors.
+fn resolve_aliases(config: Config) -> Config {
+    config.resolve_aliases(&connected_edids(&backend::display().monitor_data()))
+}
+
+fn edid_fingerprints() -> BTreeMap<String, String> {
+    backend::display().monitor_data().into_iter()
+        .filter_map(|monitor| Some((monitor.name, monitor.edid?.fingerprint())))
+        .collect()
+}
+
+// The outputs in use, as reported by the window manager, or the connected ones when it cannot be
+// reached.
+fn active_output_names() -> Vec<String> {
+    match backend::wm().and_then(|mut wm| wm.outputs()) {
+        Ok(outputs) => outputs.into_iter()
+            .filter(|output| output.active)
+            .map(|output| output.name)
+            .collect(),
+        Err(_) => backend::display().monitor_data().into_iter()
+            .filter(|monitor| monitor.connected)
+            .map(|monitor| monitor.name)
+            .collect(),
+    }
+}
+
+fn get_focused_workspace(wm: &mut dyn WmBackend) -> Option<String> {
+    if let Ok(i3_workspaces) = wm.workspaces() {
+        for workspace in i3_workspaces {
             if workspace.focused {
-                return Some(workspace.num);
+                return Some(workspace.name);
             }
         }
     }
     None
 }
 
-fn focus(i3: &mut I3Stream, num: i32) {
-    let command = format!("workspace {}", num);
-    if let Err(error) = i3.send_msg(Msg::RunCommand, &command) {
-        eprintln!("Cannot focus workspace: {}", error);
-    }
+fn adjust_workspaces(workspaces: &DashMap<String, Workspace>) {
+    track_workspaces(workspaces, &connected_outputs(&backend::display().monitor_data()));
 }
 
-fn main() -> io::Result<()> {
-    // TODO: instead of taking those as cli arguments, infer them from the current xrandr config.
-    let primary_monitor = "HDMI-A-0".to_string();
-    let monitor_pos = "DVI-D-0:--right-of HDMI-A-0";
+// `fingerprints` are the connected outputs with the fingerprint of their monitor.
+fn track_workspaces(workspaces: &DashMap<String, Workspace>, fingerprints: &BTreeMap<String, Option<String>>) {
+    if let Ok(i3_workspaces) = backend::wm().and_then(|mut wm| wm.workspaces()) {
+        let active_outputs = active_output_names();
+        for workspace in &i3_workspaces {
+            let old_workspace = workspaces.get(&workspace.name).map(|old_workspace| old_workspace.clone());
+            let workspace = restore::track(old_workspace.as_ref(), workspace, &active_outputs, fingerprints);
+            workspaces.insert(workspace.name.clone(), workspace);
+        }
 
-    let monitor_pos = MonitorPos::parse(monitor_pos);
+        workspaces.retain(|name, workspace| {
+            i3_workspaces.iter().any(|i3_workspace| &i3_workspace.name == name) || restore::vanish(workspace, &active_outputs)
+        });
+    }
+}
 
-    let workspaces = Arc::new(DashMap::new());
+fn containers(wm: &mut dyn WmBackend) -> Vec<Container> {
+    // The output and workspace nodes above the current node, and the floating container.
+    struct Parents<'a> {
+        output: Option<&'a Node>,
+        workspace: Option<&'a String>,
+        floating: Option<&'a Node>,
+    }
 
-    let i3 = I3::connect();
-    if let Ok(i3_workspaces) = i3.and_then(|mut i3| i3.get_workspaces()) {
-        for workspace in &i3_workspaces {
-            let num = workspace.num;
-            workspaces.insert(num, Workspace {
-                focused: workspace.focused || workspace.visible,
-                num,
-                output: workspace.output.clone(),
-                previous_output: None,
-                was_focused: false,
-            });
+    fn collect<'a>(node: &'a Node, parents: Parents<'a>, containers: &mut Vec<Container>) {
+        let parents =
+            match node.node_type {
+                NodeType::Output => Parents { output: Some(node), ..parents },
+                NodeType::Workspace => Parents { workspace: node.name.as_ref(), ..parents },
+                NodeType::FloatingCon => Parents { floating: Some(node), ..parents },
+                _ => parents,
+            };
+        if node.node_type == NodeType::Con {
+            if let (Some(output), Some(workspace)) = (parents.output, parents.workspace) {
+                containers.push(Container {
+                    id: node.id,
+                    workspace: workspace.clone(),
+                    output: output.name.clone().unwrap_or_default(),
+                    output_rect: output.rect.clone(),
+                    focused: node.focused,
+                    fullscreen_mode: node.fullscreen_mode,
+                    floating: parents.floating.is_some(),
+                    rect: parents.floating.unwrap_or(node).rect.clone(),
+                });
+            }
+        }
+        for child in node.nodes.iter().chain(&node.floating_nodes) {
+            // Only the direct children of a floating container are floating windows.
+            let floating = if node.node_type == NodeType::FloatingCon { parents.floating } else { None };
+            collect(child, Parents { floating, ..parents }, containers);
         }
     }
 
-    let adjust_workspaces = {
-        let workspaces = Arc::clone(&workspaces);
-        move || {
-            if let Ok(i3_workspaces) = I3::connect().and_then(|mut i3| i3.get_workspaces()) {
-                for workspace in &i3_workspaces {
-                    let num = workspace.num;
-
-                    let mut previous_output = None;
-                    let mut was_focused = false;
-                    if let Some(old_workspace) = workspaces.get(&num) {
-                        // If there was no change, keep the old data.
-                        if workspace.output == old_workspace.output {
-                            previous_output = old_workspace.previous_output.clone();
-                            was_focused = old_workspace.was_focused;
-                        }
-                        // If there was a change after the monitor was disconnected.
-                        else if !monitor_connected(&old_workspace.output) {
-                            previous_output = Some(old_workspace.output.clone());
-                            was_focused = old_workspace.focused;
-                        }
-                    }
+    let mut containers = vec![];
+    match wm.tree() {
+        Ok(tree) => collect(&tree, Parents { output: None, workspace: None, floating: None }, &mut containers),
+        Err(error) => tracing::error!("Cannot get the i3 tree: {}", error),
+    }
+    containers
+}
 
-                    let workspace = Workspace {
-                        focused: workspace.focused || workspace.visible,
-                        num,
-                        output: workspace.output.clone(),
-                        previous_output,
-                        was_focused,
-                    };
-                    workspaces.insert(num, workspace);
+fn active_outputs(wm: &mut dyn WmBackend) -> Vec<I3Output> {
+    match wm.outputs() {
+        Ok(outputs) => outputs.into_iter()
+            .filter(|output| output.active)
+            .collect(),
+        Err(error) => {
+            tracing::error!("Cannot get the i3 outputs: {}", error);
+            vec![]
+        },
+    }
+}
+
+fn snapshot_workspaces() -> Snapshot {
+    let mut existing_workspaces = vec![];
+    let mut visible_workspaces = BTreeMap::new();
+    let mut focused_container = None;
+    let mut fullscreen_containers = vec![];
+    let mut floating_containers = vec![];
+    let focused_workspace = {
+        if let Ok(mut wm) = backend::wm() {
+            // The scratchpad windows stay where they are.
+            let containers = containers(&mut *wm).into_iter()
+                .filter(|container| container.workspace != SCRATCHPAD_WORKSPACE);
+            for container in containers {
+                if container.focused {
+                    focused_container = Some((container.id, container.workspace.clone()));
+                }
+                if container.fullscreen_mode != FullscreenMode::None {
+                    fullscreen_containers.push(container);
+                }
+                else if container.floating {
+                    floating_containers.push(container);
+                }
+            }
+            if let Ok(i3_workspaces) = wm.workspaces() {
+                for workspace in i3_workspaces {
+                    if workspace.visible {
+                        visible_workspaces.insert(workspace.output, workspace.name.clone());
+                    }
+                    existing_workspaces.push(workspace.name);
                 }
             }
+
+            get_focused_workspace(&mut *wm)
+        }
+        else {
+            None
         }
     };
+    Snapshot {
+        existing_workspaces,
+        focused_workspace,
+        previous_workspace: None,
+        visible_workspaces,
+        focused_container,
+        fullscreen_containers,
+        floating_containers,
+    }
+}
 
-    std::thread::spawn({
-        let adjust_workspaces = adjust_workspaces.clone();
-        move || {
-            if let Ok(mut i3) = I3Stream::conn_sub(&[Subscribe::Window, Subscribe::Workspace]) {
-                for event in i3.listen() {
-                    if let Ok(event) = event {
-                        match event {
-                            Event::Workspace(_) => {
-                                adjust_workspaces();
-                            },
-                            Event::Output(_) | Event::Window(_) | Event::Mode(_) | Event::BarConfig(_) | Event::Binding(_) |
-                                Event::Shutdown(_) | Event::Tick(_) => (),
-                        }
-                    }
+// The outputs that the daemon configures.
+fn managed_monitor_data(config: &Config) -> Vec<MonitorData> {
+    outputs::managed(backend::display().monitor_data(), |name, fingerprint| config.manages(name, fingerprint))
+}
+
+// The post-switch hooks only run when the outputs were configured.
+fn apply_with_hooks(backend: Backend, config: &Config, profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()> {
+    let switch = hooks::Switch {
+        profile: profile.name.clone(),
+        old_outputs: active_output_names(),
+        new_outputs: enabled_outputs(profile, monitor_data),
+    };
+    if dry_run() {
+        // Only the outputs are shown: the hooks and the other settings are skipped.
+        return backend::display().apply(profile, monitor_data);
+    }
+    hooks::run("pre-switch", &config.pre_switch, &switch);
+    hooks::run("exec_before", &profile.exec_before, &switch);
+    let mut gamma_ramps = BTreeMap::new();
+    if backend == Backend::I3 && config.preserve_gamma && config.night_light.is_none() {
+        match color::save() {
+            Ok(ramps) => gamma_ramps = ramps,
+            Err(error) => tracing::error!("Cannot save the gamma ramps: {}", error),
+        }
+    }
+    if config.standby_disabled {
+        set_standby(profile, monitor_data, true);
+    }
+    backend::display().apply(profile, monitor_data)?;
+    if config.standby_disabled {
+        set_standby(profile, monitor_data, false);
+    }
+    split_outputs(backend, profile, monitor_data);
+    if backend == Backend::I3 {
+        set_adaptive_sync(profile, monitor_data);
+    }
+    set_colors(backend, config, profile, monitor_data, &gamma_ramps);
+    if let Some(ref night_light) = config.night_light {
+        hooks::run("night light", slice::from_ref(night_light), &switch);
+    }
+    if backend == Backend::I3 && config.xft_dpi {
+        let max_scale = output_scales(profile, monitor_data).into_values().fold(1.0, f64::max);
+        match xresources::set_dpi(REFERENCE_DPI * max_scale) {
+            Ok(true) => hooks::run("dpi-change", &config.dpi_change, &switch),
+            Ok(false) => (),
+            Err(error) => tracing::error!("Cannot set Xft.dpi: {}", error),
+        }
+    }
+    if let Err(error) = backend.set_wallpapers(&config.wallpapers(profile)) {
+        tracing::error!("Cannot set the wallpapers: {}", error);
+    }
+    if let Err(error) = backend.map_inputs(&config.input_mappings(profile)) {
+        tracing::error!("Cannot map the input devices to their output: {}", error);
+    }
+    for monitor in monitor_data {
+        let brightness = profile.outputs.get(&monitor.name)
+            .filter(|output_config| monitor.connected && output_config.enabled)
+            .and_then(|output_config| output_config.brightness);
+        if let (Some(brightness), Some(edid)) = (brightness, &monitor.edid) {
+            if let Err(error) = ddc::set_brightness(edid, brightness) {
+                tracing::error!(output = %monitor.name, "Cannot set the brightness of {}: {}", monitor.name, error);
+            }
+        }
+    }
+    if let Some(ref input) = profile.input {
+        if let Err(error) = backend.configure_inputs(input) {
+            tracing::error!("Cannot configure the input devices: {}", error);
+        }
+    }
+    hooks::run("exec_after", &profile.exec_after, &switch);
+    hooks::run("post-switch", &config.post_switch, &switch);
+    Ok(())
+}
+
+// Even without any split, the monitors of the outputs split by the previous profile are deleted.
+fn split_outputs(backend: Backend, profile: &Profile, monitor_data: &[MonitorData]) {
+    let splits: BTreeMap<_, _> = enabled_outputs(profile, monitor_data).into_iter()
+        .filter_map(|name| {
+            let parts = profile.outputs[&name].split?;
+            Some((name, parts))
+        })
+        .collect();
+    match backend {
+        Backend::I3 => {
+            if let Err(error) = randr::split_outputs(&splits) {
+                tracing::error!("Cannot split the outputs: {}", error);
+            }
+        },
+        Backend::Sway => {
+            for name in splits.keys() {
+                tracing::warn!(output = %name, "Sway cannot split output {}", name);
+            }
+        },
+    }
+}
+
+// The monitors are woken up before the outputs are enabled, so that they see the signal, and put
+// in standby once the outputs are turned off. Internal panels have no DDC/CI.
+fn set_standby(profile: &Profile, monitor_data: &[MonitorData], wake_up: bool) {
+    for monitor in monitor_data {
+        let enabled =
+            match profile.outputs.get(&monitor.name) {
+                Some(output_config) => output_config.enabled,
+                None => continue,
+            };
+        let edid =
+            match monitor.edid {
+                Some(ref edid) if monitor.connected && !is_internal(&monitor.name) => edid,
+                _ => continue,
+            };
+        let result =
+            match (wake_up, enabled) {
+                (true, true) => ddc::wake_up(edid),
+                (false, false) => ddc::standby(edid),
+                _ => continue,
+            };
+        if let Err(error) = result {
+            tracing::error!(output = %monitor.name, "Cannot change the power mode of {}: {}", monitor.name, error);
+        }
+    }
+}
+
+// The Xorg drivers that support adaptive sync expose it as the vrr_capable property of the outputs.
+// Sway sets it with the other settings of the outputs.
+fn set_adaptive_sync(profile: &Profile, monitor_data: &[MonitorData]) {
+    for name in enabled_outputs(profile, monitor_data) {
+        if let Some(adaptive_sync) = profile.outputs[&name].adaptive_sync {
+            if let Err(error) = randr::set_output_property(&name, "vrr_capable", adaptive_sync as i64) {
+                tracing::error!(output = %name, "Cannot set the adaptive sync of {}: {}", name, error);
+            }
+        }
+    }
+}
+
+// The gamma ramps are reset when an output is enabled, so the colors are set after every
+// reconfiguration, even of the outputs that did not change. The other outputs get back their
+// `saved_ramps`.
+fn set_colors(backend: Backend, config: &Config, profile: &Profile, monitor_data: &[MonitorData],
+    saved_ramps: &BTreeMap<String, [Vec<u16>; 3]>)
+{
+    for name in enabled_outputs(profile, monitor_data) {
+        let output_config = &profile.outputs[&name];
+        let icc_profile = config.color_profile(profile, &name);
+        if icc_profile.is_none() && output_config.gamma.is_none() && output_config.software_brightness.is_none() {
+            if let Some(ramps) = saved_ramps.get(&name) {
+                if let Err(error) = color::restore(&name, ramps) {
+                    tracing::error!(output = %name, "Cannot restore the gamma of {}: {}", name, error);
                 }
             }
+            continue;
+        }
+        if backend == Backend::Sway {
+            tracing::warn!(output = %name, "Sway cannot change the colors of output {}, use a tool like gammastep", name);
+            continue;
+        }
+
+        let Gamma(gamma) = output_config.gamma.unwrap_or(Gamma([1.0; 3]));
+        let brightness = output_config.software_brightness.unwrap_or(1.0);
+        if let Err(error) = color::apply(&name, icc_profile.map(wallpaper::expand_home).as_deref(), gamma, brightness) {
+            tracing::error!(output = %name, "Cannot set the colors of {}: {}", name, error);
+        }
+    }
+}
+
+// Sway composites by itself, so only the geometry of the X outputs matters.
+fn output_geometries(backend: Backend) -> BTreeMap<String, Geometry> {
+    if backend != Backend::I3 {
+        return BTreeMap::new();
+    }
+    randr::query().unwrap_or_default().into_iter()
+        .filter_map(|output| Some((output.name, output.geometry?)))
+        .collect()
+}
+
+// Sway has no notion of primary output.
+fn current_primary(backend: Backend) -> Option<String> {
+    if backend != Backend::I3 {
+        return None;
+    }
+    randr::query().ok()?.into_iter()
+        .find(|output| output.primary)
+        .map(|output| output.name)
+}
+
+// Whether an output shows the same picture as another one. The window manager then sees them as
+// a single output, so the workspaces are left where they are.
+fn mirroring(backend: Backend) -> bool {
+    if backend != Backend::I3 {
+        return false;
+    }
+    let geometries: Vec<_> = randr::query().unwrap_or_default().into_iter()
+        .filter_map(|output| output.geometry)
+        .collect();
+    geometries.iter().enumerate()
+        .any(|(index, geometry)| geometries[index + 1..].iter().any(|other| other.x == geometry.x && other.y == geometry.y))
+}
+
+// Docks renumber their outputs: the previous output of a workspace is replaced by the one its
+// monitor is now connected to, or by another branch of the same MST port.
+fn remap_previous_outputs(workspaces: &Workspaces, active: &[String]) {
+    let fingerprints = connected_outputs(&backend::display().monitor_data());
+    for mut workspace in workspaces.iter_mut() {
+        if let Some(new_output) = restore::remap(&workspace, active, &fingerprints) {
+            workspace.previous_output = Some(new_output);
         }
+    }
+}
+
+// The errors of the commands rejected by i3 are returned once all the others were run.
+fn restore_workspaces(backend: Backend, workspaces: &Workspaces, snapshot: &Snapshot, config: &Config) -> io::Result<()> {
+    let _span = tracing::info_span!("restore").entered();
+    adjust_workspaces(workspaces);
+    let mut wm = backend::wm()?;
+
+    // Everything is computed from the state before the restoration and sent as a single message,
+    // so that the user cannot interleave actions and the screens flicker less.
+    let current = containers(&mut *wm);
+    let outputs = active_outputs(&mut *wm);
+
+    // Move the workspaces back to their previous monitor, unless the configuration assigns them
+    // to another output: to the preferred one among those that are active, so that they move
+    // back up their chain when a better output reconnects.
+    let active: Vec<_> = outputs.iter()
+        .map(|output| output.name.clone())
+        .filter(|name| !config.ignores(name))
+        .collect();
+    remap_previous_outputs(workspaces, &active);
+    let primary = outputs.iter().find(|output| output.primary).map(|output| output.name.clone());
+    let assigned = config.workspaces.iter()
+        .filter_map(|(name, chain)| Some((name.clone(), chain.pick(&active, primary.as_ref())?.clone())))
+        .collect();
+    let rects = outputs.iter()
+        .map(|output| (output.name.clone(), output.rect.clone()))
+        .collect();
+    let history = workspaces.iter()
+        .map(|workspace| (workspace.key().clone(), workspace.value().clone()))
+        .collect();
+    let plan = restore::plan(&history, snapshot, &restore::Current {
+        containers: &current,
+        outputs: &rects,
+        active: &active,
+        assigned: &assigned,
+        mirroring: mirroring(backend),
     });
+    for (name, output) in &plan.moves {
+        tracing::info!(workspace = %name, output = %output, "Moving workspace {} to {}", name, output);
+    }
+    let commands = plan.commands;
 
-    let client = Client::new(&[]);
-
-    client.connect_uevent(move |_client, _name, device| {
-        if device.devtype().map(|string| string.to_string()) == Some("drm_minor".to_string()) {
-            let primary_monitor = primary_monitor.clone();
-            let monitor_pos = monitor_pos.clone();
-            let workspaces = Arc::clone(&workspaces);
-            let adjust_workspaces = adjust_workspaces.clone();
-            timeout_add_once(Duration::from_millis(500), move || {
-                // Since i3 creates empty workspaces, make a list of existing workspaces to avoid
-                // focusing unexisting workspaces later.
-                let mut existing_workspaces = vec![];
-                let focused_workspace = {
-                    if let Ok(mut i3) = I3::connect() {
-                        if let Ok(i3_workspaces) = i3.get_workspaces() {
-                            for workspace in &i3_workspaces {
-                                existing_workspaces.push(workspace.num);
-                            }
-                        }
+    let mut errors = vec![];
+    if !commands.is_empty() && dry_run() {
+        tracing::info!(commands = %commands.join("; "), "Would run {}", commands.join("; "));
+    }
+    else if !commands.is_empty() {
+        tracing::trace!(commands = %commands.join("; "), "Running {}", commands.join("; "));
+        match wm.run_commands(&commands) {
+            Ok(replies) => {
+                errors.extend(replies.into_iter()
+                    .filter(|reply| !reply.success)
+                    .map(|reply| reply.error.unwrap_or_default()));
+            },
+            Err(error) => errors.push(error.to_string()),
+        }
+    }
+    for mut workspace in workspaces.iter_mut() {
+        workspace.was_focused = false;
+    }
 
-                        get_focused_workspace(&mut i3)
-                    }
-                    else {
-                        None
-                    }
+    // The disappeared workspaces are either recreated now or gone for good.
+    workspaces.retain(|_, workspace| {
+        workspace.present || !workspace.previous_output.as_ref().is_some_and(|output| active.contains(output))
+    });
+
+    if errors.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(io::Error::other(errors.join("; ")))
+    }
+}
+
+// Connects a new stream to the window manager, receiving the requested events.
+fn subscribe(events: &[Subscribe]) -> io::Result<UnixStream> {
+    let mut stream = UnixStream::connect(i3_ipc::socket_path()?)?;
+    let subscribe = stream.encode_msg_json(Msg::Subscribe, events)?;
+    stream.write_all(&subscribe)?;
+    let (msg_type, payload) = stream.decode_msg()?;
+    let reply: MsgResponse<Success> = MsgResponse::new(msg_type, payload)?;
+    if !reply.body.success {
+        return Err(io::Error::other("cannot subscribe to the i3 events"));
+    }
+    Ok(stream)
+}
+
+// Blocks until the window manager reports a change of outputs, or until the timeout.
+fn wait_for_output_event(stream: Option<&mut UnixStream>, timeout: Duration) {
+    let stream =
+        match stream {
+            Some(stream) => stream,
+            None => {
+                std::thread::sleep(timeout);
+                return;
+            },
+        };
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || stream.set_read_timeout(Some(remaining)).is_err() {
+            return;
+        }
+        let event = stream.decode_msg()
+            .and_then(|(event_type, payload)| i3_ipc::decode_event(event_type, payload));
+        match event {
+            Ok(Event::Output(_)) | Err(_) => return,
+            Ok(_) => (),
+        }
+    }
+}
+
+// What decides the profile besides the connected monitors. UPower is only asked when a profile
+// changes on battery, and the lid is only read when it is handled.
+fn conditions(config: &Config) -> Conditions {
+    let on_battery = config.profiles.iter().any(|profile| !profile.battery.is_empty()) &&
+        power::on_battery()
+            .map_err(|error| tracing::error!("Cannot get the power source: {}", error))
+            .unwrap_or_default();
+    Conditions {
+        docks: config::attached_docks(config),
+        now: TimeOfDay::now(),
+        on_battery,
+        lid_closed: config.lid_switch && lid::closed(),
+        orientation: None,
+        user_primary: None,
+    }
+}
+
+// The profile for the managed outputs, or the one with this name, in the current conditions.
+fn choose_profile(config: &Config, monitor_data: &[MonitorData], name: Option<&str>) -> io::Result<Profile> {
+    selection::choose_profile(config, monitor_data, name, &conditions(config))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no profile named {}", name.unwrap_or_default())))
+}
+
+// Apply the matching (or the requested) profile once, without waiting for hotplug events.
+fn apply_once(args: &Args, profile_name: Option<&str>) -> io::Result<()> {
+    // The running daemon knows where the workspaces were, and asks to confirm the layout itself.
+    let request =
+        match profile_name {
+            Some(name) => Request::Apply { profile: name.to_string(), confirm: args.confirm },
+            None => Request::Reconcile { confirm: args.confirm },
+        };
+    // The daemon would apply the profile for real.
+    if !dry_run() && control::send(&request)?.is_some() {
+        return Ok(());
+    }
+
+    let config = resolve_aliases(load_config(args)?);
+    let workspaces = Arc::new(DashMap::new());
+    adjust_workspaces(&workspaces);
+
+    let snapshot = snapshot_workspaces();
+    let monitor_data = managed_monitor_data(&config);
+    let profile = choose_profile(&config, &monitor_data, profile_name)?;
+
+    let previous = args.confirm.map(|_| backend::display().current_profile("previous")).transpose()?;
+    let post_apply_delay = config.post_apply_delay(&profile);
+    // Subscribe before applying the profile so that the event cannot be missed.
+    let mut events = subscribe(&[Subscribe::Output])
+        .map_err(|error| tracing::error!("Cannot subscribe to the output events: {}", error))
+        .ok();
+    apply_with_hooks(args.backend, &config, &profile, &monitor_data)?;
+    wait_for_output_event(events.as_mut(), post_apply_delay);
+    if let Err(error) = restore_workspaces(args.backend, &workspaces, &snapshot, &config) {
+        tracing::error!("Cannot restore the workspaces: {}", error);
+    }
+
+    if let (Some(seconds), Some(previous)) = (args.confirm, previous) {
+        let main_loop = MainLoop::new(None, false);
+        let kept = Arc::new(AtomicBool::new(true));
+        let _confirmation = confirm::ask(args.backend.nagbar(), Duration::from_secs(seconds), {
+            let main_loop = main_loop.clone();
+            let kept = Arc::clone(&kept);
+            move |answer| {
+                kept.store(answer, Ordering::SeqCst);
+                main_loop.quit();
+            }
+        })?;
+        main_loop.run();
+
+        if !kept.load(Ordering::SeqCst) {
+            apply_with_hooks(args.backend, &config, &previous, &managed_monitor_data(&config))?;
+            wait_for_output_event(events.as_mut(), post_apply_delay);
+            if let Err(error) = restore_workspaces(args.backend, &workspaces, &snapshot, &config) {
+                tracing::error!("Cannot restore the workspaces: {}", error);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn present(args: &Args, toggle: Toggle) -> io::Result<()> {
+    if toggle == Toggle::Off {
+        return leave_mode(args, PRESENTATION_MODE);
+    }
+    if args.backend == Backend::Sway {
+        return Err(io::Error::other("sway cannot mirror outputs"));
+    }
+
+    let config = resolve_aliases(load_config(args)?);
+    let monitor_data = managed_monitor_data(&config);
+    let connected = connected_outputs(&monitor_data);
+    let times = state::connection_times(&connected.keys().cloned().collect())?;
+    let external = connected.keys()
+        .filter(|name| !is_internal(name))
+        .max_by_key(|name| times.get(*name))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no external output to present on"))?;
+    // The internal panel of a laptop, otherwise the primary output.
+    let source = connected.keys().find(|name| is_internal(name)).cloned()
+        .or_else(|| current_primary(args.backend).filter(|primary| primary != external))
+        .or_else(|| connected.keys().find(|name| *name != external).cloned())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no other output to mirror"))?;
+
+    let mut profile = single_output_profile(&config, &connected, PRESENTATION_MODE, &source);
+    profile.outputs.insert(external.clone(), OutputConfig { mirror: Some(source), ..OutputConfig::default() });
+    enter_mode(args, &config, &profile, &monitor_data)
+}
+
+fn exclusive(args: &Args, toggle: Toggle, output: Option<&str>) -> io::Result<()> {
+    let output =
+        match (toggle, output) {
+            (Toggle::On, Some(output)) => output,
+            _ => return leave_mode(args, EXCLUSIVE_MODE),
+        };
+
+    let config = load_config(args)?;
+    // The monitor is identified like in the configuration, so aliases work too.
+    let connector = config.alias_connectors(&connected_edids(&backend::display().monitor_data())).remove(output)
+        .unwrap_or_else(|| output.to_string());
+    let config = resolve_aliases(config);
+    let monitor_data = managed_monitor_data(&config);
+    let connected = connected_outputs(&monitor_data);
+    if !connected.contains_key(&connector) {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not connected", output)));
+    }
+
+    // The window manager moves the workspaces of the disabled outputs to the remaining one.
+    let profile = single_output_profile(&config, &connected, EXCLUSIVE_MODE, &connector);
+    enter_mode(args, &config, &profile, &monitor_data)
+}
+
+// The profile matching the connected outputs, with only `output` enabled.
+fn single_output_profile(config: &Config, connected: &BTreeMap<String, Option<String>>, name: &str, output: &str) -> Profile {
+    let mut profile = config.profile_for(connected, &config::attached_docks(config), TimeOfDay::now());
+    profile.name = name.to_string();
+    profile.primary = Some(output.to_string());
+    for (name, output_config) in &mut profile.outputs {
+        output_config.enabled = name == output;
+    }
+    profile.outputs.entry(output.to_string()).or_default();
+    profile
+}
+
+// The layout and the workspaces from before the temporary mode are saved to the disk, so that they
+// can be put back even after a restart.
+fn enter_mode(args: &Args, config: &Config, profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()> {
+    if let Some(saved_layout) = state::load_saved_layout()? {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("the {} mode is already on", saved_layout.mode)));
+    }
+
+    let workspaces = DashMap::new();
+    adjust_workspaces(&workspaces);
+    state::save_layout(&state::SavedLayout {
+        mode: profile.name.clone(),
+        previous: backend::display().current_profile("previous")?,
+        workspaces: workspaces.into_iter().map(|(_, workspace)| workspace).collect(),
+    })?;
+    apply_with_hooks(args.backend, config, profile, monitor_data)
+}
+
+fn leave_mode(args: &Args, mode: &str) -> io::Result<()> {
+    let saved_layout =
+        match state::load_saved_layout()? {
+            Some(saved_layout) if saved_layout.mode == mode => saved_layout,
+            Some(saved_layout) => return Err(io::Error::other(format!("the {} mode is on, not the {} one", saved_layout.mode, mode))),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("the {} mode is not on", mode))),
+        };
+    let config = resolve_aliases(load_config(args)?);
+    let monitor_data = managed_monitor_data(&config);
+
+    let snapshot = snapshot_workspaces();
+    // Subscribe before applying the profile so that the event cannot be missed.
+    let mut events = subscribe(&[Subscribe::Output])
+        .map_err(|error| tracing::error!("Cannot subscribe to the output events: {}", error))
+        .ok();
+    apply_with_hooks(args.backend, &config, &saved_layout.previous, &monitor_data)?;
+    state::remove_saved_layout()?;
+    wait_for_output_event(events.as_mut(), config.post_apply_delay(&saved_layout.previous));
+
+    // Every workspace goes back to the output it was on, like when a disconnected monitor comes
+    // back.
+    let workspaces = Arc::new(DashMap::new());
+    adjust_workspaces(&workspaces);
+    for saved in saved_layout.workspaces {
+        match workspaces.get_mut(&saved.name) {
+            Some(mut workspace) => {
+                workspace.previous_output = Some(saved.output);
+                workspace.monitor = saved.monitor;
+                workspace.was_visible = saved.visible;
+                workspace.was_focused = saved.focused;
+            },
+            None => {
+                let workspace = Workspace {
+                    previous_output: Some(saved.output.clone()),
+                    was_visible: saved.visible,
+                    was_focused: saved.focused,
+                    present: false,
+                    ..saved
                 };
+                workspaces.insert(workspace.name.clone(), workspace);
+            },
+        }
+    }
+    restore_workspaces(args.backend, &workspaces, &snapshot, &config)
+}
 
-                let outputs = xrandr_outputs();
-                let mut monitor_data = vec![];
-                for output in outputs {
-                    let connected = output.edid().is_some();
-                    monitor_data.push(MonitorData {
-                        name: output.name,
-                        connected,
-                    });
-                }
+fn xrandr_profile(name: &str) -> io::Result<Profile> {
+    let fingerprints = edid_fingerprints();
+    let mut profile = Profile {
+        name: name.to_string(),
+        primary: None,
+        pre_apply_delay: None,
+        post_apply_delay: None,
+        dock: None,
+        exec_before: vec![],
+        exec_after: vec![],
+        wallpapers: BTreeMap::new(),
+        input: None,
+        battery: BTreeMap::new(),
+        after: None,
+        before: None,
+        outputs: BTreeMap::new(),
+    };
 
-                let mut command = Command::new("xrandr");
+    for output in randr::query()? {
+        if !output.connected {
+            continue;
+        }
 
-                let mut primary_connected = false;
+        if output.primary {
+            profile.primary = Some(output.name.clone());
+        }
 
-                for monitor in &monitor_data {
-                    if primary_monitor == monitor.name && monitor.connected {
-                        primary_connected = true;
-                    }
+        let mut output_config = OutputConfig {
+            edid: fingerprints.get(&output.name).cloned(),
+            ..OutputConfig::default()
+        };
+        match output.geometry {
+            Some(geometry) => {
+                if let Some(mode) = output.mode {
+                    output_config.args.extend(["--mode".to_string(), mode]);
+                }
+                if let Some(rate) = output.rate {
+                    output_config.args.extend(["--rate".to_string(), rate]);
+                }
+                output_config.args.extend([
+                    "--pos".to_string(),
+                    format!("{}x{}", geometry.x, geometry.y),
+                    "--rotate".to_string(),
+                    output.rotation,
+                ]);
+                if output.scale != (1.0, 1.0) {
+                    output_config.args.extend(["--scale".to_string(), format!("{}x{}", output.scale.0, output.scale.1)]);
                 }
+            },
+            None => output_config.enabled = false,
+        }
+        profile.outputs.insert(output.name, output_config);
+    }
+    Ok(profile)
+}
 
-                let mut primary_set = primary_connected;
+fn save_profile(args: &Args, name: &str) -> io::Result<()> {
+    // The running daemon also reloads its configuration, to know the new profile.
+    let path =
+        match control::send(&Request::Save(name.to_string()))? {
+            Some(path) => PathBuf::from(path.unwrap_or_default()),
+            None => save_current_layout(args, name)?,
+        };
+    println!("Saved profile {} to {}", name, path.display());
+    Ok(())
+}
 
-                for monitor in &monitor_data {
-                    command.arg("--output");
-                    command.arg(&monitor.name);
+// Returns the path of the file of the profile.
+fn save_current_layout(args: &Args, name: &str) -> io::Result<PathBuf> {
+    let profile = backend::display().current_profile(name)?;
+    let dir = config::profiles_dir(args.config.as_deref())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cannot find the configuration directory"))?;
+    profile.save(&dir)
+}
 
-                    if monitor.connected {
-                        // TODO: also infer this from the current xrandr config to set the correct
-                        // resolution.
-                        command.arg("--auto");
+fn print_status(args: &Args, json: bool) -> io::Result<()> {
+    let status =
+        match control::send(&Request::GetStatus)? {
+            Some(reply) => serde_json::from_str(&reply.unwrap_or_default())
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("invalid status from the daemon: {}", error)))?,
+            None => current_status(args)?,
+        };
+    status::print_status(&status, json)
+}
 
-                        if let Some(ref monitor_pos) = monitor_pos {
-                            if monitor_pos.name == monitor.name {
-                                command.args(&monitor_pos.args);
-                            }
-                        }
+// The bar shows that the daemon is not running, until it starts.
+fn print_bar_status(format: status::BarFormat) -> io::Result<()> {
+    loop {
+        let watched = control::watch(|reply| {
+            match serde_json::from_str(&reply) {
+                Ok(status) => status::print_bar(Some(&status), format),
+                Err(error) => tracing::error!("Invalid status from the daemon: {}", error),
+            }
+        });
+        if let Err(error) = watched {
+            tracing::error!("Cannot watch the daemon: {}", error);
+        }
+        status::print_bar(None, format);
+        std::thread::sleep(BAR_RETRY_INTERVAL);
+    }
+}
 
-                        if monitor.name == primary_monitor || !primary_set {
-                            command.arg("--primary");
-                            primary_set = true;
-                        }
-                    }
-                    else {
-                        command.arg("--off");
-                    }
-                }
+// The status detected without the daemon, which has no workspace history.
+fn current_status(args: &Args) -> io::Result<status::Status> {
+    let config = resolve_aliases(load_config(args)?);
+    let monitor_data = backend::display().monitor_data();
+    let managed = managed_monitor_data(&config);
+    let profile = choose_profile(&config, &managed, None)?;
 
-                if let Err(error) = command.status() {
-                    eprintln!("Could not set the monitor config: {}", error);
-                }
+    let workspaces = DashMap::new();
+    adjust_workspaces(&workspaces);
+    let mut workspaces: Vec<_> = workspaces.into_iter()
+        .map(|(_, workspace)| workspace)
+        .collect();
+    workspaces.sort_by_key(workspace_order);
+    Ok(status::Status::new(&monitor_data, &profile, workspaces))
+}
 
-                timeout_add_once(Duration::from_millis(500), move || {
-                    adjust_workspaces();
-                    let mut i3 =
-                        match I3::connect() {
-                            Ok(i3) => i3,
-                            Err(error) => {
-                                eprintln!("Error connecting to i3: {}", error);
-                                return;
-                            },
-                        };
-
-                    // Move the workspaces to their previous monitor.
-                    for workspace in workspaces.iter() {
-                        if let Some(ref output) = workspace.previous_output {
-                            if monitor_connected(output) {
-                                let command = format!("[workspace=\"{}\"] move workspace to output {}", workspace.num, output);
-                                if let Err(error) = i3.send_msg(Msg::RunCommand, &command) {
-                                    eprintln!("Cannot move workspace: {}", error);
-                                }
-                            }
-                        }
-                    }
+fn print_diff(args: &Args, profile_name: Option<&str>) -> io::Result<()> {
+    let config = resolve_aliases(load_config(args)?);
+    let monitor_data = managed_monitor_data(&config);
+    let profile = choose_profile(&config, &monitor_data, profile_name)?;
 
-                    // Make visible the right workspaces.
-                    for workspace in workspaces.iter() {
-                        if workspace.was_focused && existing_workspaces.contains(&workspace.num) {
-                            focus(&mut i3, workspace.num);
-                        }
-                    }
+    let states =
+        match args.backend {
+            Backend::I3 => randr::query()?,
+            Backend::Sway => vec![],
+        };
+    let current: BTreeMap<_, _> =
+        match args.backend {
+            Backend::I3 => states.iter()
+                .filter(|state| state.connected)
+                .map(|state| (state.name.clone(), diff::OutputLayout {
+                    enabled: state.geometry.is_some(),
+                    primary: state.primary,
+                    mode: state.mode.clone(),
+                    rotation: Some(state.rotation.clone()),
+                }))
+                .collect(),
+            // Sway has no primary output.
+            Backend::Sway => backend::wm()?.outputs()?.into_iter()
+                .map(|output| (output.name, diff::OutputLayout {
+                    enabled: output.active,
+                    ..diff::OutputLayout::default()
+                }))
+                .collect(),
+        };
+    let modes = randr::output_modes(&states);
+    let enabled = enabled_outputs(&profile, &monitor_data);
+    let planned: BTreeMap<_, _> = monitor_data.iter()
+        .filter(|monitor| !config.ignores(&monitor.name))
+        .filter_map(|monitor| {
+            let output_config = profile.outputs.get(&monitor.name)?;
+            let layout = diff::OutputLayout {
+                enabled: enabled.contains(&monitor.name),
+                primary: args.backend == Backend::I3 && profile.primary.as_ref() == Some(&monitor.name),
+                mode: layout::output_mode(output_config, modes.get(&monitor.name)),
+                rotation: output_config.rotate.map(|rotation| rotation.xrandr_name().to_string()),
+            };
+            Some((monitor.name.clone(), layout))
+        })
+        .collect();
+    let mut changes = diff::output_changes(&current, &planned);
 
-                    if let Some(workspace) = focused_workspace {
-                        if existing_workspaces.contains(&workspace) {
-                            focus(&mut i3, workspace);
-                        }
-                    }
-                });
-            });
+    // The workspaces go back to their previous output, from the history of the daemon.
+    let workspaces = DashMap::new();
+    if let Err(error) = state::load(&workspaces) {
+        tracing::error!("Cannot load the workspaces: {}", error);
+    }
+    adjust_workspaces(&workspaces);
+    let mut workspaces: Vec<_> = workspaces.into_iter()
+        .map(|(_, workspace)| workspace)
+        .collect();
+    workspaces.sort_by_key(workspace_order);
+    let planned = restore::planned_outputs(&config, &profile, &enabled, &workspaces);
+    changes.extend(diff::workspace_changes(&workspaces, &planned));
+
+    diff::print(&profile.name, &changes);
+    Ok(())
+}
+
+// The battery, the lid and the docks are the current ones, not those of the recording.
+fn replay(args: &Args, file: &Path) -> io::Result<()> {
+    let config = load_config(args)?;
+    let records = record::read(file)?;
+    let start = records.first().map_or(0.0, |record| record.time);
+    let mut workspaces = vec![];
+    for record in records {
+        let time = record.time - start;
+        match record.event {
+            record::Event::Uevent { action, subsystem, devtype, name } => {
+                println!("{:>9.3}  uevent {} {} {} {}", time, action, subsystem.unwrap_or_default(),
+                    devtype.unwrap_or_default(), name.unwrap_or_default());
+            },
+            record::Event::Hotplug { trigger } => println!("{:>9.3}  hotplug event from {}", time, trigger),
+            record::Event::Workspaces { workspaces: recorded } => workspaces = recorded,
+            record::Event::Outputs { monitors } => {
+                // The aliases name the monitors of the recording.
+                let config = config.clone().resolve_aliases(&connected_edids(&monitors));
+                let monitors = outputs::managed(monitors, |name, fingerprint| config.manages(name, fingerprint));
+                let connected = connected_outputs(&monitors);
+                let profile = choose_profile(&config, &monitors, None)?;
+                let names: Vec<_> = connected.keys().map(String::as_str).collect();
+                println!("{:>9.3}  outputs {}", time, names.join(", "));
+                println!("{:>9.3}  profile {} with primary output {}", time, profile.name,
+                    profile.primary.as_deref().unwrap_or("none"));
+                let enabled = enabled_outputs(&profile, &monitors);
+                let planned = restore::planned_outputs(&config, &profile, &enabled, &workspaces);
+                for change in diff::workspace_changes(&workspaces, &planned) {
+                    println!("{:>9.3}  {}", time, change);
+                }
+            },
         }
-    });
+    }
+    Ok(())
+}
+
+fn simulate(event: HotplugEvent, output: &str) -> io::Result<()> {
+    let request = Request::Simulate {
+        output: output.to_string(),
+        connected: event == HotplugEvent::Connect,
+    };
+    match control::send(&request)? {
+        Some(_) => Ok(()),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "the simulation needs a running daemon")),
+    }
+}
+
+fn run_doctor(args: &Args) -> io::Result<()> {
+    let mut checks = vec![];
+    if args.poll.is_none() && args.trigger == Trigger::Udev {
+        checks.push(doctor::udev());
+    }
+    if args.backend == Backend::I3 {
+        checks.push(doctor::x_server());
+        checks.push(doctor::randr());
+    }
+    checks.push(doctor::window_manager());
+    checks.push(doctor::conflicting_daemons());
+    let outcome =
+        match load_config(args) {
+            Ok(config) => {
+                let profiles = config.profiles.len();
+                doctor::Outcome::Ok(format!("{} profile{}", profiles, if profiles == 1 { "" } else { "s" }))
+            },
+            Err(error) => doctor::Outcome::Error(error.to_string()),
+        };
+    checks.push(doctor::Check::new("configuration", outcome));
+
+    if doctor::print(&checks) {
+        Ok(())
+    }
+    else {
+        Err(io::Error::other("some checks failed"))
+    }
+}
+
+// The monitor is identified like in the configuration, so aliases work too.
+fn set_brightness(args: &Args, output: &str, value: u8) -> io::Result<()> {
+    let config = load_config(args)?;
+    let monitors = connected_edids(&backend::display().monitor_data());
+    let connector = config.alias_connectors(&monitors).remove(output)
+        .unwrap_or_else(|| output.to_string());
+    let edid = monitors.get(&connector)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no identifiable monitor connected to {}", output)))?;
+    ddc::set_brightness(edid, value)
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    log::init(args.verbose, args.log_target.unwrap_or_default(), args.log_format.unwrap_or_default());
+    if args.dry_run && !matches!(args.action, None | Some(Action::Apply { .. })) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--dry-run only works with the daemon and the apply command"));
+    }
+    DRY_RUN.store(args.dry_run, Ordering::SeqCst);
+    args.backend.install();
+
+    // Sway exposes an i3-compatible IPC socket.
+    if args.backend == Backend::Sway && env::var_os("I3SOCK").is_none() {
+        if let Some(socket) = env::var_os("SWAYSOCK") {
+            env::set_var("I3SOCK", socket);
+        }
+    }
+
+    match args.action {
+        Some(Action::Save { ref name }) => save_profile(&args, name),
+        Some(Action::Apply { ref profile }) => apply_once(&args, profile.as_deref()),
+        Some(Action::Diff { ref profile }) => print_diff(&args, profile.as_deref()),
+        Some(Action::Doctor) => run_doctor(&args),
+        Some(Action::BarStatus { format }) => print_bar_status(format),
+        Some(Action::Status { json }) => print_status(&args, json),
+        Some(Action::ListProfiles { json }) => {
+            let config = resolve_aliases(load_config(&args)?);
+            let active = choose_profile(&config, &managed_monitor_data(&config), None)?;
+            status::print_profiles(&config, &active, json)
+        },
+        Some(Action::Brightness { ref output, value }) => set_brightness(&args, output, value),
+        Some(Action::Present { toggle }) => present(&args, toggle),
+        Some(Action::Exclusive { toggle, ref output }) => exclusive(&args, toggle, output.as_deref()),
+        Some(Action::Replay { ref file }) => replay(&args, file),
+        Some(Action::Simulate { event, ref output }) => simulate(event, output),
+        Some(Action::TestXephyr { display }) => xephyr::run(display),
+        None => run_daemon(args),
+    }
+}
+
+// Requests of the clients of the daemon, from the control socket or D-Bus.
+#[derive(Deserialize, Serialize)]
+enum Request {
+    Apply {
+        profile: String,
+        // Seconds to confirm the new layout in, instead of the --confirm of the daemon.
+        #[serde(default)]
+        confirm: Option<u64>,
+    },
+    // Save the current layout as a profile.
+    Save(String),
+    // Apply the profile matching the outputs and put the workspaces back.
+    Reconcile {
+        #[serde(default)]
+        confirm: Option<u64>,
+    },
+    GetStatus,
+    // Get the status, then again after every change.
+    Watch,
+    // Pretend that an output was connected or disconnected.
+    Simulate {
+        output: String,
+        connected: bool,
+    },
+}
+
+// State shared by the event handlers of the daemon.
+#[derive(Clone)]
+struct Daemon {
+    // To reload the configuration.
+    args: Args,
+    backend: Backend,
+    config: Arc<RwLock<Config>>,
+    // The configuration with the aliases replaced by the connectors of the connected monitors.
+    resolved_config: Arc<RwLock<Config>>,
+    // Profile applied by the last reconfiguration.
+    profile: Arc<Mutex<Option<Profile>>>,
+    // Connection to the session bus, to emit the D-Bus signals.
+    bus: Arc<Mutex<Option<DBusConnection>>>,
+    workspaces: Workspaces,
+    // Placement of the workspaces last written to the disk.
+    saved_placement: Arc<Mutex<Option<state::Placement>>>,
+    debounce: Duration,
+    // Reconfiguration waiting for the burst of hotplug events to end.
+    pending: Arc<Mutex<Option<SourceId>>>,
+    // Switch to the profile of the next time of day.
+    scheduled_switch: Arc<Mutex<Option<SourceId>>>,
+    // State of the outputs right after the last reconfiguration.
+    last_state: Arc<Mutex<Option<OutputsState>>>,
+    // Fingerprints of the monitors of the connected outputs, for the workspace events.
+    fingerprints: Arc<Mutex<BTreeMap<String, Option<String>>>>,
+    confirm: Option<Duration>,
+    // Layout waiting to be kept by the user.
+    confirmation: Arc<Mutex<Option<Confirmation>>>,
+    // Workspaces waiting for the window manager to notice the new outputs.
+    pending_restore: Arc<Mutex<Option<PendingRestore>>>,
+    // Restoration waiting for the outputs changed by another tool to settle.
+    external_change: Arc<Mutex<Option<SourceId>>>,
+    // Position of the pointer on a disconnected output.
+    pointer: Arc<Mutex<Option<PointerPosition>>>,
+    // Scratchpad windows not shown since the last layout change.
+    scratchpad: Arc<Mutex<Vec<usize>>>,
+    // Workspace that had the focus before the focused one.
+    previous_workspace: Arc<Mutex<Option<String>>>,
+    // Phases of the reconfiguration in progress.
+    latency: Arc<Mutex<Option<Latency>>>,
+    // Outputs pretended connected or disconnected by `i3-aww simulate`.
+    simulated: Arc<Mutex<BTreeMap<String, bool>>>,
+    // Primary output chosen by the last reconfiguration.
+    primary: Arc<Mutex<Option<String>>>,
+    // Bars started for every output.
+    bars: Arc<Mutex<Vec<Child>>>,
+    compositor: Arc<Mutex<Option<Child>>>,
+    // Default audio sink from before switching to the speakers of a monitor.
+    previous_sink: Arc<Mutex<Option<String>>>,
+    // Nagbar showing the last error.
+    error_nagbar: Arc<Mutex<Option<Child>>>,
+    // Orientation of the device, from its accelerometer.
+    orientation: Arc<Mutex<Option<Rotation>>>,
+    locked: Arc<AtomicBool>,
+    // Whether the external monitors are in standby for inactivity.
+    idle: Arc<AtomicBool>,
+    // Reconfiguration waiting for the session to be unlocked or for the user to come back.
+    deferred: Arc<AtomicBool>,
+    main_loop: MainLoop,
+}
+
+struct PendingRestore {
+    snapshot: Snapshot,
+    // Timeout after which the workspaces are restored anyway.
+    source: SourceId,
+}
+
+#[derive(Debug, PartialEq)]
+struct OutputsState {
+    outputs: BTreeMap<String, (Option<String>, Option<String>)>,
+    // A dock can select another profile for the same monitors.
+    docks: BTreeSet<String>,
+    lid_closed: bool,
+}
+
+impl Daemon {
+    fn new(args: &Args, workspaces: Workspaces) -> io::Result<Self> {
+        let config = load_config(args)?;
+        let monitor_data = backend::display().monitor_data();
+        Ok(Self {
+            args: args.clone(),
+            backend: args.backend,
+            resolved_config: Arc::new(RwLock::new(config.clone().resolve_aliases(&connected_edids(&monitor_data)))),
+            config: Arc::new(RwLock::new(config)),
+            profile: Arc::new(Mutex::new(None)),
+            bus: Arc::new(Mutex::new(None)),
+            workspaces,
+            saved_placement: Arc::new(Mutex::new(None)),
+            debounce: Duration::from_millis(args.debounce),
+            pending: Arc::new(Mutex::new(None)),
+            scheduled_switch: Arc::new(Mutex::new(None)),
+            last_state: Arc::new(Mutex::new(None)),
+            fingerprints: Arc::new(Mutex::new(connected_outputs(&monitor_data))),
+            confirm: args.confirm.map(Duration::from_secs),
+            confirmation: Arc::new(Mutex::new(None)),
+            pending_restore: Arc::new(Mutex::new(None)),
+            external_change: Arc::new(Mutex::new(None)),
+            pointer: Arc::new(Mutex::new(None)),
+            scratchpad: Arc::new(Mutex::new(vec![])),
+            previous_workspace: Arc::new(Mutex::new(None)),
+            latency: Arc::new(Mutex::new(None)),
+            simulated: Arc::new(Mutex::new(BTreeMap::new())),
+            primary: Arc::new(Mutex::new(None)),
+            bars: Arc::new(Mutex::new(vec![])),
+            compositor: Arc::new(Mutex::new(None)),
+            previous_sink: Arc::new(Mutex::new(None)),
+            error_nagbar: Arc::new(Mutex::new(None)),
+            orientation: Arc::new(Mutex::new(None)),
+            locked: Arc::new(AtomicBool::new(false)),
+            idle: Arc::new(AtomicBool::new(false)),
+            deferred: Arc::new(AtomicBool::new(false)),
+            main_loop: MainLoop::new(None, false),
+        })
+    }
+
+    fn apply(&self) {
+        self.refresh_outputs();
+        self.reconfigure(self.confirm);
+    }
+
+    // Applies the profile matching the outputs, once they are refreshed. The new layout is reverted
+    // unless it is confirmed within `confirm`.
+    fn reconfigure(&self, confirm: Option<Duration>) {
+        let _span = tracing::info_span!("apply").entered();
+        if self.defer() {
+            tracing::debug!("Deferred until the session is unlocked or the user is back");
+            self.latency.lock().expect("latency lock").take();
+            return;
+        }
+        self.latency.lock().expect("latency lock").get_or_insert_with(Latency::new).start_apply();
+        let previous = self.previous_layout(confirm);
+
+        let monitor_data = self.monitor_data();
+        let connected = connected_outputs(&monitor_data);
+        tracing::debug!(outputs = %connected.keys().cloned().collect::<Vec<_>>().join(","), "Connected outputs: {:?}", connected);
+        if record::enabled() {
+            record::write(record::Event::Outputs {
+                monitors: monitor_data.clone(),
+            });
+            record::write(record::Event::Workspaces {
+                workspaces: self.workspaces.iter().map(|workspace| workspace.clone()).collect(),
+            });
+        }
+        // To know the newest external output, for the presentation mode.
+        if let Err(error) = state::connection_times(&connected.keys().cloned().collect()) {
+            tracing::error!("Cannot save the connection times: {}", error);
+        }
+        let user_primary = self.user_primary();
+        let (profile, post_apply_delay) = {
+            let config = self.config();
+            let conditions = Conditions {
+                orientation: *self.orientation.lock().expect("orientation lock"),
+                user_primary,
+                ..conditions(&config)
+            };
+            let profile = selection::choose_profile(&config, &monitor_data, None, &conditions)
+                .expect("profile for the connected outputs");
+            let post_apply_delay = config.post_apply_delay(&profile);
+            (profile, post_apply_delay)
+        };
+        tracing::debug!(profile = %profile.name, "Chose profile {} with primary output {:?}", profile.name, profile.primary);
+        let applied = self.apply_profile(&profile, &monitor_data, post_apply_delay);
+        *self.last_state.lock().expect("state lock") = Some(self.state());
+        *self.primary.lock().expect("primary lock") = current_primary(self.backend);
+
+        if let (Some(timeout), Some(previous), true) = (confirm, previous, applied) {
+            self.ask_confirmation(timeout, previous);
+        }
+    }
+
+    // Applies the profile with this name, even if it does not match the connected outputs.
+    fn apply_named(&self, name: &str, confirm: Option<Duration>) -> io::Result<()> {
+        self.refresh_outputs();
+        let previous = self.previous_layout(confirm);
+        let monitor_data = self.monitor_data();
+        let (profile, post_apply_delay) = {
+            let config = self.config();
+            let profile = choose_profile(&config, &monitor_data, Some(name))?;
+            let post_apply_delay = config.post_apply_delay(&profile);
+            (profile, post_apply_delay)
+        };
+        let applied = self.apply_profile(&profile, &monitor_data, post_apply_delay);
+        *self.last_state.lock().expect("state lock") = Some(self.state());
+        *self.primary.lock().expect("primary lock") = current_primary(self.backend);
+        if !applied {
+            return Err(io::Error::other(format!("cannot apply profile {}", name)));
+        }
+        if let (Some(timeout), Some(previous)) = (confirm, previous) {
+            self.ask_confirmation(timeout, previous);
+        }
+        Ok(())
+    }
+
+    // The layout to go back to when the new one is not confirmed. A newer layout supersedes the one
+    // waiting for a confirmation.
+    fn previous_layout(&self, confirm: Option<Duration>) -> Option<Profile> {
+        if let Some(confirmation) = self.confirmation.lock().expect("confirmation lock").take() {
+            confirmation.cancel();
+        }
+        confirm.and_then(|_| {
+            backend::display().current_profile("previous")
+                .map_err(|error| tracing::error!("Cannot get the current layout: {}", error))
+                .ok()
+        })
+    }
+
+    fn on_request(&self, request: Request) -> io::Result<Option<String>> {
+        match request {
+            Request::Apply { profile, confirm } => self.apply_named(&profile, confirm.map(Duration::from_secs).or(self.confirm))?,
+            Request::Save(name) => {
+                let path = save_current_layout(&self.args, &name)?;
+                self.reload_config()?;
+                return Ok(Some(path.display().to_string()));
+            },
+            Request::Reconcile { confirm } => {
+                self.refresh_outputs();
+                self.reconfigure(confirm.map(Duration::from_secs).or(self.confirm));
+            },
+            Request::GetStatus | Request::Watch => return self.status_json().map(Some),
+            Request::Simulate { output, connected } => self.simulate(output, connected),
+        }
+        Ok(None)
+    }
+
+    // Ticks sent with `i3-msg -t send_tick i3-aww:reapply` or `i3-aww:profile:NAME`, e.g. from the
+    // key bindings. The ticks of the other programs are ignored.
+    fn on_tick(&self, payload: &str) {
+        let command =
+            match payload.strip_prefix(TICK_PREFIX) {
+                Some(command) => command,
+                None => return,
+            };
+        let request =
+            if command == "reapply" {
+                Request::Reconcile { confirm: None }
+            }
+            else if let Some(name) = command.strip_prefix("profile:") {
+                Request::Apply { profile: name.to_string(), confirm: None }
+            }
+            else {
+                tracing::warn!("Unknown tick command {}", command);
+                return;
+            };
+        if let Err(error) = self.on_request(request) {
+            tracing::error!("Cannot handle the tick {}: {}", payload, error);
+        }
+    }
+
+    // The status with the workspace history of the daemon, and the profile it applied.
+    fn status_json(&self) -> io::Result<String> {
+        let monitor_data = backend::display().monitor_data();
+        let profile = self.profile.lock().expect("profile lock").clone();
+        let profile =
+            match profile {
+                Some(profile) => profile,
+                None => choose_profile(&self.config(), &self.monitor_data(), None)?,
+            };
+        self.adjust_workspaces();
+        let mut workspaces: Vec<_> = self.workspaces.iter()
+            .map(|workspace| workspace.clone())
+            .collect();
+        workspaces.sort_by_key(workspace_order);
+        Ok(serde_json::to_string(&status::Status::new(&monitor_data, &profile, workspaces))?)
+    }
+
+    // A pretend event undoes the opposite one, otherwise the output keeps the pretended state until
+    // the daemon exits.
+    fn simulate(&self, output: String, connected: bool) {
+        {
+            let mut simulated = self.simulated.lock().expect("simulated lock");
+            if simulated.get(&output) == Some(&!connected) {
+                simulated.remove(&output);
+            }
+            else {
+                simulated.insert(output, connected);
+            }
+        }
+        self.on_hotplug("simulate");
+    }
+
+    fn set_profile(&self, profile: &Profile) {
+        let previous = self.profile.lock().expect("profile lock").replace(profile.clone());
+        // The bars also show the number of outputs, which can change with the same profile.
+        if control::has_watchers() {
+            match self.status_json() {
+                Ok(status) => control::notify(status),
+                Err(error) => tracing::error!("Cannot get the status for the watchers: {}", error),
+            }
+        }
+        if previous.is_some_and(|previous| previous.name == profile.name) {
+            return;
+        }
+        if let Some(ref connection) = *self.bus.lock().expect("bus lock") {
+            if let Err(error) = dbus::emit_profile_changed(connection, &profile.name) {
+                tracing::error!("Cannot emit the profile change: {}", error);
+            }
+        }
+    }
+
+    // The primary output when the user changed it since the last reconfiguration: it is kept
+    // unless the profile names another one.
+    fn user_primary(&self) -> Option<String> {
+        let current = current_primary(self.backend)?;
+        let applied = self.primary.lock().expect("primary lock");
+        applied.as_ref().is_some_and(|applied| applied != &current).then_some(current)
+    }
+
+    fn ask_confirmation(&self, timeout: Duration, previous: Profile) {
+        let daemon = self.clone();
+        let on_answer = move |kept: bool| {
+            if !kept {
+                daemon.revert(&previous);
+            }
+        };
+        match confirm::ask(self.backend.nagbar(), timeout, on_answer) {
+            Ok(confirmation) => *self.confirmation.lock().expect("confirmation lock") = Some(confirmation),
+            Err(error) => tracing::error!("Cannot ask to confirm the new layout: {}", error),
+        }
+    }
+
+    fn revert(&self, previous: &Profile) {
+        let monitor_data = self.monitor_data();
+        let post_apply_delay = self.config.read().expect("config lock").post_apply_delay(previous);
+        self.apply_profile(previous, &monitor_data, post_apply_delay);
+        *self.last_state.lock().expect("state lock") = Some(self.state());
+        *self.primary.lock().expect("primary lock") = current_primary(self.backend);
+    }
+
+    // Returns whether the profile was applied.
+    fn apply_profile(&self, profile: &Profile, monitor_data: &[MonitorData], post_apply_delay: Duration) -> bool {
+        let snapshot = Snapshot {
+            previous_workspace: self.previous_workspace.lock().expect("previous workspace lock").clone(),
+            ..snapshot_workspaces()
+        };
+        self.remember_pointer(monitor_data);
+        let config = self.config();
+        let geometries = output_geometries(self.backend);
+        let start = Instant::now();
+        let result = apply_with_hooks(self.backend, &config, profile, monitor_data);
+        metrics::reconfiguration(start.elapsed(), result.is_ok());
+        if let Some(ref mut latency) = *self.latency.lock().expect("latency lock") {
+            latency.applied();
+        }
+        match result {
+            Ok(()) if dry_run() => tracing::info!(profile = %profile.name, "Would apply profile {}", profile.name),
+            Ok(()) => {
+                self.clear_error();
+                tracing::info!(profile = %profile.name, "Applied profile {}", profile.name);
+                self.set_profile(profile);
+                if let Some(ref bar) = config.bar {
+                    let outputs = enabled_outputs(profile, monitor_data);
+                    bars::restart(bar, &outputs, &mut self.bars.lock().expect("bars lock"));
+                }
+                if config.audio {
+                    self.switch_audio(profile, monitor_data);
+                }
+                if let Some(ref compositor) = config.compositor {
+                    if output_geometries(self.backend) != geometries {
+                        compositor::restart(compositor, &mut self.compositor.lock().expect("compositor lock"));
+                    }
+                }
+                if config.notifications {
+                    let enabled = profile.outputs.values()
+                        .filter(|output_config| output_config.enabled)
+                        .count();
+                    let outputs = if enabled == 1 { "output" } else { "outputs" };
+                    notify::send(Urgency::Normal, "Display configuration changed",
+                        &format!("Applied profile '{}' \u{2014} {} {}", profile.name, enabled, outputs));
+                }
+            },
+            Err(ref error) => {
+                tracing::error!(profile = %profile.name, "Could not set the monitor config for profile {}: {}", profile.name, error);
+                self.report_error(&config, &format!("Cannot apply profile {}: {}", profile.name, error));
+                if config.notifications {
+                    notify::send(Urgency::Critical, &format!("Failed to apply profile '{}'", profile.name), &error.to_string());
+                }
+            },
+        }
+        self.restore_workspaces_later(snapshot, post_apply_delay);
+        result.is_ok()
+    }
+
+    // The workspaces are moved once the window manager reports the new outputs, so that they do
+    // not land on the wrong output. The timeout is a fallback for when no event comes, e.g. when
+    // nothing changed.
+    fn restore_workspaces_later(&self, snapshot: Snapshot, timeout: Duration) {
+        // This restoration supersedes the one for the changes made by another tool.
+        let external_change = self.external_change.lock().expect("external change lock").take();
+        if let Some(source) = external_change {
+            source.remove();
+        }
+
+        let daemon = self.clone();
+        let source = timeout_add_once(timeout, move || {
+            let pending = daemon.pending_restore.lock().expect("restore lock").take();
+            if let Some(pending) = pending {
+                daemon.restore(&pending.snapshot);
+            }
+        });
+
+        let previous = self.pending_restore.lock().expect("restore lock").replace(PendingRestore {
+            snapshot,
+            source,
+        });
+        if let Some(previous) = previous {
+            previous.source.remove();
+        }
+    }
+
+    fn on_output_event(&self) {
+        self.refresh_outputs();
+        let pending = self.pending_restore.lock().expect("restore lock").take();
+        if let Some(pending) = pending {
+            pending.source.remove();
+            self.restore(&pending.snapshot);
+            return;
+        }
+
+        // The outputs were changed by another tool (e.g. arandr): only put the workspaces back,
+        // once the burst of events ends, without applying a profile over the new layout.
+        let mut external_change = self.external_change.lock().expect("external change lock");
+        if let Some(source) = external_change.take() {
+            source.remove();
+        }
+        let daemon = self.clone();
+        *external_change = Some(timeout_add_once(self.debounce, move || {
+            daemon.external_change.lock().expect("external change lock").take();
+            daemon.restore(&snapshot_workspaces());
+        }));
+    }
+
+    fn restore(&self, snapshot: &Snapshot) {
+        if self.defer() {
+            return;
+        }
+        if let Some(ref mut latency) = *self.latency.lock().expect("latency lock") {
+            latency.start_restore();
+        }
+        let config = self.config();
+        if let Err(error) = restore_workspaces(self.backend, &self.workspaces, snapshot, &config) {
+            tracing::error!("Cannot restore the workspaces: {}", error);
+            self.report_error(&config, &format!("Cannot restore the workspaces: {}", error));
+        }
+        self.save_state();
+
+        if config.center_scratchpad {
+            *self.scratchpad.lock().expect("scratchpad lock") = backend::wm()
+                .map(|mut wm| containers(&mut *wm))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|container| container.workspace == SCRATCHPAD_WORKSPACE && container.floating)
+                .map(|container| container.id)
+                .collect();
+        }
+
+        // Last, since focusing a workspace on another output moves the pointer.
+        let position = self.pointer.lock().expect("pointer lock").take();
+        if let Some(position) = position {
+            match pointer::restore(&position) {
+                Ok(true) => (),
+                // The output is still not back.
+                Ok(false) => *self.pointer.lock().expect("pointer lock") = Some(position),
+                Err(error) => tracing::error!("Cannot restore the pointer position: {}", error),
+            }
+        }
+
+        let mut latency = self.latency.lock().expect("latency lock");
+        if latency.as_ref().is_some_and(Latency::is_restoring) {
+            if let Some(latency) = latency.take() {
+                latency.log();
+            }
+        }
+    }
+
+    // A scratchpad window keeps its position on the previous layout, which can be off-screen or on
+    // another output: center it on the primary output the first time it is shown.
+    fn on_window_moved(&self, id: usize) {
+        {
+            let mut scratchpad = self.scratchpad.lock().expect("scratchpad lock");
+            match scratchpad.iter().position(|&scratchpad_id| scratchpad_id == id) {
+                Some(index) => scratchpad.swap_remove(index),
+                None => return,
+            };
+        }
+
+        let result = backend::wm().and_then(|mut wm| {
+            let container = containers(&mut *wm).into_iter()
+                .find(|container| container.id == id && container.workspace != SCRATCHPAD_WORKSPACE);
+            let primary = wm.outputs()?.into_iter()
+                .find(|output| output.active && output.primary);
+            if let (Some(container), Some(primary)) = (container, primary) {
+                let x = primary.rect.x + (primary.rect.width - container.rect.width) / 2;
+                let y = primary.rect.y + (primary.rect.height - container.rect.height) / 2;
+                let command = format!("[con_id={}] move absolute position {} px {} px", id, x, y);
+                if dry_run() {
+                    tracing::info!("Would run {}", command);
+                }
+                else {
+                    wm.run_commands(&[command])?;
+                }
+            }
+            Ok(())
+        });
+        if let Err(error) = result {
+            tracing::error!("Cannot center scratchpad window: {}", error);
+        }
+    }
+
+    fn reload_config(&self) -> io::Result<()> {
+        *self.config.write().expect("config lock") = load_config(&self.args)?;
+        self.refresh_outputs();
+        Ok(())
+    }
+
+    fn config(&self) -> Config {
+        self.resolved_config.read().expect("config lock").clone()
+    }
+
+    fn monitor_data(&self) -> Vec<MonitorData> {
+        let mut monitor_data = managed_monitor_data(&self.config());
+        for (name, &connected) in self.simulated.lock().expect("simulated lock").iter() {
+            match monitor_data.iter_mut().find(|monitor| &monitor.name == name) {
+                Some(monitor) => monitor.connected = connected,
+                None if connected => monitor_data.push(MonitorData {
+                    name: name.clone(),
+                    connected,
+                    edid: None,
+                    mode: None,
+                }),
+                None => (),
+            }
+        }
+        monitor_data
+    }
+
+    fn state(&self) -> OutputsState {
+        let config = self.config();
+        OutputsState {
+            outputs: outputs_state(&self.monitor_data()),
+            docks: config::attached_docks(&config),
+            lid_closed: config.lid_switch && lid::closed(),
+        }
+    }
+
+    // Shows the error in a nagbar, replacing the previous one, with a button to apply the layout
+    // again and one to open the log in a terminal.
+    fn report_error(&self, config: &Config, message: &str) {
+        if !config.error_nagbar {
+            return;
+        }
+        self.clear_error();
+        let retry = format!("kill -{} {}", RETRY_SIGNAL, process::id());
+        let nagbar = Command::new(self.backend.nagbar())
+            .args(["-t", "error", "-m", &format!("i3-aww: {}", message), "-B", "Retry", &retry, "-b", "Open the log", SHOW_LOG_COMMAND])
+            .spawn();
+        let nagbar =
+            match nagbar {
+                Ok(nagbar) => nagbar,
+                Err(error) => {
+                    tracing::error!("Cannot show the error: {}", error);
+                    return;
+                },
+            };
+
+        // Reaped as soon as the user closes it, and forgotten unless another one replaced it.
+        let pid = nagbar.id();
+        *self.error_nagbar.lock().expect("error nagbar lock") = Some(nagbar);
+        child_watch_add_local(Pid(pid as i32), {
+            let error_nagbar = self.error_nagbar.clone();
+            move |_pid, _status| {
+                let mut error_nagbar = error_nagbar.lock().expect("error nagbar lock");
+                if error_nagbar.as_ref().is_some_and(|nagbar| nagbar.id() == pid) {
+                    *error_nagbar = None;
+                }
+            }
+        });
+    }
+
+    fn switch_audio(&self, profile: &Profile, monitor_data: &[MonitorData]) {
+        let enabled = enabled_outputs(profile, monitor_data);
+        let mut monitors: Vec<_> = monitor_data.iter()
+            .filter(|monitor| enabled.contains(&monitor.name))
+            .collect();
+        monitors.sort_by_key(|monitor| profile.primary.as_ref() != Some(&monitor.name));
+        let monitor_sink = monitors.iter()
+            .filter_map(|monitor| monitor.edid.as_ref()?.model.as_deref())
+            .find_map(|model| audio::monitor_sink(model).unwrap_or_else(|error| {
+                tracing::error!("Cannot list the audio sinks: {}", error);
+                None
+            }));
+
+        let mut previous_sink = self.previous_sink.lock().expect("sink lock");
+        let result =
+            match monitor_sink {
+                Some(sink) => {
+                    if previous_sink.is_none() {
+                        *previous_sink = audio::default_sink()
+                            .map_err(|error| tracing::error!("Cannot get the default audio sink: {}", error))
+                            .ok();
+                    }
+                    audio::set_default_sink(&sink)
+                },
+                None => match previous_sink.take() {
+                    Some(sink) => audio::set_default_sink(&sink),
+                    None => Ok(()),
+                },
+            };
+        if let Err(error) = result {
+            tracing::error!("Cannot set the default audio sink: {}", error);
+        }
+    }
+
+    // The nagbar is reaped by the child watch added by report_error.
+    fn clear_error(&self) {
+        if let Some(mut nagbar) = self.error_nagbar.lock().expect("error nagbar lock").take() {
+            if let Err(error) = nagbar.kill() {
+                tracing::error!("Cannot close the nagbar: {}", error);
+            }
+        }
+    }
+
+    // Whether the session is locked or the monitors are in standby, and the changes wait for the
+    // user to come back. The whole layout is applied then, which also restores the workspaces.
+    // Monitors in standby can disappear, which must not move their workspaces.
+    fn defer(&self) -> bool {
+        let defer = (self.locked.load(Ordering::SeqCst) && self.config().defer_while_locked)
+            || self.idle.load(Ordering::SeqCst);
+        if defer {
+            self.deferred.store(true, Ordering::SeqCst);
+        }
+        defer
+    }
+
+    fn on_rotation(&self, rotation: Rotation) {
+        let previous = self.orientation.lock().expect("orientation lock").replace(rotation);
+        // The first orientation is the one of the device when the daemon starts.
+        if previous.is_some() {
+            self.apply();
+        }
+    }
+
+    fn on_logind_event(&self, event: logind::Event) {
+        match event {
+            logind::Event::Resume => self.on_resume(),
+            logind::Event::Lock => self.locked.store(true, Ordering::SeqCst),
+            logind::Event::Unlock => {
+                self.locked.store(false, Ordering::SeqCst);
+                if self.deferred.swap(false, Ordering::SeqCst) {
+                    self.apply();
+                }
+            },
+        }
+    }
+
+    fn on_idle_time(&self, idle_time: Duration) {
+        let minutes =
+            match self.config().idle_standby {
+                Some(minutes) => minutes,
+                None => return,
+            };
+        let idle = idle_time >= Duration::from_secs(minutes * 60);
+        if self.idle.swap(idle, Ordering::SeqCst) == idle {
+            return;
+        }
+
+        for monitor in self.monitor_data() {
+            let edid =
+                match monitor.edid {
+                    Some(ref edid) if monitor.connected && !is_internal(&monitor.name) => edid,
+                    _ => continue,
+                };
+            let result = if idle { ddc::standby(edid) } else { ddc::wake_up(edid) };
+            if let Err(error) = result {
+                tracing::error!(output = %monitor.name, "Cannot change the power mode of {}: {}", monitor.name, error);
+            }
+        }
+        if !idle && self.deferred.swap(false, Ordering::SeqCst) {
+            self.apply();
+        }
+    }
+
+    // The monitors are only queried when the outputs or the configuration change, since the
+    // workspace events and the requests are frequent.
+    fn refresh_outputs(&self) {
+        let monitor_data = backend::display().monitor_data();
+        *self.fingerprints.lock().expect("fingerprints lock") = connected_outputs(&monitor_data);
+        let config = self.config.read().expect("config lock").clone();
+        *self.resolved_config.write().expect("config lock") = config.resolve_aliases(&connected_edids(&monitor_data));
+    }
+
+    fn adjust_workspaces(&self) {
+        let fingerprints = self.fingerprints.lock().expect("fingerprints lock").clone();
+        track_workspaces(&self.workspaces, &fingerprints);
+    }
+
+    // Most workspace events only change the focus: the history is only written when a workspace
+    // moved, appeared or disappeared.
+    fn save_state(&self) {
+        // The history was updated for workspaces that did not move.
+        if dry_run() {
+            return;
+        }
+        let placement = state::placement(&self.workspaces);
+        let mut saved_placement = self.saved_placement.lock().expect("saved placement lock");
+        if saved_placement.as_ref() == Some(&placement) {
+            return;
+        }
+        match state::save(&self.workspaces) {
+            Ok(()) => *saved_placement = Some(placement),
+            Err(error) => tracing::error!("Cannot save the workspaces: {}", error),
+        }
+    }
+
+    // Called before the reconfiguration, while the disconnected outputs still have their place on
+    // the screen.
+    fn remember_pointer(&self, monitor_data: &[MonitorData]) {
+        if self.backend != Backend::I3 || !self.config.read().expect("config lock").restore_pointer {
+            return;
+        }
+        match pointer::position() {
+            Ok(Some(position)) => {
+                let connected = monitor_data.iter()
+                    .any(|monitor| monitor.name == position.output && monitor.connected);
+                if !connected {
+                    *self.pointer.lock().expect("pointer lock") = Some(position);
+                }
+            },
+            Ok(None) => (),
+            Err(error) => tracing::error!("Cannot get the pointer position: {}", error),
+        }
+    }
+
+    // Some events (e.g. pressing the button of the active input on a KVM switch) leave the
+    // outputs as they were: reconfiguring would only shuffle the workspaces around.
+    fn apply_if_changed(&self) {
+        let _span = tracing::info_span!("hotplug").entered();
+        self.refresh_outputs();
+        let state = self.state();
+        if self.last_state.lock().expect("state lock").as_ref() == Some(&state) {
+            tracing::debug!("The outputs did not change");
+            self.latency.lock().expect("latency lock").take();
+            return;
+        }
+        tracing::debug!("The outputs changed: {:?}", state);
+        self.reconfigure(self.confirm);
+    }
+
+    // A single plug can emit several events in quick succession: restart the timer on every
+    // event so that only one reconfiguration happens.
+    fn on_hotplug(&self, trigger: &str) {
+        record::write(record::Event::Hotplug {
+            trigger: trigger.to_string(),
+        });
+        metrics::hotplug_event(trigger);
+        self.latency.lock().expect("latency lock").get_or_insert_with(Latency::new);
+        tracing::debug!(trigger = %trigger, "Hotplug event from {}, waiting {:?} for the next one", trigger, self.debounce);
+        let mut pending = self.pending.lock().expect("pending lock");
+        if let Some(source) = pending.take() {
+            source.remove();
+        }
+
+        let daemon = self.clone();
+        *pending = Some(timeout_add_once(self.debounce, move || {
+            daemon.pending.lock().expect("pending lock").take();
+            daemon.refresh_outputs();
+            daemon.schedule_apply();
+        }));
+    }
+
+    // The profiles can only match at some times of the day: apply the layout again when one of
+    // these times is reached.
+    fn schedule_switch(&self) {
+        let mut scheduled_switch = self.scheduled_switch.lock().expect("switch lock");
+        if let Some(source) = scheduled_switch.take() {
+            source.remove();
+        }
+
+        if let Some(delay) = self.config().next_switch(TimeOfDay::now()) {
+            let daemon = self.clone();
+            *scheduled_switch = Some(timeout_add_once(delay, move || {
+                daemon.scheduled_switch.lock().expect("switch lock").take();
+                daemon.apply();
+                daemon.schedule_switch();
+            }));
+        }
+    }
+
+    // Let the outputs settle before reconfiguring them. The delay is the one of the profile
+    // matching the outputs detected so far.
+    fn schedule_apply(&self) {
+        let daemon = self.clone();
+        let delay = self.pre_apply_delay();
+        tracing::debug!("Waiting {:?} for the outputs to settle", delay);
+        timeout_add_once(delay, move || {
+            daemon.apply_if_changed();
+        });
+    }
+
+    fn pre_apply_delay(&self) -> Duration {
+        let config = self.config();
+        match choose_profile(&config, &self.monitor_data(), None) {
+            Ok(profile) => config.pre_apply_delay(&profile),
+            Err(_) => Duration::ZERO,
+        }
+    }
+
+    // The outputs are reconfigured and the workspaces restored even if nothing seems to have
+    // changed, since the monitors can be woken up in a different state.
+    fn on_resume(&self) {
+        let daemon = self.clone();
+        timeout_add_once(self.pre_apply_delay(), move || {
+            daemon.apply();
+            // The timers do not run while the machine is asleep.
+            daemon.schedule_switch();
+        });
+    }
+}
+
+// The events are read from the main loop when the socket becomes readable, and the watch is
+// removed when the connection is lost, so that nothing spins on a dead socket. It is replaced by a
+// new connection as soon as i3 is back.
+fn watch_i3_events(daemon: &Daemon) -> io::Result<()> {
+    let mut stream = subscribe(&[Subscribe::Workspace, Subscribe::Output, Subscribe::Window, Subscribe::Shutdown, Subscribe::Tick])?;
+    let daemon = daemon.clone();
+    let fd = stream.as_raw_fd();
+    unix_fd_add_local(fd, IOCondition::IN | IOCondition::HUP | IOCondition::ERR, move |_fd, condition| {
+        if !condition.contains(IOCondition::IN) {
+            tracing::error!("Lost the connection to i3");
+            reconnect_i3_events(&daemon, RECONNECT_MIN_DELAY);
+            return Continue(false);
+        }
+
+        let event = stream.decode_msg()
+            .and_then(|(event_type, payload)| i3_ipc::decode_event(event_type, payload));
+        match event {
+            Ok(Event::Workspace(data)) => {
+                if data.change == WorkspaceChange::Focus {
+                    if let Some(name) = data.old.and_then(|old| old.name) {
+                        *daemon.previous_workspace.lock().expect("previous workspace lock") = Some(name);
+                    }
+                }
+                daemon.adjust_workspaces();
+                daemon.save_state();
+            },
+            Ok(Event::Output(_)) => daemon.on_output_event(),
+            Ok(Event::Window(data)) if data.change == WindowChange::Move => daemon.on_window_moved(data.container.id),
+            Ok(Event::Shutdown(data)) => {
+                match data.change {
+                    // Wait for the new socket of the restarted i3.
+                    ShutdownChange::Restart => reconnect_i3_events(&daemon, RECONNECT_MIN_DELAY),
+                    // Nothing is left to manage without i3.
+                    ShutdownChange::Exit => {
+                        daemon.save_state();
+                        daemon.main_loop.quit();
+                    },
+                }
+                return Continue(false);
+            },
+            // The first tick only confirms the subscription.
+            Ok(Event::Tick(data)) if !data.first => daemon.on_tick(&data.payload),
+            Ok(Event::Window(_) | Event::Mode(_) | Event::BarConfig(_) | Event::Binding(_) | Event::Tick(_)) => (),
+            Err(error) => {
+                tracing::error!("Lost the connection to i3: {}", error);
+                reconnect_i3_events(&daemon, RECONNECT_MIN_DELAY);
+                return Continue(false);
+            },
+        }
+        Continue(true)
+    });
+
+    Ok(())
+}
+
+// Retries with an exponential backoff, since i3 can take a while to restart.
+fn reconnect_i3_events(daemon: &Daemon, delay: Duration) {
+    let daemon = daemon.clone();
+    timeout_add_local_once(delay, move || {
+        match watch_i3_events(&daemon) {
+            Ok(()) => {
+                // The workspaces may have changed while disconnected.
+                daemon.adjust_workspaces();
+                daemon.save_state();
+            },
+            Err(error) => {
+                tracing::error!("Cannot reconnect to i3: {}", error);
+                reconnect_i3_events(&daemon, (delay * 2).min(RECONNECT_MAX_DELAY));
+            },
+        }
+    });
+}
+
+fn run_daemon(args: Args) -> io::Result<()> {
+    let _lock = lock::acquire(args.replace)?;
+    if let Some(ref path) = args.record {
+        record::start(path)?;
+    }
+
+    let trigger = args.trigger;
+    let poll_interval = args.poll.map(Duration::from_millis);
+    let workspaces = Arc::new(DashMap::new());
+    // The history from the previous run is reconciled with the current workspaces like after any
+    // other change.
+    if let Err(error) = state::load(&workspaces) {
+        tracing::error!("Cannot load the saved workspaces: {}", error);
+    }
+    adjust_workspaces(&workspaces);
+
+    let daemon = Daemon::new(&args, workspaces)?;
+    *daemon.last_state.lock().expect("state lock") = Some(daemon.state());
+    *daemon.primary.lock().expect("primary lock") = current_primary(daemon.backend);
+
+    // The events are watched once i3 is reachable.
+    if let Err(error) = watch_i3_events(&daemon) {
+        tracing::error!("Cannot connect to i3: {}", error);
+        reconnect_i3_events(&daemon, RECONNECT_MIN_DELAY);
+    }
+
+    let config = daemon.config();
+    if let Some(ref bar) = config.bar {
+        bars::restart(bar, &active_output_names(), &mut daemon.bars.lock().expect("bars lock"));
+    }
+    if let Some(ref compositor) = config.compositor {
+        compositor::restart(compositor, &mut daemon.compositor.lock().expect("compositor lock"));
+    }
+
+    // Reload the configuration and apply the new layout without losing the workspace history.
+    unix_signal_add(libc::SIGHUP, {
+        let daemon = daemon.clone();
+        move || {
+            match daemon.reload_config() {
+                Ok(()) => {
+                    daemon.reconfigure(daemon.confirm);
+                    daemon.schedule_switch();
+                },
+                Err(error) => tracing::error!("Cannot reload the configuration: {}", error),
+            }
+            Continue(true)
+        }
+    });
+
+    let control_socket = control::listen({
+        let daemon = daemon.clone();
+        move |request| daemon.on_request(request)
+    });
+    if let Err(error) = control_socket {
+        tracing::error!("Cannot listen on the control socket: {}", error);
+    }
+
+    if let Some(address) = args.metrics_address.as_ref().or(config.metrics_address.as_ref()) {
+        if let Err(error) = metrics::serve(address) {
+            tracing::error!("Cannot serve the metrics on {}: {}", address, error);
+        }
+    }
+
+    let _bus_name = dbus::serve(
+        {
+            let daemon = daemon.clone();
+            move |request| daemon.on_request(request)
+        },
+        {
+            let daemon = daemon.clone();
+            move |connection| *daemon.bus.lock().expect("bus lock") = Some(connection)
+        },
+    );
+
+    // The workspaces of the internal panel go to the other outputs when the lid is closed, and come
+    // back when it is opened.
+    if config.lid_switch {
+        lid::watch({
+            let daemon = daemon.clone();
+            move |_closed| daemon.on_hotplug("lid")
+        });
+    }
+
+    let logind_watch = logind::watch({
+        let daemon = daemon.clone();
+        move |event| daemon.on_logind_event(event)
+    });
+    if let Err(error) = logind_watch {
+        tracing::error!("Cannot watch the logind signals: {}", error);
+    }
+
+    unix_signal_add(RETRY_SIGNAL, {
+        let daemon = daemon.clone();
+        move || {
+            daemon.apply();
+            Continue(true)
+        }
+    });
+
+    // Stopped by systemd, by another instance started with --replace or with Ctrl-C: the workspace
+    // history is saved and the bars and the compositor are stopped before exiting.
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        unix_signal_add(signal, {
+            let daemon = daemon.clone();
+            move || {
+                if let Err(error) = systemd::notify("STOPPING=1") {
+                    tracing::error!("Cannot notify systemd: {}", error);
+                }
+                daemon.save_state();
+                bars::stop(&mut daemon.bars.lock().expect("bars lock"));
+                compositor::stop(&mut daemon.compositor.lock().expect("compositor lock"));
+                daemon.main_loop.quit();
+                Continue(false)
+            }
+        });
+    }
+
+    if config.idle_standby.is_some() && daemon.backend == Backend::Sway {
+        tracing::warn!("The idle time is only known with i3, idle_standby is ignored");
+    }
+    else if config.idle_standby.is_some() {
+        let idle_watch = idle::watch(IDLE_CHECK_INTERVAL, {
+            let daemon = daemon.clone();
+            move |idle_time| daemon.on_idle_time(idle_time)
+        });
+        if let Err(error) = idle_watch {
+            tracing::error!("Cannot watch the idle time: {}", error);
+        }
+    }
+
+    if config.profiles.iter().any(|profile| !profile.battery.is_empty()) {
+        let power_watch = power::watch({
+            let daemon = daemon.clone();
+            move |_on_battery| daemon.apply()
+        });
+        if let Err(error) = power_watch {
+            tracing::error!("Cannot watch the power source: {}", error);
+        }
+    }
+
+    daemon.schedule_switch();
+
+    if config.auto_rotate {
+        let sensor_watch = sensor::watch({
+            let daemon = daemon.clone();
+            move |rotation| daemon.on_rotation(rotation)
+        });
+        if let Err(error) = sensor_watch {
+            tracing::error!("Cannot watch the orientation of the device: {}", error);
+        }
+    }
+
+    // Keep the udev client alive for as long as the main loop runs.
+    let _client =
+        if let Some(interval) = poll_interval {
+            // Last resort for when no hotplug event is available.
+            let daemon = daemon.clone();
+            let mut last_outputs = connected_outputs(&daemon.monitor_data());
+            timeout_add_local(interval, move || {
+                let outputs = connected_outputs(&daemon.monitor_data());
+                if outputs != last_outputs {
+                    last_outputs = outputs;
+                    daemon.on_hotplug("poll");
+                }
+                Continue(true)
+            });
+            None
+        }
+        else {
+            match trigger {
+                Trigger::Udev => {
+                    let client = Client::new(&[]);
+                    let daemon = daemon.clone();
+                    client.connect_uevent(move |_client, action, device| {
+                        record::write(record::Event::Uevent {
+                            action: action.to_string(),
+                            subsystem: device.subsystem().map(|string| string.to_string()),
+                            devtype: device.devtype().map(|string| string.to_string()),
+                            name: device.name().map(|string| string.to_string()),
+                        });
+                        // Docks are USB devices.
+                        let devtype = device.devtype().map(|string| string.to_string());
+                        if matches!(devtype.as_deref(), Some("drm_minor" | "usb_device")) {
+                            daemon.on_hotplug("udev");
+                        }
+                    });
+                    Some(client)
+                },
+                Trigger::Randr => {
+                    let daemon = daemon.clone();
+                    xevents::watch(move || daemon.on_hotplug("randr"))?;
+                    None
+                },
+            }
+        };
+
+    // Every event source is set up.
+    if let Err(error) = systemd::notify("READY=1") {
+        tracing::error!("Cannot notify systemd: {}", error);
+    }
+    systemd::start_watchdog();
 
-    let main_loop = MainLoop::new(None, false);
-    main_loop.run();
+    daemon.main_loop.run();
 
     Ok(())
 }
@@ -0,0 +1,159 @@
+// Prometheus metrics of the daemon, served over HTTP when metrics_address is set, to follow the
+// hotplug events and the reconfigurations of many docked workstations. The requests are answered
+// on their own thread, so that a slow client cannot block the main loop.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+// Upper bounds of the buckets of the apply duration, in seconds.
+const APPLY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics::new());
+
+struct Metrics {
+    // Per trigger.
+    hotplug_events: BTreeMap<String, u64>,
+    reconfigurations: u64,
+    failures: u64,
+    // Cumulative, like the Prometheus buckets.
+    apply_buckets: [u64; APPLY_BUCKETS.len()],
+    apply_count: u64,
+    apply_sum: f64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            hotplug_events: BTreeMap::new(),
+            reconfigurations: 0,
+            failures: 0,
+            apply_buckets: [0; APPLY_BUCKETS.len()],
+            apply_count: 0,
+            apply_sum: 0.0,
+        }
+    }
+
+    fn add_reconfiguration(&mut self, duration: Duration, success: bool) {
+        self.reconfigurations += 1;
+        if !success {
+            self.failures += 1;
+        }
+        let seconds = duration.as_secs_f64();
+        for (bucket, &bound) in self.apply_buckets.iter_mut().zip(&APPLY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.apply_count += 1;
+        self.apply_sum += seconds;
+    }
+}
+
+pub fn hotplug_event(trigger: &str) {
+    let mut metrics = METRICS.lock().expect("metrics lock");
+    *metrics.hotplug_events.entry(trigger.to_string()).or_default() += 1;
+}
+
+pub fn reconfiguration(duration: Duration, success: bool) {
+    METRICS.lock().expect("metrics lock").add_reconfiguration(duration, success);
+}
+
+// Serves the metrics on every path, like "http://127.0.0.1:9753/metrics".
+pub fn serve(address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(answer);
+            if let Err(error) = result {
+                tracing::error!("Cannot answer the metrics request: {}", error);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn answer(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    // Only the request line matters, but the headers are read so that the client does not get a
+    // reset connection.
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+    let body = render(&METRICS.lock().expect("metrics lock"));
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(), body)
+}
+
+fn render(metrics: &Metrics) -> String {
+    let mut body = String::new();
+    body.push_str("# HELP i3_aww_hotplug_events_total Hotplug events received, before the debounce.\n");
+    body.push_str("# TYPE i3_aww_hotplug_events_total counter\n");
+    for (trigger, count) in &metrics.hotplug_events {
+        body.push_str(&format!("i3_aww_hotplug_events_total{{trigger=\"{}\"}} {}\n", trigger, count));
+    }
+    body.push_str("# HELP i3_aww_reconfigurations_total Profiles applied.\n");
+    body.push_str("# TYPE i3_aww_reconfigurations_total counter\n");
+    body.push_str(&format!("i3_aww_reconfigurations_total {}\n", metrics.reconfigurations));
+    body.push_str("# HELP i3_aww_reconfiguration_failures_total Profiles that could not be applied.\n");
+    body.push_str("# TYPE i3_aww_reconfiguration_failures_total counter\n");
+    body.push_str(&format!("i3_aww_reconfiguration_failures_total {}\n", metrics.failures));
+    body.push_str("# HELP i3_aww_apply_duration_seconds Time taken to configure the outputs and run the hooks.\n");
+    body.push_str("# TYPE i3_aww_apply_duration_seconds histogram\n");
+    for (count, bound) in metrics.apply_buckets.iter().zip(&APPLY_BUCKETS) {
+        body.push_str(&format!("i3_aww_apply_duration_seconds_bucket{{le=\"{}\"}} {}\n", bound, count));
+    }
+    body.push_str(&format!("i3_aww_apply_duration_seconds_bucket{{le=\"+Inf\"}} {}\n", metrics.apply_count));
+    body.push_str(&format!("i3_aww_apply_duration_seconds_sum {}\n", metrics.apply_sum));
+    body.push_str(&format!("i3_aww_apply_duration_seconds_count {}\n", metrics.apply_count));
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Metrics, render};
+
+    // The buckets are cumulative: a reconfiguration is counted in every bucket above its duration.
+    #[test]
+    fn renders_apply_duration_histogram() {
+        let mut metrics = Metrics::new();
+        metrics.add_reconfiguration(Duration::from_millis(80), true);
+        metrics.add_reconfiguration(Duration::from_millis(700), false);
+        metrics.add_reconfiguration(Duration::from_secs(60), true);
+
+        let body = render(&metrics);
+        let lines: Vec<_> = body.lines()
+            .filter(|line| line.starts_with("i3_aww_apply_duration_seconds"))
+            .collect();
+        assert_eq!(lines, [
+            r#"i3_aww_apply_duration_seconds_bucket{le="0.05"} 0"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="0.1"} 1"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="0.25"} 1"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="0.5"} 1"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="1"} 2"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="2.5"} 2"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="5"} 2"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="10"} 2"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="30"} 2"#,
+            r#"i3_aww_apply_duration_seconds_bucket{le="+Inf"} 3"#,
+            "i3_aww_apply_duration_seconds_sum 60.78",
+            "i3_aww_apply_duration_seconds_count 3",
+        ]);
+        assert!(body.contains("i3_aww_reconfigurations_total 3\n"));
+        assert!(body.contains("i3_aww_reconfiguration_failures_total 1\n"));
+    }
+}
@@ -0,0 +1,266 @@
+// Fake i3 answering on a temporary IPC socket, to test the restoration of the workspaces without a
+// window manager: it replies to the queries with the state set by the test, records the commands
+// instead of running them, and pushes the events requested by the test to the subscribers. The
+// outputs only exist for the window manager: the display backend has no monitor.
+
+use std::{
+    env,
+    io::{self, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    process,
+    sync::{
+        Arc,
+        Mutex,
+        MutexGuard,
+        PoisonError,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use i3_aww_core::outputs::MonitorData;
+use i3_ipc::{I3IPC, MAGIC, event::Subscribe, msg::Msg};
+use serde_json::{Value, json};
+
+use crate::{
+    backend::{self, DisplayBackend, I3Ipc},
+    config::Profile,
+};
+
+// The socket is found through I3SOCK, which is shared by the tests running in parallel.
+static SOCKET: Mutex<()> = Mutex::new(());
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+const EVENT_BIT: u32 = 1 << 31;
+
+struct NoMonitor;
+
+impl DisplayBackend for NoMonitor {
+    fn monitor_data(&self) -> Vec<MonitorData> {
+        vec![]
+    }
+
+    fn apply(&self, _profile: &Profile, _monitor_data: &[MonitorData]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn current_profile(&self, _name: &str) -> io::Result<Profile> {
+        Err(io::Error::new(ErrorKind::Unsupported, "no monitor in the tests"))
+    }
+}
+
+// Workspace name and ids of its windows.
+pub type WorkspaceWindows<'a> = (&'a str, &'a [usize]);
+
+#[derive(Default)]
+struct State {
+    workspaces: Vec<Value>,
+    outputs: Vec<Value>,
+    tree: Value,
+    commands: Vec<String>,
+    subscribers: Vec<UnixStream>,
+}
+
+pub struct MockI3 {
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+    _socket: MutexGuard<'static, ()>,
+}
+
+impl MockI3 {
+    // Listens on a new socket and points I3SOCK to it until the mock is dropped.
+    pub fn start() -> io::Result<Self> {
+        let socket = SOCKET.lock().unwrap_or_else(PoisonError::into_inner);
+        let path = env::temp_dir().join(format!("i3-aww-mock-{}-{}.sock", process::id(), COUNTER.fetch_add(1, Ordering::SeqCst)));
+        let listener = UnixListener::bind(&path)?;
+        env::set_var("I3SOCK", &path);
+        backend::install(Arc::new(NoMonitor), Arc::new(I3Ipc));
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let state = state.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = state.clone();
+                        thread::spawn(move || answer(stream, &state));
+                    }
+                }
+            });
+        }
+
+        Ok(Self {
+            path,
+            state,
+            stopped,
+            _socket: socket,
+        })
+    }
+
+    pub fn set_workspaces(&self, workspaces: Vec<Value>) {
+        self.state().workspaces = workspaces;
+    }
+
+    pub fn set_outputs(&self, outputs: Vec<Value>) {
+        self.state().outputs = outputs;
+    }
+
+    pub fn set_tree(&self, tree: Value) {
+        self.state().tree = tree;
+    }
+
+    // The payloads of the RUN_COMMAND messages received so far.
+    pub fn commands(&self) -> Vec<String> {
+        self.state().commands.clone()
+    }
+
+    pub fn push_event(&self, event: Subscribe, payload: Value) {
+        let event_type = EVENT_BIT | event as u32;
+        let payload = payload.to_string();
+        self.state().subscribers
+            .retain_mut(|stream| write_message(stream, event_type, &payload).is_ok());
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("mock state lock")
+    }
+}
+
+impl Drop for MockI3 {
+    fn drop(&mut self) {
+        // Wake up the listener so that it sees that it is stopped.
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = UnixStream::connect(&self.path);
+        let _ = std::fs::remove_file(&self.path);
+        env::remove_var("I3SOCK");
+    }
+}
+
+fn answer(mut stream: UnixStream, state: &Mutex<State>) {
+    while let Ok((msg_type, payload)) = stream.decode_msg() {
+        let payload = String::from_utf8_lossy(&payload).into_owned();
+        let mut state = state.lock().expect("mock state lock");
+        let reply =
+            match Msg::from(msg_type) {
+                Msg::RunCommand => {
+                    let count = payload.split(';').count();
+                    state.commands.push(payload);
+                    Value::Array(vec![json!({ "success": true }); count])
+                },
+                Msg::Workspaces => Value::Array(state.workspaces.clone()),
+                Msg::Subscribe => {
+                    match stream.try_clone() {
+                        Ok(subscriber) => state.subscribers.push(subscriber),
+                        Err(_) => return,
+                    }
+                    json!({ "success": true })
+                },
+                Msg::Outputs => Value::Array(state.outputs.clone()),
+                Msg::Tree => state.tree.clone(),
+                _ => json!({ "success": false, "error": "not supported by the mock" }),
+            };
+        drop(state);
+        if write_message(&mut stream, msg_type, &reply.to_string()).is_err() {
+            return;
+        }
+    }
+}
+
+fn write_message(stream: &mut UnixStream, msg_type: u32, payload: &str) -> io::Result<()> {
+    let mut message = MAGIC.as_bytes().to_vec();
+    message.extend((payload.len() as u32).to_ne_bytes());
+    message.extend(msg_type.to_ne_bytes());
+    message.extend(payload.as_bytes());
+    stream.write_all(&message)
+}
+
+pub fn rect(x: isize, width: isize) -> Value {
+    json!({ "x": x, "y": 0, "width": width, "height": 1080 })
+}
+
+pub fn output(name: &str, active: bool, x: isize) -> Value {
+    json!({
+        "name": name,
+        "active": active,
+        "primary": false,
+        "current_workspace": null,
+        "rect": rect(x, 1920),
+    })
+}
+
+// `num` is -1 for a named workspace.
+pub fn workspace(num: i32, name: &str, output: &str, visible: bool, focused: bool) -> Value {
+    json!({
+        "id": 0,
+        "num": num,
+        "name": name,
+        "visible": visible,
+        "focused": focused,
+        "urgent": false,
+        "rect": rect(0, 1920),
+        "output": output,
+    })
+}
+
+// A node of the tree, with only what the restoration looks at set to meaningful values.
+pub fn node(id: usize, node_type: &str, name: &str, nodes: Vec<Value>) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "num": null,
+        "type": node_type,
+        "layout": "splith",
+        "output": null,
+        "orientation": "horizontal",
+        "border": "normal",
+        "scratchpad_state": "none",
+        "percent": null,
+        "rect": rect(0, 1920),
+        "window_rect": rect(0, 0),
+        "deco_rect": rect(0, 0),
+        "geometry": rect(0, 0),
+        "window": null,
+        "window_properties": null,
+        "window_type": null,
+        "current_border_width": 0,
+        "urgent": false,
+        "marks": null,
+        "focused": false,
+        "focus": [],
+        "sticky": false,
+        "floating": null,
+        "floating_nodes": [],
+        "fullscreen_mode": 0,
+        "nodes": nodes,
+    })
+}
+
+// The tree of the workspaces on each output, with the ids of the windows on each workspace.
+pub fn tree(outputs: &[(&str, &[WorkspaceWindows])]) -> Value {
+    let mut next_id = 1000;
+    let mut id = || {
+        next_id += 1;
+        next_id
+    };
+    let outputs = outputs.iter()
+        .map(|(output, workspaces)| {
+            let workspaces = workspaces.iter()
+                .map(|(workspace, windows)| {
+                    let windows = windows.iter()
+                        .map(|&window| node(window, "con", "window", vec![]))
+                        .collect();
+                    node(id(), "workspace", workspace, windows)
+                })
+                .collect();
+            node(id(), "output", output, workspaces)
+        })
+        .collect();
+    node(1, "root", "root", outputs)
+}
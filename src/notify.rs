@@ -0,0 +1,26 @@
+// Desktop notifications through notify-send, so that the user knows what happened without reading
+// the logs.
+
+use std::process::Command;
+
+#[derive(Clone, Copy)]
+pub enum Urgency {
+    Normal,
+    Critical,
+}
+
+pub fn send(urgency: Urgency, summary: &str, body: &str) {
+    let urgency =
+        match urgency {
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        };
+    let status = Command::new("notify-send")
+        .args(["--app-name", "i3-aww", "--urgency", urgency, summary, body])
+        .status();
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => tracing::error!("Cannot send a notification: notify-send failed: {}", status),
+        Err(error) => tracing::error!("Cannot send a notification: {}", error),
+    }
+}
@@ -0,0 +1,85 @@
+// Position of the mouse pointer relative to the output it is on, to put it back there when the
+// output comes back after being disconnected.
+
+use std::{io, ptr};
+
+use x11::xlib;
+
+use crate::randr;
+
+pub struct PointerPosition {
+    pub output: String,
+    // Fractions of the size of the output, since its mode can be different when it comes back.
+    x: f64,
+    y: f64,
+}
+
+pub fn position() -> io::Result<Option<PointerPosition>> {
+    let (x, y) = with_display(|display, root| {
+        let mut root_return = 0;
+        let mut child = 0;
+        let mut root_x = 0;
+        let mut root_y = 0;
+        let mut window_x = 0;
+        let mut window_y = 0;
+        let mut mask = 0;
+        let same_screen = unsafe {
+            xlib::XQueryPointer(display, root, &mut root_return, &mut child, &mut root_x, &mut root_y, &mut window_x,
+                &mut window_y, &mut mask)
+        };
+        if same_screen == 0 {
+            return Err(io::Error::other("the pointer is not on this screen"));
+        }
+        Ok((root_x, root_y))
+    })?;
+
+    let position = randr::query()?.into_iter()
+        .find_map(|output| {
+            let geometry = output.geometry?;
+            let relative_x = x - geometry.x;
+            let relative_y = y - geometry.y;
+            if relative_x < 0 || relative_y < 0 || relative_x >= geometry.width as i32 || relative_y >= geometry.height as i32 {
+                return None;
+            }
+            Some(PointerPosition {
+                output: output.name,
+                x: relative_x as f64 / geometry.width as f64,
+                y: relative_y as f64 / geometry.height as f64,
+            })
+        });
+    Ok(position)
+}
+
+// Returns false when the output is not enabled.
+pub fn restore(position: &PointerPosition) -> io::Result<bool> {
+    let geometry = randr::query()?.into_iter()
+        .find(|output| output.name == position.output)
+        .and_then(|output| output.geometry);
+    let geometry =
+        match geometry {
+            Some(geometry) => geometry,
+            None => return Ok(false),
+        };
+
+    let x = geometry.x + (position.x * geometry.width as f64) as i32;
+    let y = geometry.y + (position.y * geometry.height as f64) as i32;
+    with_display(|display, root| {
+        unsafe {
+            xlib::XWarpPointer(display, 0, root, 0, 0, 0, 0, x, y);
+        }
+        Ok(())
+    })?;
+    Ok(true)
+}
+
+fn with_display<T, F: FnOnce(*mut xlib::Display, xlib::Window) -> io::Result<T>>(callback: F) -> io::Result<T> {
+    let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+    if display.is_null() {
+        return Err(io::Error::other("cannot open the X display"));
+    }
+    let result = callback(display, unsafe { xlib::XDefaultRootWindow(display) });
+    unsafe {
+        xlib::XCloseDisplay(display);
+    }
+    result
+}
@@ -0,0 +1,40 @@
+// Power source of laptops, from UPower through gdbus: the profiles can lower the refresh rate or
+// turn outputs off on battery.
+
+use std::{
+    io,
+    process::Command,
+};
+
+use crate::child;
+
+const UPOWER_ARGS: [&str; 5] = ["--system", "--dest", "org.freedesktop.UPower", "--object-path", "/org/freedesktop/UPower"];
+
+pub fn on_battery() -> io::Result<bool> {
+    let output = Command::new("gdbus")
+        .arg("call")
+        .args(UPOWER_ARGS)
+        .args(["--method", "org.freedesktop.DBus.Properties.Get", "org.freedesktop.UPower", "OnBattery"])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("gdbus failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    // The reply looks like "(<true>,)".
+    Ok(String::from_utf8_lossy(&output.stdout).contains("true"))
+}
+
+// Calls `callback` with whether the laptop runs on battery every time the power source changes.
+pub fn watch<F: Fn(bool) + 'static>(callback: F) -> io::Result<()> {
+    let mut command = Command::new("gdbus");
+    command.arg("monitor").args(UPOWER_ARGS);
+    child::watch_lines(&mut command, "upower", move |line| {
+        // The changes look like "/org/freedesktop/UPower: org.freedesktop.DBus.Properties.PropertiesChanged
+        // ('org.freedesktop.UPower', {'OnBattery': <true>}, @as [])".
+        if line.contains("'OnBattery': <true>") {
+            callback(true);
+        }
+        else if line.contains("'OnBattery': <false>") {
+            callback(false);
+        }
+    })
+}
@@ -0,0 +1,1515 @@
+// Access to the outputs through the RandR extension of the X server: query of their current state
+// and configuration, understanding the same arguments as `xrandr`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::{CStr, CString},
+    io::{self, ErrorKind},
+    os::raw::{c_char, c_int, c_long},
+    mem,
+    ptr,
+    slice,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use i3_aww_core::{
+    layout::{DesiredOutput, OutputModes},
+    outputs,
+};
+use x11::{xlib, xrandr, xrender::{XFixed, XTransform}};
+
+const RELATIVE_POSITIONS: [&str; 5] = ["--right-of", "--left-of", "--above", "--below", "--same-as"];
+// 1 in the 16.16 fixed point format of the transformations.
+const FIXED_ONE: XFixed = 1 << 16;
+const IDENTITY: [[XFixed; 3]; 3] = [[FIXED_ONE, 0, 0], [0, FIXED_ONE, 0], [0, 0, FIXED_ONE]];
+
+// Code of the last X error received while configuring the outputs.
+static X_ERROR: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Clone, Debug)]
+pub struct OutputState {
+    pub name: String,
+    pub connected: bool,
+    pub primary: bool,
+    // Only set when the output is enabled.
+    pub geometry: Option<Geometry>,
+    pub rotation: String,
+    pub reflection: String,
+    pub mode: Option<String>,
+    pub rate: Option<String>,
+    pub preferred_mode: Option<String>,
+    // Available modes with their refresh rate, the preferred ones first.
+    pub modes: Vec<(String, f64)>,
+    // Horizontal and vertical scale of the picture.
+    pub scale: (f64, f64),
+    pub transform: [[XFixed; 3]; 3],
+    // Only set when it is not the one xrandr would choose.
+    pub filter: Option<&'static str>,
+    pub panning: Option<Panning>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Geometry {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+// Connection to the X server, with the RandR resources of its screen.
+struct Screen {
+    display: *mut xlib::Display,
+    root: xlib::Window,
+    resources: *mut xrandr::XRRScreenResources,
+}
+
+struct OutputInfo {
+    id: xrandr::RROutput,
+    name: String,
+    connected: bool,
+    crtc: xrandr::RRCrtc,
+    crtcs: Vec<xrandr::RRCrtc>,
+    // Physical size, in millimeters.
+    mm_size: (u64, u64),
+    // The preferred modes come first.
+    modes: Vec<xrandr::RRMode>,
+    preferred: usize,
+}
+
+#[derive(Clone, PartialEq)]
+struct CrtcConfig {
+    x: i32,
+    y: i32,
+    // 0 when the CRTC is disabled.
+    mode: xrandr::RRMode,
+    rotation: xrandr::Rotation,
+    // Transformation of the picture, e.g. for scaling it.
+    transform: [[XFixed; 3]; 3],
+    // Scaling filter, chosen like xrandr does when not set.
+    filter: Option<&'static str>,
+    panning: Option<Panning>,
+    outputs: Vec<xrandr::RROutput>,
+}
+
+// Like xrandr --panning: the output shows part of a larger area of the screen, following the
+// pointer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Panning {
+    // The position of the output when not given.
+    position: Option<(u32, u32)>,
+    size: (u32, u32),
+    // Left, top, width and height of the area where the pointer pans, the panning area when empty.
+    tracking: [u32; 4],
+    // Left, top, right and bottom distances to the edges where the panning starts.
+    border: [i32; 4],
+}
+
+enum Position<'a> {
+    Current,
+    Absolute(i32, i32),
+    Relative(&'a str, &'a str),
+}
+
+// Configuration of an output that stays enabled.
+struct OutputPlan<'a> {
+    output: &'a OutputInfo,
+    mode: xrandr::RRMode,
+    rotation: xrandr::Rotation,
+    transform: [[XFixed; 3]; 3],
+    filter: Option<&'static str>,
+    panning: Option<Panning>,
+    position: Position<'a>,
+    primary: bool,
+    crtc: xrandr::RRCrtc,
+}
+
+impl Screen {
+    fn open() -> io::Result<Self> {
+        let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+        if display.is_null() {
+            return Err(io::Error::other("cannot open the X display"));
+        }
+
+        let root = unsafe { xlib::XDefaultRootWindow(display) };
+        let resources = unsafe { xrandr::XRRGetScreenResourcesCurrent(display, root) };
+        if resources.is_null() {
+            unsafe {
+                xlib::XCloseDisplay(display);
+            }
+            return Err(io::Error::other("cannot get the RandR resources of the screen"));
+        }
+
+        Ok(Self {
+            display,
+            root,
+            resources,
+        })
+    }
+
+    fn crtc_configs(&self) -> BTreeMap<xrandr::RRCrtc, CrtcConfig> {
+        let crtcs = unsafe { to_vec((*self.resources).crtcs, (*self.resources).ncrtc) };
+        crtcs.into_iter()
+            .filter_map(|crtc| Some((crtc, self.crtc_config(crtc)?)))
+            .collect()
+    }
+
+    fn outputs(&self) -> Vec<OutputInfo> {
+        let ids = unsafe { to_vec((*self.resources).outputs, (*self.resources).noutput) };
+        ids.into_iter()
+            .filter_map(|id| self.output_info(id))
+            .collect()
+    }
+
+    fn modes(&self) -> &[xrandr::XRRModeInfo] {
+        unsafe {
+            let resources = &*self.resources;
+            if resources.modes.is_null() || resources.nmode <= 0 {
+                return &[];
+            }
+            slice::from_raw_parts(resources.modes, resources.nmode as usize)
+        }
+    }
+
+    fn mode(&self, id: xrandr::RRMode) -> Option<&xrandr::XRRModeInfo> {
+        self.modes().iter()
+            .find(|mode| mode.id == id)
+    }
+
+    fn output_info(&self, id: xrandr::RROutput) -> Option<OutputInfo> {
+        unsafe {
+            let info = xrandr::XRRGetOutputInfo(self.display, self.resources, id);
+            if info.is_null() {
+                return None;
+            }
+            let name = to_vec((*info).name as *const u8, (*info).nameLen);
+            let output = OutputInfo {
+                id,
+                name: String::from_utf8_lossy(&name).into_owned(),
+                connected: (*info).connection == xrandr::RR_Connected as xrandr::Connection,
+                crtc: (*info).crtc,
+                crtcs: to_vec((*info).crtcs, (*info).ncrtc),
+                mm_size: ((*info).mm_width as u64, (*info).mm_height as u64),
+                modes: to_vec((*info).modes, (*info).nmode),
+                preferred: (*info).npreferred.max(0) as usize,
+            };
+            xrandr::XRRFreeOutputInfo(info);
+            Some(output)
+        }
+    }
+
+    fn crtc_config(&self, id: xrandr::RRCrtc) -> Option<CrtcConfig> {
+        if id == 0 {
+            return None;
+        }
+        unsafe {
+            let info = xrandr::XRRGetCrtcInfo(self.display, self.resources, id);
+            if info.is_null() {
+                return None;
+            }
+            let mut config = CrtcConfig {
+                x: (*info).x,
+                y: (*info).y,
+                mode: (*info).mode,
+                rotation: (*info).rotation,
+                transform: IDENTITY,
+                filter: None,
+                panning: None,
+                outputs: to_vec((*info).outputs, (*info).noutput),
+            };
+            xrandr::XRRFreeCrtcInfo(info);
+
+            let mut attributes = ptr::null_mut();
+            if xrandr::XRRGetCrtcTransform(self.display, id, &mut attributes) != 0 && !attributes.is_null() {
+                config.transform = (*attributes).currentTransform.matrix;
+                let filter = (*attributes).currentFilter;
+                if !filter.is_null() {
+                    // Only a filter other than the one xrandr would choose is set explicitly.
+                    config.filter = parse_filter(&CStr::from_ptr(filter).to_string_lossy())
+                        .filter(|&filter| filter != default_filter(&config.transform));
+                }
+                xlib::XFree(attributes as *mut _);
+            }
+
+            let panning = xrandr::XRRGetPanning(self.display, self.resources, id);
+            if !panning.is_null() {
+                if (*panning).width != 0 && (*panning).height != 0 {
+                    config.panning = Some(Panning {
+                        position: Some(((*panning).left, (*panning).top)),
+                        size: ((*panning).width, (*panning).height),
+                        tracking: [(*panning).track_left, (*panning).track_top, (*panning).track_width, (*panning).track_height],
+                        border: [(*panning).border_left, (*panning).border_top, (*panning).border_right, (*panning).border_bottom],
+                    });
+                }
+                xrandr::XRRFreePanning(panning);
+            }
+            Some(config)
+        }
+    }
+
+    fn size(&self) -> (i32, i32) {
+        unsafe {
+            let screen_number = xlib::XDefaultScreen(self.display);
+            (xlib::XDisplayWidth(self.display, screen_number), xlib::XDisplayHeight(self.display, screen_number))
+        }
+    }
+
+    // Size taken by the CRTC on the screen, once rotated and transformed.
+    fn crtc_size(&self, config: &CrtcConfig) -> Option<(u32, u32)> {
+        let mode = self.mode(config.mode)?;
+        if let Some(ref panning) = config.panning {
+            return Some(panning.size);
+        }
+        let (width, height) =
+            if config.rotation as c_int & (xrandr::RR_Rotate_90 | xrandr::RR_Rotate_270) != 0 {
+                (mode.height, mode.width)
+            }
+            else {
+                (mode.width, mode.height)
+            };
+        if config.transform == IDENTITY {
+            return Some((width, height));
+        }
+
+        // Bounding box of the transformed corners.
+        let corners = [(0.0, 0.0), (width as f64, 0.0), (0.0, height as f64), (width as f64, height as f64)];
+        let (mut max_x, mut max_y) = (0.0_f64, 0.0_f64);
+        let (mut min_x, mut min_y) = (f64::MAX, f64::MAX);
+        for (x, y) in corners {
+            let (x, y) = transform_point(&config.transform, x, y);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        Some(((max_x - min_x).ceil() as u32, (max_y - min_y).ceil() as u32))
+    }
+
+    fn set_crtc_config(&self, crtc: xrandr::RRCrtc, config: &CrtcConfig) -> io::Result<()> {
+        // The transformation is pending until the next configuration of the CRTC.
+        if config.mode != 0 {
+            let filter = CString::new(config.filter.unwrap_or(default_filter(&config.transform)))
+                .map_err(io::Error::other)?;
+            let mut transform = XTransform {
+                matrix: config.transform,
+            };
+            unsafe {
+                xrandr::XRRSetCrtcTransform(self.display, crtc, &mut transform, filter.as_ptr(), ptr::null_mut(), 0);
+            }
+        }
+
+        let mut outputs = config.outputs.clone();
+        let status = unsafe {
+            xrandr::XRRSetCrtcConfig(self.display, self.resources, crtc, xlib::CurrentTime, config.x, config.y,
+                config.mode, config.rotation, outputs.as_mut_ptr(), outputs.len() as c_int)
+        };
+        if status != xrandr::RRSetConfigSuccess {
+            return Err(io::Error::other(format!("cannot configure the CRTC {} (status {})", crtc, status)));
+        }
+        if config.mode != 0 {
+            self.set_panning(crtc, config)?;
+        }
+        Ok(())
+    }
+
+    // The panning of a CRTC is only touched when it is requested or has to be turned off, since
+    // many drivers do not support it.
+    fn set_panning(&self, crtc: xrandr::RRCrtc, config: &CrtcConfig) -> io::Result<()> {
+        unsafe {
+            let panning = xrandr::XRRGetPanning(self.display, self.resources, crtc);
+            if panning.is_null() {
+                return match config.panning {
+                    Some(_) => Err(io::Error::other(format!("the CRTC {} does not support panning", crtc))),
+                    None => Ok(()),
+                };
+            }
+            let enabled = (*panning).width != 0 && (*panning).height != 0;
+            let status =
+                match config.panning {
+                    Some(ref requested) => {
+                        let (left, top) = requested.position.unwrap_or((config.x as u32, config.y as u32));
+                        let [track_left, track_top, track_width, track_height] = requested.tracking;
+                        let [border_left, border_top, border_right, border_bottom] = requested.border;
+                        *panning = xrandr::XRRPanning {
+                            timestamp: (*panning).timestamp,
+                            left,
+                            top,
+                            width: requested.size.0,
+                            height: requested.size.1,
+                            track_left,
+                            track_top,
+                            track_width,
+                            track_height,
+                            border_left,
+                            border_top,
+                            border_right,
+                            border_bottom,
+                        };
+                        xrandr::XRRSetPanning(self.display, self.resources, crtc, panning)
+                    },
+                    None if enabled => {
+                        *panning = xrandr::XRRPanning {
+                            timestamp: (*panning).timestamp,
+                            ..mem::zeroed()
+                        };
+                        xrandr::XRRSetPanning(self.display, self.resources, crtc, panning)
+                    },
+                    None => xrandr::RRSetConfigSuccess,
+                };
+            xrandr::XRRFreePanning(panning);
+            if status != xrandr::RRSetConfigSuccess {
+                return Err(io::Error::other(format!("cannot set the panning of the CRTC {} (status {})", crtc, status)));
+            }
+        }
+        Ok(())
+    }
+
+    // Waits for the X server to process the requests and reports the error it sent, if any.
+    fn sync(&self) -> io::Result<()> {
+        unsafe {
+            xlib::XSync(self.display, xlib::False);
+        }
+        let code = X_ERROR.swap(0, Ordering::SeqCst);
+        if code == 0 {
+            return Ok(());
+        }
+
+        let mut buffer = [0 as c_char; 256];
+        let message = unsafe {
+            xlib::XGetErrorText(self.display, code as c_int, buffer.as_mut_ptr(), buffer.len() as c_int);
+            CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned()
+        };
+        Err(io::Error::other(format!("X error while configuring the outputs: {}", message)))
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        unsafe {
+            xrandr::XRRFreeScreenResources(self.resources);
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+// Version of the RandR extension of the X server.
+pub fn version() -> io::Result<(i32, i32)> {
+    let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+    if display.is_null() {
+        return Err(io::Error::other("cannot open the X display"));
+    }
+    let (mut major, mut minor) = (0, 0);
+    let status = unsafe { xrandr::XRRQueryVersion(display, &mut major, &mut minor) };
+    unsafe {
+        xlib::XCloseDisplay(display);
+    }
+    if status == 0 {
+        return Err(io::Error::new(ErrorKind::Unsupported, "the X server has no RandR extension"));
+    }
+    Ok((major, minor))
+}
+
+// Sets the gamma ramps of the output: `curve` gives the level of the channel (0 for red, 1 for
+// green and 2 for blue) for an input level, both between 0 and 1.
+pub fn set_gamma<F: Fn(usize, f64) -> f64>(name: &str, curve: F) -> io::Result<()> {
+    let screen = Screen::open()?;
+    let output = find_output(&screen, name)?;
+    if output.crtc == 0 {
+        return Err(io::Error::other(format!("output {} is disabled", name)));
+    }
+
+    unsafe {
+        let size = xrandr::XRRGetCrtcGammaSize(screen.display, output.crtc);
+        if size <= 1 {
+            return Err(io::Error::other(format!("cannot change the gamma of output {}", name)));
+        }
+        let ramps = xrandr::XRRAllocGamma(size);
+        if ramps.is_null() {
+            return Err(io::Error::other("cannot allocate the gamma ramps"));
+        }
+        let channels = [(*ramps).red, (*ramps).green, (*ramps).blue];
+        for (channel, ramp) in channels.into_iter().enumerate() {
+            let ramp = slice::from_raw_parts_mut(ramp, size as usize);
+            for (index, value) in ramp.iter_mut().enumerate() {
+                let level = curve(channel, index as f64 / (size - 1) as f64);
+                *value = (level.clamp(0.0, 1.0) * u16::MAX as f64) as u16;
+            }
+        }
+        xrandr::XRRSetCrtcGamma(screen.display, output.crtc, ramps);
+        xrandr::XRRFreeGamma(ramps);
+    }
+    screen.sync()
+}
+
+// The gamma ramps of the enabled outputs, by output.
+pub fn gamma_ramps() -> io::Result<BTreeMap<String, [Vec<u16>; 3]>> {
+    let screen = Screen::open()?;
+    let mut ramps = BTreeMap::new();
+    for output in screen.outputs() {
+        if output.crtc == 0 {
+            continue;
+        }
+        unsafe {
+            let gamma = xrandr::XRRGetCrtcGamma(screen.display, output.crtc);
+            if gamma.is_null() {
+                continue;
+            }
+            let size = (*gamma).size;
+            if size > 1 {
+                let channels = [to_vec((*gamma).red, size), to_vec((*gamma).green, size), to_vec((*gamma).blue, size)];
+                ramps.insert(output.name, channels);
+            }
+            xrandr::XRRFreeGamma(gamma);
+        }
+    }
+    Ok(ramps)
+}
+
+// Sets an integer property of the output, like the ones the drivers expose for the DRM connector
+// properties. The property must exist and not be read-only.
+pub fn set_output_property(name: &str, property: &str, value: i64) -> io::Result<()> {
+    let screen = Screen::open()?;
+    let output = find_output(&screen, name)?;
+    let property_name = CString::new(property).map_err(io::Error::other)?;
+    let missing = || io::Error::new(ErrorKind::Unsupported, format!("output {} has no property {}", name, property));
+    unsafe {
+        let atom = xlib::XInternAtom(screen.display, property_name.as_ptr(), xlib::True);
+        if atom == 0 {
+            return Err(missing());
+        }
+        let info = xrandr::XRRQueryOutputProperty(screen.display, output.id, atom);
+        if info.is_null() {
+            return Err(missing());
+        }
+        let immutable = (*info).immutable != 0;
+        xlib::XFree(info as *mut _);
+        if immutable {
+            return Err(io::Error::new(ErrorKind::PermissionDenied,
+                format!("the property {} of output {} is read-only", property, name)));
+        }
+
+        // Properties of format 32 are passed as longs.
+        let data = value as c_long;
+        xrandr::XRRChangeOutputProperty(screen.display, output.id, atom, xlib::XA_INTEGER, 32, xlib::PropModeReplace,
+            &data as *const c_long as *const u8, 1);
+    }
+    screen.sync()
+}
+
+// Publishes the ICC profile of the monitor in the _ICC_PROFILE property of its output, as
+// specified by the ICC Profiles in X specification, for the color managed applications.
+pub fn set_icc_profile(name: &str, data: &[u8]) -> io::Result<()> {
+    let screen = Screen::open()?;
+    let output = find_output(&screen, name)?;
+    unsafe {
+        let atom = xlib::XInternAtom(screen.display, c"_ICC_PROFILE".as_ptr(), xlib::False);
+        xrandr::XRRChangeOutputProperty(screen.display, output.id, atom, xlib::XA_CARDINAL, 8,
+            xlib::PropModeReplace, data.as_ptr(), data.len() as c_int);
+    }
+    screen.sync()
+}
+
+// Gives the output a mode with the CVT reduced blanking timings, like `cvt -r`, creating it if
+// needed. The mode is named like the exact modes of the profiles, e.g. "1920x1080".
+pub fn add_mode(name: &str, width: u32, height: u32, rate: f64) -> io::Result<()> {
+    let screen = Screen::open()?;
+    let output = find_output(&screen, name)?;
+    let size = format!("{}x{}", width, height);
+    let has_mode = output.modes.iter()
+        .filter_map(|&id| screen.mode(id))
+        .any(|mode| mode_name(mode) == size);
+    if has_mode {
+        return Ok(());
+    }
+
+    let mode =
+        match screen.modes().iter().find(|mode| mode_name(mode) == size) {
+            Some(mode) => mode.id,
+            None => {
+                let mut name_bytes = size.into_bytes();
+                let mut info = cvt_reduced_blanking(width, height, rate);
+                info.name = name_bytes.as_mut_ptr() as *mut c_char;
+                info.nameLength = name_bytes.len() as _;
+                unsafe { xrandr::XRRCreateMode(screen.display, screen.root, &mut info) }
+            },
+        };
+    unsafe {
+        xrandr::XRRAddOutputMode(screen.display, output.id, mode);
+    }
+    screen.sync()
+}
+
+// Version 1 of the reduced blanking of the VESA Coordinated Video Timings.
+fn cvt_reduced_blanking(width: u32, height: u32, rate: f64) -> xrandr::XRRModeInfo {
+    const MIN_VBLANK_MICROSECONDS: f64 = 460.0;
+    const H_BLANK: u32 = 160;
+    const H_SYNC: u32 = 32;
+    const H_FRONT_PORCH: u32 = 48;
+    const V_FRONT_PORCH: u32 = 3;
+    const MIN_V_BACK_PORCH: u32 = 6;
+
+    let width = width / 8 * 8;
+    // The width of the vertical sync identifies the aspect ratio.
+    let v_sync =
+        match (width * 3 == height * 4, width * 9 == height * 16, width * 10 == height * 16, width * 4 == height * 5) {
+            (true, _, _, _) => 4,
+            (_, true, _, _) => 5,
+            (_, _, true, _) => 6,
+            (_, _, _, true) => 7,
+            _ => 10,
+        };
+    let h_period = (1_000_000.0 / rate - MIN_VBLANK_MICROSECONDS) / height as f64;
+    let vblank = ((MIN_VBLANK_MICROSECONDS / h_period) as u32 + 1).max(V_FRONT_PORCH + v_sync + MIN_V_BACK_PORCH);
+    let h_total = width + H_BLANK;
+    let v_total = height + vblank;
+    // In steps of 0.25 MHz.
+    let dot_clock = (rate * (h_total * v_total) as f64 / 250_000.0).floor() as u64 * 250_000;
+
+    xrandr::XRRModeInfo {
+        id: 0,
+        width,
+        height,
+        dotClock: dot_clock as _,
+        hSyncStart: width + H_FRONT_PORCH,
+        hSyncEnd: width + H_FRONT_PORCH + H_SYNC,
+        hTotal: h_total,
+        hSkew: 0,
+        vSyncStart: height + V_FRONT_PORCH,
+        vSyncEnd: height + V_FRONT_PORCH + v_sync,
+        vTotal: v_total,
+        name: ptr::null_mut(),
+        nameLength: 0,
+        modeFlags: (xrandr::RR_HSyncPositive | xrandr::RR_VSyncNegative) as xrandr::XRRModeFlags,
+    }
+}
+
+// Splits the outputs in RandR monitors of equal width, which i3 sees as outputs, e.g. DP-1~1 and
+// DP-1~2 for { "DP-1" = 2 }. The monitors of the outputs that are not split anymore are deleted.
+pub fn split_outputs(splits: &BTreeMap<String, u32>) -> io::Result<()> {
+    let screen = Screen::open()?;
+    let configs = screen.crtc_configs();
+    let mut monitors = BTreeSet::new();
+    for output in screen.outputs() {
+        let parts =
+            match splits.get(&output.name) {
+                Some(&parts) if parts > 1 => parts,
+                _ => continue,
+            };
+        let (x, y, width, height) = configs.get(&output.crtc)
+            .filter(|config| config.mode != 0)
+            .and_then(|config| Some((config.x, config.y, screen.crtc_size(config)?)))
+            .map(|(x, y, (width, height))| (x, y, width, height))
+            .ok_or_else(|| io::Error::other(format!("cannot split the disabled output {}", output.name)))?;
+
+        for part in 0..parts {
+            let name = format!("{}~{}", output.name, part + 1);
+            let left = width * part / parts;
+            let part_width = width * (part + 1) / parts - left;
+            let name_bytes = CString::new(name.clone()).map_err(io::Error::other)?;
+            unsafe {
+                let monitor = xrandr::XRRAllocateMonitor(screen.display, 1);
+                if monitor.is_null() {
+                    return Err(io::Error::other("cannot allocate a monitor"));
+                }
+                (*monitor).name = xlib::XInternAtom(screen.display, name_bytes.as_ptr(), xlib::False);
+                (*monitor).x = x + left as c_int;
+                (*monitor).y = y;
+                (*monitor).width = part_width as c_int;
+                (*monitor).height = height as c_int;
+                (*monitor).mwidth = (output.mm_size.0 * part_width as u64 / width.max(1) as u64) as c_int;
+                (*monitor).mheight = output.mm_size.1 as c_int;
+                // Like with xrandr, only the first monitor lists the output: an output belongs to
+                // a single monitor.
+                if part == 0 {
+                    *(*monitor).outputs = output.id;
+                }
+                else {
+                    (*monitor).noutput = 0;
+                }
+                xrandr::XRRSetMonitor(screen.display, screen.root, monitor);
+                xrandr::XRRFreeMonitors(monitor);
+            }
+            monitors.insert(name);
+        }
+    }
+
+    unsafe {
+        let mut count = 0;
+        let current = xrandr::XRRGetMonitors(screen.display, screen.root, xlib::False, &mut count);
+        if !current.is_null() {
+            for monitor in slice::from_raw_parts(current, count.max(0) as usize) {
+                let atom_name = xlib::XGetAtomName(screen.display, monitor.name);
+                if atom_name.is_null() {
+                    continue;
+                }
+                let name = CStr::from_ptr(atom_name).to_string_lossy().into_owned();
+                xlib::XFree(atom_name as *mut _);
+                if outputs::split_part(&name).1.is_some() && !monitors.contains(&name) {
+                    xrandr::XRRDeleteMonitor(screen.display, screen.root, monitor.name);
+                }
+            }
+            xrandr::XRRFreeMonitors(current);
+        }
+    }
+    screen.sync()
+}
+
+fn find_output(screen: &Screen, name: &str) -> io::Result<OutputInfo> {
+    screen.outputs().into_iter()
+        .find(|output| output.name == name)
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("unknown output {}", name)))
+}
+
+pub fn query() -> io::Result<Vec<OutputState>> {
+    let screen = Screen::open()?;
+    let primary = unsafe { xrandr::XRRGetOutputPrimary(screen.display, screen.root) };
+
+    let mut outputs = vec![];
+    for output in screen.outputs() {
+        let preferred_mode = output.modes.first()
+            .filter(|_| output.preferred > 0)
+            .and_then(|&mode| screen.mode(mode))
+            .map(mode_name);
+        let mut state = OutputState {
+            name: output.name,
+            connected: output.connected,
+            primary: output.id == primary,
+            geometry: None,
+            rotation: "normal".to_string(),
+            reflection: "normal".to_string(),
+            mode: None,
+            rate: None,
+            preferred_mode,
+            modes: output.modes.iter()
+                .filter_map(|&mode| screen.mode(mode))
+                .map(|mode| (mode_name(mode), refresh_rate(mode)))
+                .collect(),
+            scale: (1.0, 1.0),
+            transform: IDENTITY,
+            filter: None,
+            panning: None,
+        };
+
+        if let Some(config) = screen.crtc_config(output.crtc) {
+            if let (Some(mode), Some((width, height))) = (screen.mode(config.mode), screen.crtc_size(&config)) {
+                state.geometry = Some(Geometry {
+                    width,
+                    height,
+                    x: config.x,
+                    y: config.y,
+                });
+                state.rotation = rotation_name(config.rotation).to_string();
+                state.mode = Some(mode_name(mode));
+                state.rate = Some(format!("{:.2}", refresh_rate(mode)));
+                state.reflection = reflection_name(config.rotation).to_string();
+                state.scale = (fixed_to_f64(config.transform[0][0]), fixed_to_f64(config.transform[1][1]));
+                state.transform = config.transform;
+                state.filter = config.filter;
+                state.panning = config.panning;
+            }
+        }
+        outputs.push(state);
+    }
+    Ok(outputs)
+}
+
+// The modes of the outputs, for the planning of their configuration.
+pub fn output_modes(states: &[OutputState]) -> BTreeMap<String, OutputModes> {
+    states.iter()
+        .map(|state| {
+            let modes = OutputModes {
+                preferred_mode: state.preferred_mode.clone(),
+                modes: state.modes.clone(),
+            };
+            (state.name.clone(), modes)
+        })
+        .collect()
+}
+
+// Configures the outputs like `xrandr --output NAME ARGS…` would. The outputs which are not listed
+// keep their current configuration. The DPI of the screen is kept when not specified.
+pub fn apply(desired: &[DesiredOutput], dpi: Option<f64>) -> io::Result<()> {
+    let screen = Screen::open()?;
+    let outputs = screen.outputs();
+
+    let current = screen.crtc_configs();
+    let mut configs = current.clone();
+
+    let mut plans = vec![];
+    for desired_output in desired {
+        let output = outputs.iter()
+            .find(|output| output.name == desired_output.name)
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("unknown output {}", desired_output.name)))?;
+
+        // Release the CRTC of the output: it gets one back below if it stays enabled.
+        if let Some(config) = configs.get_mut(&output.crtc) {
+            config.outputs.retain(|&id| id != output.id);
+            if config.outputs.is_empty() {
+                config.mode = 0;
+            }
+        }
+
+        if desired_output.enabled {
+            if let Some(mut plan) = parse_args(&screen, output, &desired_output.args)? {
+                plan.primary |= desired_output.primary;
+                plans.push(plan);
+            }
+        }
+    }
+
+    for plan in &mut plans {
+        let output = plan.output;
+        // Keep the same CRTC when possible, to avoid reconfiguring the others.
+        let crtc = output.crtcs.iter()
+            .copied()
+            .filter(|crtc| configs.get(crtc).is_some_and(|config| config.outputs.is_empty()))
+            .min_by_key(|&crtc| crtc != output.crtc)
+            .ok_or_else(|| io::Error::other(format!("no CRTC available for output {}", output.name)))?;
+
+        let (x, y) =
+            match current.get(&output.crtc) {
+                Some(config) if config.mode != 0 => (config.x, config.y),
+                _ => (0, 0),
+            };
+        plan.crtc = crtc;
+        configs.insert(crtc, CrtcConfig {
+            x,
+            y,
+            mode: plan.mode,
+            rotation: plan.rotation,
+            transform: plan.transform,
+            filter: plan.filter,
+            panning: plan.panning,
+            outputs: vec![output.id],
+        });
+    }
+
+    place_outputs(&screen, &outputs, &plans, &mut configs)?;
+    // The panning areas follow their output, unless placed explicitly.
+    for config in configs.values_mut() {
+        if let Some(ref mut panning) = config.panning {
+            panning.position.get_or_insert((config.x as u32, config.y as u32));
+        }
+    }
+    let (width, height) = screen_size(&screen, &configs)?;
+
+    let (previous_width, previous_height) = screen.size();
+    let previous_primary = unsafe { xrandr::XRRGetOutputPrimary(screen.display, screen.root) };
+    let primary = plans.iter()
+        .find(|plan| plan.primary)
+        .map(|plan| plan.output.id);
+
+    if let Err(error) = configure(&screen, &current, &configs, width, height, dpi, primary) {
+        // Do not leave the user with black screens: go back to the configuration from before.
+        let restored = Screen::open()
+            .and_then(|screen| {
+                let actual = screen.crtc_configs();
+                configure(&screen, &actual, &current, previous_width, previous_height, None, Some(previous_primary))
+            });
+        return match restored {
+            Ok(()) => Err(io::Error::new(error.kind(), format!("{} (the previous configuration was restored)", error))),
+            Err(restore_error) => {
+                tracing::error!("Cannot restore the previous configuration of the outputs: {}", restore_error);
+                Err(error)
+            },
+        };
+    }
+    Ok(())
+}
+
+fn configure(screen: &Screen, current: &BTreeMap<xrandr::RRCrtc, CrtcConfig>,
+    configs: &BTreeMap<xrandr::RRCrtc, CrtcConfig>, width: i32, height: i32, dpi: Option<f64>,
+    primary: Option<xrandr::RROutput>) -> io::Result<()>
+{
+    let previous_handler = unsafe {
+        xlib::XGrabServer(screen.display);
+        xlib::XSetErrorHandler(Some(record_error))
+    };
+    let result = set_configs(screen, current, configs, width, height, dpi)
+        .and_then(|()| {
+            if let Some(primary) = primary {
+                unsafe {
+                    xrandr::XRRSetOutputPrimary(screen.display, screen.root, primary);
+                }
+            }
+            screen.sync()
+        });
+    unsafe {
+        xlib::XUngrabServer(screen.display);
+        xlib::XSync(screen.display, xlib::False);
+        xlib::XSetErrorHandler(previous_handler);
+    }
+    result
+}
+
+// Parses the xrandr arguments of an output. Returns None when the output is turned off.
+fn parse_args<'a>(screen: &Screen, output: &'a OutputInfo, args: &'a [String]) -> io::Result<Option<OutputPlan<'a>>> {
+    let invalid = |arg: &str| {
+        io::Error::new(ErrorKind::InvalidInput, format!("invalid xrandr argument for output {}: {}", output.name, arg))
+    };
+
+    let mut requested_mode = None;
+    let mut rate = None;
+    let mut rotation = xrandr::RR_Rotate_0;
+    let mut reflection = 0;
+    let mut transform = IDENTITY;
+    let mut filter = None;
+    let mut panning = None;
+    let mut position = Position::Current;
+    let mut primary = false;
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "--auto" => requested_mode = None,
+            "--off" => return Ok(None),
+            "--primary" => primary = true,
+            "--mode" => requested_mode = Some(args.next().ok_or_else(|| invalid(arg))?),
+            "--rate" | "--refresh" => {
+                let value = args.next().ok_or_else(|| invalid(arg))?;
+                rate = Some(value.parse::<f64>().map_err(|_| invalid(value))?);
+            },
+            "--rotate" | "--rotation" => {
+                rotation =
+                    match args.next() {
+                        Some("normal") => xrandr::RR_Rotate_0,
+                        Some("left") => xrandr::RR_Rotate_90,
+                        Some("inverted") => xrandr::RR_Rotate_180,
+                        Some("right") => xrandr::RR_Rotate_270,
+                        value => return Err(invalid(value.unwrap_or(arg))),
+                    };
+            },
+            "--reflect" => {
+                reflection =
+                    match args.next() {
+                        Some("normal") => 0,
+                        Some("x") => xrandr::RR_Reflect_X,
+                        Some("y") => xrandr::RR_Reflect_Y,
+                        Some("xy") => xrandr::RR_Reflect_X | xrandr::RR_Reflect_Y,
+                        value => return Err(invalid(value.unwrap_or(arg))),
+                    };
+            },
+            "--scale" => {
+                let value = args.next().ok_or_else(|| invalid(arg))?;
+                let (x, y) = parse_scale(value).ok_or_else(|| invalid(value))?;
+                transform = IDENTITY;
+                transform[0][0] = f64_to_fixed(x);
+                transform[1][1] = f64_to_fixed(y);
+            },
+            "--transform" => {
+                let value = args.next().ok_or_else(|| invalid(arg))?;
+                transform = parse_transform(value).ok_or_else(|| invalid(value))?;
+            },
+            "--filter" => {
+                let value = args.next().ok_or_else(|| invalid(arg))?;
+                filter = Some(parse_filter(value).ok_or_else(|| invalid(value))?);
+            },
+            "--panning" => {
+                let value = args.next().ok_or_else(|| invalid(arg))?;
+                let requested = parse_panning(value).ok_or_else(|| invalid(value))?;
+                panning = (requested.size != (0, 0)).then_some(requested);
+            },
+            "--pos" => {
+                let value = args.next().ok_or_else(|| invalid(arg))?;
+                let (x, y) = value.split_once('x').ok_or_else(|| invalid(value))?;
+                let x = x.parse().map_err(|_| invalid(value))?;
+                let y = y.parse().map_err(|_| invalid(value))?;
+                position = Position::Absolute(x, y);
+            },
+            _ if RELATIVE_POSITIONS.contains(&arg) => {
+                position = Position::Relative(arg, args.next().ok_or_else(|| invalid(arg))?);
+            },
+            _ => return Err(invalid(arg)),
+        }
+    }
+
+    let modes: Vec<_> = output.modes.iter()
+        .filter_map(|&id| screen.mode(id))
+        .collect();
+    let mode =
+        match requested_mode {
+            Some(name) => {
+                let mut candidates = modes.iter()
+                    .filter(|mode| mode_name(mode) == name);
+                match rate {
+                    Some(rate) => candidates.min_by(|mode1, mode2| {
+                        let distance1 = (refresh_rate(mode1) - rate).abs();
+                        let distance2 = (refresh_rate(mode2) - rate).abs();
+                        distance1.total_cmp(&distance2)
+                    }),
+                    None => candidates.next(),
+                }
+            },
+            // The preferred mode is listed first.
+            None => modes.first(),
+        };
+    let mode = mode.ok_or_else(|| invalid(requested_mode.unwrap_or("--auto")))?;
+
+    // Like xrandr, the position of the panning area places the output.
+    if let (Position::Current, Some((x, y))) = (&position, panning.and_then(|panning| panning.position)) {
+        position = Position::Absolute(x as i32, y as i32);
+    }
+
+    Ok(Some(OutputPlan {
+        output,
+        mode: mode.id,
+        rotation: (rotation | reflection) as xrandr::Rotation,
+        transform,
+        filter,
+        panning,
+        position,
+        primary,
+        crtc: 0,
+    }))
+}
+
+// Resolves the positions of the outputs, the relative ones once the output they refer to is
+// placed.
+fn place_outputs(screen: &Screen, outputs: &[OutputInfo], plans: &[OutputPlan],
+    configs: &mut BTreeMap<xrandr::RRCrtc, CrtcConfig>) -> io::Result<()>
+{
+    let mut placed = BTreeSet::new();
+    while placed.len() < plans.len() {
+        let count = placed.len();
+        for plan in plans {
+            if placed.contains(plan.output.name.as_str()) {
+                continue;
+            }
+
+            let position =
+                match plan.position {
+                    Position::Current => None,
+                    Position::Absolute(x, y) => Some((x, y)),
+                    Position::Relative(relation, other) => {
+                        let pending = plans.iter().any(|plan| plan.output.name == other) && !placed.contains(other);
+                        if pending {
+                            continue;
+                        }
+                        Some(relative_position(screen, outputs, plans, configs, plan, relation, other)?)
+                    },
+                };
+            if let (Some((x, y)), Some(config)) = (position, configs.get_mut(&plan.crtc)) {
+                config.x = x;
+                config.y = y;
+            }
+            placed.insert(plan.output.name.as_str());
+        }
+
+        if placed.len() == count {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "the outputs are positioned relative to each other"));
+        }
+    }
+
+    // Like xrandr, move everything so that no output has a negative position.
+    let min_x = configs.values().filter(|config| config.mode != 0).map(|config| config.x).min().unwrap_or_default();
+    let min_y = configs.values().filter(|config| config.mode != 0).map(|config| config.y).min().unwrap_or_default();
+    for config in configs.values_mut().filter(|config| config.mode != 0) {
+        config.x -= min_x.min(0);
+        config.y -= min_y.min(0);
+    }
+    Ok(())
+}
+
+fn relative_position(screen: &Screen, outputs: &[OutputInfo], plans: &[OutputPlan],
+    configs: &BTreeMap<xrandr::RRCrtc, CrtcConfig>, plan: &OutputPlan, relation: &str, other: &str)
+    -> io::Result<(i32, i32)>
+{
+    let other_crtc = plans.iter()
+        .find(|plan| plan.output.name == other)
+        .map(|plan| plan.crtc)
+        .or_else(|| outputs.iter().find(|output| output.name == other).map(|output| output.crtc));
+    let other_config = other_crtc
+        .and_then(|crtc| configs.get(&crtc))
+        .filter(|config| config.mode != 0)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput,
+            format!("cannot place output {} {} {} since it is not enabled", plan.output.name, relation, other)))?;
+    let (other_width, other_height) = screen.crtc_size(other_config).unwrap_or_default();
+    let (width, height) = configs.get(&plan.crtc)
+        .and_then(|config| screen.crtc_size(config))
+        .unwrap_or_default();
+
+    let (x, y) = (other_config.x, other_config.y);
+    let position =
+        match relation {
+            "--right-of" => (x + other_width as i32, y),
+            "--left-of" => (x - width as i32, y),
+            "--above" => (x, y - height as i32),
+            "--below" => (x, y + other_height as i32),
+            _ => (x, y),
+        };
+    Ok(position)
+}
+
+// Size of the screen containing all the enabled CRTCs.
+fn screen_size(screen: &Screen, configs: &BTreeMap<xrandr::RRCrtc, CrtcConfig>) -> io::Result<(i32, i32)> {
+    let mut width = 0;
+    let mut height = 0;
+    for config in configs.values() {
+        if let Some((crtc_width, crtc_height)) = screen.crtc_size(config) {
+            width = width.max(config.x + crtc_width as i32);
+            height = height.max(config.y + crtc_height as i32);
+        }
+    }
+
+    let mut min_width = 0;
+    let mut min_height = 0;
+    let mut max_width = 0;
+    let mut max_height = 0;
+    unsafe {
+        xrandr::XRRGetScreenSizeRange(screen.display, screen.root, &mut min_width, &mut min_height, &mut max_width,
+            &mut max_height);
+    }
+    if width > max_width || height > max_height {
+        return Err(io::Error::new(ErrorKind::InvalidInput,
+            format!("the screen size {}x{} is bigger than the maximum {}x{}", width, height, max_width, max_height)));
+    }
+    Ok((width.max(min_width), height.max(min_height)))
+}
+
+fn set_configs(screen: &Screen, current: &BTreeMap<xrandr::RRCrtc, CrtcConfig>,
+    configs: &BTreeMap<xrandr::RRCrtc, CrtcConfig>, width: i32, height: i32, dpi: Option<f64>) -> io::Result<()>
+{
+    let changed: Vec<_> = configs.iter()
+        .filter(|(crtc, config)| current.get(crtc) != Some(config))
+        .collect();
+    tracing::debug!("Screen of {}x{}", width, height);
+    for &(crtc, config) in &changed {
+        tracing::trace!("CRTC {}: mode {} at {}x{} for the outputs {:?}", crtc, config.mode, config.x, config.y, config.outputs);
+    }
+
+    // The CRTCs which are turned off or which would not fit in the new screen are disabled before
+    // resizing it.
+    for &(&crtc, config) in &changed {
+        let fits = current.get(&crtc)
+            .and_then(|current| Some((current, screen.crtc_size(current)?)))
+            .is_none_or(|(current, (crtc_width, crtc_height))| {
+                current.x + crtc_width as i32 <= width && current.y + crtc_height as i32 <= height
+            });
+        if config.mode == 0 || !fits {
+            screen.set_crtc_config(crtc, &CrtcConfig {
+                x: 0,
+                y: 0,
+                mode: 0,
+                rotation: xrandr::RR_Rotate_0 as xrandr::Rotation,
+                transform: IDENTITY,
+                filter: None,
+                panning: None,
+                outputs: vec![],
+            })?;
+        }
+    }
+
+    let (current_width, current_height) = screen.size();
+    if (width, height) != (current_width, current_height) || dpi.is_some() {
+        unsafe {
+            // Keep the same DPI, unless another one is requested.
+            let screen_number = xlib::XDefaultScreen(screen.display);
+            let height_mm = xlib::XDisplayHeightMM(screen.display, screen_number);
+            let dpi = dpi.unwrap_or(25.4 * current_height as f64 / height_mm.max(1) as f64);
+            let millimeters = |pixels: i32| (25.4 * pixels as f64 / dpi) as c_int;
+            xrandr::XRRSetScreenSize(screen.display, screen.root, width, height, millimeters(width),
+                millimeters(height));
+        }
+    }
+
+    for (&crtc, config) in changed {
+        if config.mode != 0 {
+            screen.set_crtc_config(crtc, config)?;
+        }
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn record_error(_display: *mut xlib::Display, event: *mut xlib::XErrorEvent) -> c_int {
+    X_ERROR.store((*event).error_code, Ordering::SeqCst);
+    0
+}
+
+// The arrays returned by libXrandr are null when empty.
+unsafe fn to_vec<T: Copy>(data: *const T, len: c_int) -> Vec<T> {
+    if data.is_null() || len <= 0 {
+        return vec![];
+    }
+    slice::from_raw_parts(data, len as usize).to_vec()
+}
+
+fn mode_name(mode: &xrandr::XRRModeInfo) -> String {
+    let name = unsafe { to_vec(mode.name as *const u8, mode.nameLength as c_int) };
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+fn refresh_rate(mode: &xrandr::XRRModeInfo) -> f64 {
+    let mut vertical_total = mode.vTotal as f64;
+    if mode.modeFlags & xrandr::RR_DoubleScan as xrandr::XRRModeFlags != 0 {
+        vertical_total *= 2.0;
+    }
+    if mode.modeFlags & xrandr::RR_Interlace as xrandr::XRRModeFlags != 0 {
+        vertical_total /= 2.0;
+    }
+    if mode.hTotal == 0 || vertical_total == 0.0 {
+        return 0.0;
+    }
+    mode.dotClock as f64 / (mode.hTotal as f64 * vertical_total)
+}
+
+fn fixed_to_f64(value: XFixed) -> f64 {
+    value as f64 / FIXED_ONE as f64
+}
+
+fn f64_to_fixed(value: f64) -> XFixed {
+    (value * FIXED_ONE as f64).round() as XFixed
+}
+
+fn transform_point(matrix: &[[XFixed; 3]; 3], x: f64, y: f64) -> (f64, f64) {
+    let row = |index: usize| {
+        let row = &matrix[index];
+        fixed_to_f64(row[0]) * x + fixed_to_f64(row[1]) * y + fixed_to_f64(row[2])
+    };
+    let w = row(2);
+    if w == 0.0 {
+        return (x, y);
+    }
+    (row(0) / w, row(1) / w)
+}
+
+// Like xrandr, smooth the picture when it is transformed.
+fn default_filter(transform: &[[XFixed; 3]; 3]) -> &'static str {
+    if *transform == IDENTITY {
+        "nearest"
+    }
+    else {
+        "bilinear"
+    }
+}
+
+pub fn parse_filter(value: &str) -> Option<&'static str> {
+    match value {
+        "nearest" => Some("nearest"),
+        "bilinear" => Some("bilinear"),
+        _ => None,
+    }
+}
+
+// The 9 numbers of the matrix, row by row, like "1,0,0,0,1,0,0,0,1", or "none".
+pub fn parse_transform(value: &str) -> Option<[[XFixed; 3]; 3]> {
+    if value == "none" {
+        return Some(IDENTITY);
+    }
+    let values: Vec<f64> = value.split(',')
+        .map(|number| number.parse().ok())
+        .collect::<Option<_>>()?;
+    if values.len() != 9 {
+        return None;
+    }
+    let mut matrix = IDENTITY;
+    for (index, value) in values.into_iter().enumerate() {
+        matrix[index / 3][index % 3] = f64_to_fixed(value);
+    }
+    Some(matrix)
+}
+
+// WIDTHxHEIGHT[+X+Y[/TRACK_WIDTHxTRACK_HEIGHT+TRACK_X+TRACK_Y[/LEFT/TOP/RIGHT/BOTTOM]]], like
+// xrandr: 0x0 turns the panning off.
+pub fn parse_panning(value: &str) -> Option<Panning> {
+    // WIDTHxHEIGHT with an optional +X+Y, as [x, y, width, height].
+    fn parse_area(area: &str) -> Option<[u32; 4]> {
+        let (size, position) = area.split_once('+').unwrap_or((area, "0+0"));
+        let (x, y) = position.split_once('+')?;
+        let (width, height) = size.split_once('x')?;
+        Some([x.parse().ok()?, y.parse().ok()?, width.parse().ok()?, height.parse().ok()?])
+    }
+
+    let mut parts = value.split('/');
+    let area = parts.next()?;
+    let [x, y, width, height] = parse_area(area)?;
+    let position = area.contains('+').then_some((x, y));
+    let tracking =
+        match parts.next() {
+            Some(area) => parse_area(area)?,
+            None => [0; 4],
+        };
+    let border: Vec<i32> = parts.map(|border| border.parse().ok()).collect::<Option<_>>()?;
+    let border =
+        match border[..] {
+            [] => [0; 4],
+            [left, top, right, bottom] => [left, top, right, bottom],
+            _ => return None,
+        };
+    Some(Panning {
+        position,
+        size: (width, height),
+        tracking,
+        border,
+    })
+}
+
+// Parses a scale like xrandr: either XxY or a single factor for both.
+fn parse_scale(value: &str) -> Option<(f64, f64)> {
+    match value.split_once('x') {
+        Some((x, y)) => Some((x.parse().ok()?, y.parse().ok()?)),
+        None => {
+            let scale = value.parse().ok()?;
+            Some((scale, scale))
+        },
+    }
+}
+
+fn rotation_name(rotation: xrandr::Rotation) -> &'static str {
+    match rotation as c_int & 0xf {
+        xrandr::RR_Rotate_90 => "left",
+        xrandr::RR_Rotate_180 => "inverted",
+        xrandr::RR_Rotate_270 => "right",
+        _ => "normal",
+    }
+}
+
+fn reflection_name(rotation: xrandr::Rotation) -> &'static str {
+    let reflection = rotation as c_int & (xrandr::RR_Reflect_X | xrandr::RR_Reflect_Y);
+    if reflection == xrandr::RR_Reflect_X | xrandr::RR_Reflect_Y {
+        "xy"
+    }
+    else if reflection == xrandr::RR_Reflect_X {
+        "x"
+    }
+    else if reflection == xrandr::RR_Reflect_Y {
+        "y"
+    }
+    else {
+        "normal"
+    }
+}
+
+// Returns the outputs whose current configuration differs from the desired one. An output with
+// arguments that cannot be checked is always considered as changed.
+pub fn changed_outputs(desired: &[DesiredOutput], current: &[OutputState]) -> BTreeSet<String> {
+    let current: BTreeMap<_, _> = current.iter()
+        .map(|output| (output.name.as_str(), output))
+        .collect();
+
+    let mut changed: BTreeSet<String> = desired.iter()
+        .filter(|output| !is_satisfied(output, &current))
+        .map(|output| output.name.clone())
+        .collect();
+
+    // The outputs positioned relative to a changed output may have to move as well.
+    loop {
+        let moved: Vec<_> = desired.iter()
+            .filter(|output| !changed.contains(&output.name))
+            .filter(|output| relative_outputs(&output.args).any(|other| changed.contains(other)))
+            .map(|output| output.name.clone())
+            .collect();
+        if moved.is_empty() {
+            break;
+        }
+        changed.extend(moved);
+    }
+
+    changed
+}
+
+fn is_satisfied(desired: &DesiredOutput, current: &BTreeMap<&str, &OutputState>) -> bool {
+    let output =
+        match current.get(desired.name.as_str()) {
+            Some(output) => output,
+            None => return false,
+        };
+    let geometry =
+        match output.geometry {
+            Some(geometry) => geometry,
+            None => return !desired.enabled,
+        };
+    if !desired.enabled || (desired.primary && !output.primary) {
+        return false;
+    }
+
+    let mut mode = output.preferred_mode.as_deref();
+    let mut rate = None;
+    let mut rotation = "normal";
+    let mut reflection = "normal";
+    let mut transform = IDENTITY;
+    let mut filter = None;
+    let mut panning = None;
+    let mut args = desired.args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "--auto" => (),
+            "--primary" if !output.primary => return false,
+            "--primary" => (),
+            "--mode" => mode = args.next(),
+            "--rate" | "--refresh" => rate = args.next(),
+            "--rotate" | "--rotation" => rotation = args.next().unwrap_or_default(),
+            "--reflect" => reflection = args.next().unwrap_or_default(),
+            "--scale" => {
+                match args.next().and_then(parse_scale) {
+                    Some((x, y)) => {
+                        transform = IDENTITY;
+                        transform[0][0] = f64_to_fixed(x);
+                        transform[1][1] = f64_to_fixed(y);
+                    },
+                    None => return false,
+                }
+            },
+            "--transform" => {
+                match args.next().and_then(parse_transform) {
+                    Some(value) => transform = value,
+                    None => return false,
+                }
+            },
+            "--filter" => {
+                match args.next().and_then(parse_filter) {
+                    Some(value) => filter = Some(value),
+                    None => return false,
+                }
+            },
+            "--panning" => {
+                match args.next().and_then(parse_panning) {
+                    Some(value) => panning = (value.size != (0, 0)).then_some(value),
+                    None => return false,
+                }
+            },
+            "--pos" => {
+                if args.next() != Some(&format!("{}x{}", geometry.x, geometry.y)) {
+                    return false;
+                }
+            },
+            _ if RELATIVE_POSITIONS.contains(&arg) => {
+                let other = args.next()
+                    .and_then(|name| current.get(name))
+                    .and_then(|other| other.geometry);
+                match other {
+                    Some(other) if is_positioned(arg, &geometry, &other) => (),
+                    _ => return false,
+                }
+            },
+            _ => return false,
+        }
+    }
+
+    let same_rate =
+        match (rate, &output.rate) {
+            (None, _) => true,
+            (Some(rate), Some(current_rate)) => {
+                match (rate.parse::<f64>(), current_rate.parse::<f64>()) {
+                    (Ok(rate), Ok(current_rate)) => (rate - current_rate).abs() < 0.01,
+                    _ => false,
+                }
+            },
+            (Some(_), None) => false,
+        };
+
+    let same_filter = filter.unwrap_or(default_filter(&transform)) == output.filter.unwrap_or(default_filter(&output.transform));
+    // Like when it is applied, the panning area starts at the output when its position is not given.
+    let origin = (geometry.x as u32, geometry.y as u32);
+    let same_panning =
+        match (panning, output.panning) {
+            (None, None) => true,
+            (Some(panning), Some(current)) => {
+                panning.position.unwrap_or(origin) == current.position.unwrap_or(origin)
+                    && panning.size == current.size && panning.tracking == current.tracking && panning.border == current.border
+            },
+            _ => false,
+        };
+    mode.is_some() && mode == output.mode.as_deref() && same_rate && rotation == output.rotation
+        && reflection == output.reflection && transform == output.transform && same_filter && same_panning
+}
+
+fn is_positioned(relation: &str, geometry: &Geometry, other: &Geometry) -> bool {
+    match relation {
+        "--right-of" => geometry.x == other.x + other.width as i32 && geometry.y == other.y,
+        "--left-of" => geometry.x + geometry.width as i32 == other.x && geometry.y == other.y,
+        "--above" => geometry.y + geometry.height as i32 == other.y && geometry.x == other.x,
+        "--below" => geometry.y == other.y + other.height as i32 && geometry.x == other.x,
+        "--same-as" => geometry.x == other.x && geometry.y == other.y,
+        _ => false,
+    }
+}
+
+// Returns the outputs referenced by the relative position arguments.
+fn relative_outputs(args: &[String]) -> impl Iterator<Item = &String> {
+    args.iter()
+        .zip(args.iter().skip(1))
+        .filter(|(arg, _)| RELATIVE_POSITIONS.contains(&arg.as_str()))
+        .map(|(_, other)| other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DesiredOutput,
+        FIXED_ONE,
+        Geometry,
+        IDENTITY,
+        OutputState,
+        Panning,
+        changed_outputs,
+        parse_filter,
+        parse_panning,
+        parse_transform,
+    };
+
+    #[test]
+    fn filter() {
+        assert_eq!(parse_filter("nearest"), Some("nearest"));
+        assert_eq!(parse_filter("bilinear"), Some("bilinear"));
+        assert_eq!(parse_filter("linear"), None);
+    }
+
+    #[test]
+    fn transform() {
+        assert_eq!(parse_transform("none"), Some(IDENTITY));
+        assert_eq!(parse_transform("1,0,0,0,1,0,0,0,1"), Some(IDENTITY));
+        let half = FIXED_ONE / 2;
+        assert_eq!(parse_transform("0.5,0,10,0,0.5,-20,0,0,1"),
+            Some([[half, 0, 10 * FIXED_ONE], [0, half, -20 * FIXED_ONE], [0, 0, FIXED_ONE]]));
+        assert_eq!(parse_transform("1,0,0,0,1,0,0,0"), None);
+        assert_eq!(parse_transform("1,0,0,0,1,0,0,0,x"), None);
+    }
+
+    #[test]
+    fn panning() {
+        assert_eq!(parse_panning("0x0"), Some(Panning { position: None, size: (0, 0), tracking: [0; 4], border: [0; 4] }));
+        assert_eq!(parse_panning("3840x2160+1920+0"),
+            Some(Panning { position: Some((1920, 0)), size: (3840, 2160), tracking: [0; 4], border: [0; 4] }));
+        assert_eq!(parse_panning("3840x2160/1920x1080+10+20/1/2/3/4"),
+            Some(Panning { position: None, size: (3840, 2160), tracking: [10, 20, 1920, 1080], border: [1, 2, 3, 4] }));
+        assert_eq!(parse_panning("3840x2160/1920x1080/1/2"), None);
+        assert_eq!(parse_panning("3840"), None);
+        assert_eq!(parse_panning("3840x2160+10"), None);
+    }
+
+    #[test]
+    fn transformed_output_is_unchanged() {
+        let half = FIXED_ONE / 2;
+        let current = [OutputState {
+            name: "DP-1".to_string(),
+            connected: true,
+            primary: false,
+            geometry: Some(Geometry { width: 3840, height: 2160, x: 0, y: 0 }),
+            rotation: "normal".to_string(),
+            reflection: "x".to_string(),
+            mode: Some("1920x1080".to_string()),
+            rate: Some("60.00".to_string()),
+            preferred_mode: Some("1920x1080".to_string()),
+            modes: vec![("1920x1080".to_string(), 60.0)],
+            scale: (0.5, 0.5),
+            transform: [[half, 0, 0], [0, half, 0], [0, 0, FIXED_ONE]],
+            filter: Some("nearest"),
+            panning: Some(Panning { position: Some((0, 0)), size: (3840, 2160), tracking: [0; 4], border: [0; 4] }),
+        }];
+        let changed = |args: &[&str]| {
+            let desired = DesiredOutput {
+                name: "DP-1".to_string(),
+                enabled: true,
+                primary: false,
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+            };
+            !changed_outputs(&[desired], &current).is_empty()
+        };
+
+        assert!(!changed(&["--scale", "0.5", "--reflect", "x", "--filter", "nearest", "--panning", "3840x2160"]));
+        assert!(!changed(&["--transform", "0.5,0,0,0,0.5,0,0,0,1", "--reflect", "x", "--filter", "nearest", "--panning", "3840x2160+0+0"]));
+        assert!(changed(&["--scale", "0.5", "--filter", "nearest", "--panning", "3840x2160"]));
+        assert!(changed(&["--scale", "0.5", "--reflect", "x", "--panning", "3840x2160"]));
+        assert!(changed(&["--scale", "0.5", "--reflect", "x", "--filter", "nearest"]));
+        assert!(changed(&["--scale", "0.5", "--reflect", "x", "--filter", "nearest", "--panning", "3840x2160/1920x1080+0+0"]));
+        assert!(changed(&["--reflect", "x", "--filter", "nearest", "--panning", "3840x2160"]));
+    }
+}
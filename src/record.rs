@@ -0,0 +1,90 @@
+// Recording of what the daemon sees, with --record, to replay a hotplug problem of a user offline:
+// the uevents, the outputs with their EDID and the workspaces, as a JSON object per line.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{MonitorData, Workspace};
+
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Uevent {
+        action: String,
+        subsystem: Option<String>,
+        devtype: Option<String>,
+        name: Option<String>,
+    },
+    // Hotplug event from any trigger, before the debounce.
+    Hotplug {
+        trigger: String,
+    },
+    // The outputs detected when reconfiguring them.
+    Outputs {
+        monitors: Vec<MonitorData>,
+    },
+    // The workspace history when reconfiguring the outputs.
+    Workspaces {
+        workspaces: Vec<Workspace>,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Record {
+    // In seconds since the epoch.
+    pub time: f64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+pub fn start(path: &Path) -> io::Result<()> {
+    *FILE.lock().expect("record lock") = Some(File::create(path)?);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    FILE.lock().expect("record lock").is_some()
+}
+
+pub fn write(event: Event) {
+    let mut file = FILE.lock().expect("record lock");
+    let file =
+        match *file {
+            Some(ref mut file) => file,
+            None => return,
+        };
+    let record = Record {
+        time: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |duration| duration.as_secs_f64()),
+        event,
+    };
+    let result = serde_json::to_string(&record)
+        .map_err(io::Error::from)
+        .and_then(|line| writeln!(file, "{}", line));
+    if let Err(error) = result {
+        tracing::error!("Cannot record the event: {}", error);
+    }
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<Record>> {
+    let file = File::open(path)?;
+    let mut records = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), index + 1, error)))?;
+        records.push(record);
+    }
+    Ok(records)
+}
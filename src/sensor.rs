@@ -0,0 +1,34 @@
+// Orientation of convertibles, from the accelerometer of iio-sensor-proxy, read from
+// `monitor-sensor`, which claims the accelerometer for as long as it runs.
+
+use std::{io, process::Command};
+
+use crate::{child, config::Rotation};
+
+// Calls `callback` with the rotation matching the orientation of the device, once when it starts
+// and every time it changes.
+pub fn watch<F: Fn(Rotation) + 'static>(callback: F) -> io::Result<()> {
+    let mut command = Command::new("monitor-sensor");
+    command.arg("--accel");
+    child::watch_lines(&mut command, "iio-sensor-proxy", move |line| {
+        if let Some(rotation) = parse_orientation(line) {
+            callback(rotation);
+        }
+    })
+}
+
+// The lines look like "=== Has accelerometer (orientation: normal)" and
+// "    Accelerometer orientation changed: left-up".
+fn parse_orientation(line: &str) -> Option<Rotation> {
+    let line = line.trim().trim_end_matches(')');
+    let (_, orientation) = line.rsplit_once("orientation")?;
+    let orientation = orientation.trim_start_matches([':', ' ']).trim_start_matches("changed: ");
+    // The side of the screen that is up.
+    match orientation {
+        "normal" => Some(Rotation::Normal),
+        "bottom-up" => Some(Rotation::Inverted),
+        "left-up" => Some(Rotation::Left),
+        "right-up" => Some(Rotation::Right),
+        _ => None,
+    }
+}
@@ -0,0 +1,118 @@
+// The workspace history is kept on disk so that a restart of the daemon, or a reboot, does not
+// forget where the workspaces belong.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{config::Profile, Workspace};
+
+const WORKSPACES_FILE: &str = "workspaces.json";
+const CONNECTIONS_FILE: &str = "connections.json";
+const SAVED_LAYOUT_FILE: &str = "saved-layout.json";
+
+// The output of every workspace and the one it goes back to.
+pub type Placement = BTreeMap<String, (String, Option<String>)>;
+
+// What a temporary mode (presentation, exclusive) replaced, to put it back when it ends.
+#[derive(Deserialize, Serialize)]
+pub struct SavedLayout {
+    pub mode: String,
+    pub previous: Profile,
+    pub workspaces: Vec<Workspace>,
+}
+
+pub fn load(workspaces: &DashMap<String, Workspace>) -> io::Result<()> {
+    let saved: Vec<Workspace> = read(WORKSPACES_FILE)?.unwrap_or_default();
+    for workspace in saved {
+        workspaces.insert(workspace.name.clone(), workspace);
+    }
+    Ok(())
+}
+
+pub fn save(workspaces: &DashMap<String, Workspace>) -> io::Result<()> {
+    let entries: Vec<_> = workspaces.iter().collect();
+    let workspaces: Vec<&Workspace> = entries.iter().map(|entry| entry.value()).collect();
+    write(WORKSPACES_FILE, &workspaces)
+}
+
+pub fn placement(workspaces: &DashMap<String, Workspace>) -> Placement {
+    workspaces.iter()
+        .map(|workspace| (workspace.name.clone(), (workspace.output.clone(), workspace.previous_output.clone())))
+        .collect()
+}
+
+// When each of the connected outputs was first seen connected, in seconds since the epoch. The
+// outputs that are no longer connected are forgotten.
+pub fn connection_times(connected: &BTreeSet<String>) -> io::Result<BTreeMap<String, u64>> {
+    let mut times: BTreeMap<String, u64> = read(CONNECTIONS_FILE)?.unwrap_or_default();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    times.retain(|name, _| connected.contains(name));
+    for name in connected {
+        times.entry(name.clone()).or_insert(now);
+    }
+    write(CONNECTIONS_FILE, &times)?;
+    Ok(times)
+}
+
+pub fn load_saved_layout() -> io::Result<Option<SavedLayout>> {
+    read(SAVED_LAYOUT_FILE)
+}
+
+pub fn save_layout(saved_layout: &SavedLayout) -> io::Result<()> {
+    write(SAVED_LAYOUT_FILE, saved_layout)
+}
+
+pub fn remove_saved_layout() -> io::Result<()> {
+    let path = state_path(SAVED_LAYOUT_FILE)
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "cannot find the state directory"))?;
+    fs::remove_file(path)
+}
+
+// Returns None when the file does not exist yet.
+fn read<T: DeserializeOwned>(file: &str) -> io::Result<Option<T>> {
+    let path =
+        match state_path(file) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+    let content =
+        match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|error| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), error)))
+}
+
+fn write<T: Serialize + ?Sized>(file: &str, value: &T) -> io::Result<()> {
+    let path = state_path(file)
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "cannot find the state directory"))?;
+    let content = serde_json::to_string(value)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Write to another file first so that a crash never leaves a truncated state.
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, &path)
+}
+
+fn state_path(file: &str) -> Option<PathBuf> {
+    let base =
+        match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+        };
+    Some(base.join("i3-aww").join(file))
+}
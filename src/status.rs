@@ -0,0 +1,161 @@
+use std::io;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    MonitorData,
+    Workspace,
+    config::{Config, Profile},
+};
+
+#[derive(Deserialize, Serialize)]
+struct OutputStatus {
+    name: String,
+    connected: bool,
+    edid: Option<String>,
+    model: Option<String>,
+}
+
+// Also sent by the daemon to its clients.
+#[derive(Deserialize, Serialize)]
+pub struct Status {
+    outputs: Vec<OutputStatus>,
+    profile: String,
+    workspaces: Vec<Workspace>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BarFormat {
+    /// A JSON object per line, for a persistent block with format=json.
+    I3blocks,
+    /// A JSON object per line, for a custom module with return-type json.
+    Waybar,
+}
+
+#[derive(Serialize)]
+struct ProfileStatus<'a> {
+    #[serde(flatten)]
+    profile: &'a Profile,
+    active: bool,
+}
+
+impl Status {
+    pub fn new(monitor_data: &[MonitorData], profile: &Profile, workspaces: Vec<Workspace>) -> Self {
+        let outputs = monitor_data.iter()
+            .map(|monitor| OutputStatus {
+                name: monitor.name.clone(),
+                connected: monitor.connected,
+                edid: monitor.edid.as_ref().map(|edid| edid.fingerprint()),
+                model: monitor.edid.as_ref().and_then(|edid| edid.model.clone()),
+            })
+            .collect();
+        Status {
+            outputs,
+            profile: profile.name.clone(),
+            workspaces,
+        }
+    }
+}
+
+pub fn print_status(status: &Status, json: bool) -> io::Result<()> {
+    if json {
+        return print_json(status);
+    }
+
+    println!("Outputs:");
+    for output in &status.outputs {
+        let state = if output.connected { "connected" } else { "disconnected" };
+        print!("  {} {}", output.name, state);
+        if let Some(ref edid) = output.edid {
+            print!(" {}", edid);
+        }
+        if let Some(ref model) = output.model {
+            print!(" ({})", model);
+        }
+        println!();
+    }
+    println!("Profile: {}", status.profile);
+    println!("Workspaces:");
+    for workspace in &status.workspaces {
+        print!("  {} on {}", workspace.name, workspace.output);
+        if let Some(ref output) = workspace.previous_output {
+            print!(" (previously on {})", output);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+pub fn print_profiles(config: &Config, active: &Profile, json: bool) -> io::Result<()> {
+    let profiles: Vec<_> = config.profiles.iter()
+        .map(|profile| ProfileStatus {
+            profile,
+            active: profile.name == active.name,
+        })
+        .collect();
+
+    if json {
+        return print_json(&profiles);
+    }
+
+    for status in &profiles {
+        let profile = status.profile;
+        print!("{}", profile.name);
+        if status.active {
+            print!(" (active)");
+        }
+        println!();
+        for (name, output) in &profile.outputs {
+            print!("  {}", name);
+            if let Some(ref edid) = output.edid {
+                print!(" [{}]", edid);
+            }
+            if !output.enabled {
+                print!(" off");
+            }
+            if !output.args.is_empty() {
+                print!(" {}", output.args.join(" "));
+            }
+            println!();
+        }
+    }
+    Ok(())
+}
+
+// Without status, the daemon is not running.
+pub fn print_bar(status: Option<&Status>, format: BarFormat) {
+    let (text, profile, tooltip) =
+        match status {
+            Some(status) => {
+                let connected: Vec<_> = status.outputs.iter()
+                    .filter(|output| output.connected)
+                    .map(|output| output.name.as_str())
+                    .collect();
+                let outputs = if connected.len() == 1 { "output" } else { "outputs" };
+                (format!("{} ({} {})", status.profile, connected.len(), outputs), status.profile.as_str(), connected.join(", "))
+            },
+            None => ("i3-aww not running".to_string(), "", String::new()),
+        };
+    let line =
+        match format {
+            BarFormat::I3blocks => json!({
+                "full_text": text,
+                "short_text": profile,
+            }),
+            BarFormat::Waybar => json!({
+                "text": text,
+                "alt": profile,
+                "tooltip": tooltip,
+                "class": if status.is_some() { "running" } else { "stopped" },
+            }),
+        };
+    println!("{}", line);
+}
+
+fn print_json<T: Serialize>(value: &T) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    println!("{}", json);
+    Ok(())
+}
@@ -0,0 +1,313 @@
+// Sway backend: the outputs are detected through sysfs and configured with sway `output`
+// commands. Sway speaks the i3 IPC protocol, so the workspace handling is shared with i3.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    slice,
+};
+
+use i3_aww_core::edid::Edid;
+use serde::Deserialize;
+
+use crate::{
+    MonitorData,
+    backend::WmBackend,
+    dry_run,
+    config::{InputConfig, ModePreference, OutputConfig, Profile, Scale},
+    wallpaper,
+};
+
+const DRM_PATH: &str = "/sys/class/drm";
+
+#[derive(Deserialize)]
+struct SwayOutput {
+    name: String,
+    active: bool,
+    #[serde(default)]
+    primary: bool,
+    rect: Rect,
+    current_mode: Option<Mode>,
+    transform: Option<String>,
+    scale: Option<f64>,
+    #[serde(default)]
+    modes: Vec<Mode>,
+}
+
+#[derive(Deserialize)]
+struct Rect {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Deserialize)]
+struct Mode {
+    width: i32,
+    height: i32,
+    // In mHz.
+    refresh: i32,
+}
+
+pub fn monitor_data() -> Vec<MonitorData> {
+    let entries =
+        match fs::read_dir(DRM_PATH) {
+            Ok(entries) => entries,
+            Err(error) => {
+                tracing::error!("Cannot list the DRM connectors: {}", error);
+                return vec![];
+            },
+        };
+
+    let mut monitor_data = vec![];
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        // Connectors are named like card0-HDMI-A-1.
+        let name =
+            match file_name.to_string_lossy().split_once('-') {
+                Some((card, name)) if card.starts_with("card") => name.to_string(),
+                _ => continue,
+            };
+        let status = fs::read_to_string(entry.path().join("status")).unwrap_or_default();
+        let edid = fs::read(entry.path().join("edid")).ok()
+            .and_then(|edid| Edid::parse(&edid));
+        monitor_data.push(MonitorData {
+            name,
+            connected: status.trim() == "connected",
+            edid,
+            mode: None,
+        });
+    }
+    monitor_data.sort_by(|monitor1, monitor2| monitor1.name.cmp(&monitor2.name));
+    monitor_data
+}
+
+// Sway has no notion of primary output, so the primary setting of the profile is ignored and the
+// arguments of the outputs are appended to the `output` command.
+pub fn apply(wm: &mut dyn WmBackend, profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()> {
+    for (name, output_config) in &profile.outputs {
+        if output_config.headless {
+            tracing::warn!(output = %name, "Sway cannot enable the headless output {}, create one with `swaymsg create_output`", name);
+        }
+    }
+    let previous = current_profile(wm, "previous");
+    if let Err(error) = run_output_commands(wm, profile, monitor_data) {
+        // Do not leave the user with black screens: go back to the configuration from before.
+        let restored = previous.and_then(|previous| run_output_commands(wm, &previous, monitor_data));
+        return match restored {
+            Ok(()) => Err(io::Error::new(error.kind(), format!("{} (the previous configuration was restored)", error))),
+            Err(restore_error) => {
+                tracing::error!("Cannot restore the previous configuration of the outputs: {}", restore_error);
+                Err(error)
+            },
+        };
+    }
+    Ok(())
+}
+
+fn run_output_commands(wm: &mut dyn WmBackend, profile: &Profile, monitor_data: &[MonitorData]) -> io::Result<()> {
+    let outputs = outputs(wm)?;
+    for monitor in monitor_data {
+        if !monitor.connected {
+            continue;
+        }
+
+        let command =
+            match profile.outputs.get(&monitor.name) {
+                Some(output_config) if output_config.enabled => {
+                    let mut command = format!("output {} enable", monitor.name);
+                    if let Some(ref other) = output_config.mirror {
+                        tracing::warn!(output = %monitor.name, "Sway cannot mirror output {} on {}, enabling it on its own", other, monitor.name);
+                    }
+                    let modes = outputs.iter()
+                        .find(|output| output.name == monitor.name)
+                        .map(|output| output.modes.as_slice())
+                        .unwrap_or_default();
+                    let preferred_size = monitor.edid.as_ref().and_then(|edid| edid.preferred_size);
+                    if let Some(mode) = mode_arg(output_config.mode.as_ref(), modes, preferred_size) {
+                        command.push_str(&format!(" mode {}", mode));
+                    }
+                    if let Some(rotation) = output_config.rotate {
+                        command.push_str(&format!(" transform {}", rotation.sway_transform()));
+                    }
+                    if let Some(scale) = output_config.scale {
+                        command.push_str(&format!(" scale {}", scale.factor(monitor.edid.as_ref())));
+                    }
+                    if let Some(adaptive_sync) = output_config.adaptive_sync {
+                        command.push_str(if adaptive_sync { " adaptive_sync on" } else { " adaptive_sync off" });
+                    }
+                    for arg in &output_config.args {
+                        command.push(' ');
+                        command.push_str(arg);
+                    }
+                    command
+                },
+                _ => format!("output {} disable", monitor.name),
+            };
+
+        if dry_run() {
+            tracing::info!(output = %monitor.name, "Would run {}", command);
+            continue;
+        }
+        for reply in wm.run_commands(slice::from_ref(&command))? {
+            if !reply.success {
+                return Err(io::Error::other(format!("{}: {}", command, reply.error.unwrap_or_default())));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn set_wallpapers(wm: &mut dyn WmBackend, wallpapers: &BTreeMap<String, String>) -> io::Result<()> {
+    for (output, image) in wallpapers {
+        let command = format!("output {} bg \"{}\" fill", output, wallpaper::expand_home(image).display());
+        for reply in wm.run_commands(slice::from_ref(&command))? {
+            if !reply.success {
+                return Err(io::Error::other(format!("{}: {}", command, reply.error.unwrap_or_default())));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn map_inputs(wm: &mut dyn WmBackend, mappings: &BTreeMap<String, String>) -> io::Result<()> {
+    for (device, output) in mappings {
+        let command = format!("input \"{}\" map_to_output {}", device, output);
+        for reply in wm.run_commands(slice::from_ref(&command))? {
+            if !reply.success {
+                return Err(io::Error::other(format!("{}: {}", command, reply.error.unwrap_or_default())));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn configure_inputs(wm: &mut dyn WmBackend, input: &InputConfig) -> io::Result<()> {
+    let mut commands = vec![];
+    if let Some(ref layout) = input.keyboard_layout {
+        commands.push(format!("input type:keyboard xkb_layout \"{}\"", layout));
+    }
+    if let Some(ref variant) = input.keyboard_variant {
+        commands.push(format!("input type:keyboard xkb_variant \"{}\"", variant));
+    }
+    for device_type in ["pointer", "touchpad"] {
+        if let Some(natural_scrolling) = input.natural_scrolling {
+            let state = if natural_scrolling { "enabled" } else { "disabled" };
+            commands.push(format!("input type:{} natural_scroll {}", device_type, state));
+        }
+        if let Some(acceleration) = input.pointer_acceleration {
+            commands.push(format!("input type:{} pointer_accel {}", device_type, acceleration));
+        }
+    }
+
+    for command in commands {
+        for reply in wm.run_commands(slice::from_ref(&command))? {
+            if !reply.success {
+                return Err(io::Error::other(format!("{}: {}", command, reply.error.unwrap_or_default())));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn outputs(wm: &mut dyn WmBackend) -> io::Result<Vec<SwayOutput>> {
+    Ok(serde_json::from_value(wm.outputs_json()?)?)
+}
+
+// Sway picks the preferred mode by default. Sway does not tell which mode is preferred: it is the
+// one of the EDID, otherwise the first one, like the kernel lists them.
+fn mode_arg(preference: Option<&ModePreference>, modes: &[Mode], preferred_size: Option<(u32, u32)>) -> Option<String> {
+    match preference? {
+        ModePreference::Preferred => None,
+        ModePreference::Exact { mode, rate: Some(rate) } => Some(format!("{}@{}Hz", mode, rate)),
+        ModePreference::Exact { mode, rate: None } => Some(mode.clone()),
+        // The highest refresh rate of the preferred resolution, like with xrandr.
+        ModePreference::HighestRefresh => {
+            let (width, height) = preferred_size.map(|(width, height)| (width as i32, height as i32))
+                .or_else(|| modes.first().map(|mode| (mode.width, mode.height)))?;
+            modes.iter()
+                .filter(|mode| mode.width == width && mode.height == height)
+                .max_by_key(|mode| mode.refresh)
+                .map(|mode| format!("{}x{}@{:.3}Hz", mode.width, mode.height, mode.refresh as f64 / 1000.0))
+        },
+    }
+}
+
+pub fn current_profile(wm: &mut dyn WmBackend, name: &str) -> io::Result<Profile> {
+    let fingerprints: BTreeMap<_, _> = monitor_data().into_iter()
+        .filter_map(|monitor| Some((monitor.name, monitor.edid?.fingerprint())))
+        .collect();
+
+    let outputs = outputs(wm)?;
+
+    let mut profile = Profile {
+        name: name.to_string(),
+        primary: None,
+        pre_apply_delay: None,
+        post_apply_delay: None,
+        dock: None,
+        exec_before: vec![],
+        exec_after: vec![],
+        wallpapers: BTreeMap::new(),
+        input: None,
+        battery: BTreeMap::new(),
+        after: None,
+        before: None,
+        outputs: BTreeMap::new(),
+    };
+    for output in outputs {
+        if output.primary {
+            profile.primary = Some(output.name.clone());
+        }
+
+        let mut output_config = OutputConfig {
+            edid: fingerprints.get(&output.name).cloned(),
+            ..OutputConfig::default()
+        };
+        if output.active {
+            if let Some(mode) = output.current_mode {
+                output_config.args.extend([
+                    "mode".to_string(),
+                    format!("{}x{}@{:.3}Hz", mode.width, mode.height, mode.refresh as f64 / 1000.0),
+                ]);
+            }
+            output_config.args.extend([
+                "pos".to_string(),
+                output.rect.x.to_string(),
+                output.rect.y.to_string(),
+            ]);
+            if let Some(transform) = output.transform {
+                output_config.args.extend(["transform".to_string(), transform]);
+            }
+            output_config.scale = output.scale
+                .filter(|&scale| scale != 1.0)
+                .map(Scale::Factor);
+        }
+        else {
+            output_config.enabled = false;
+        }
+        profile.outputs.insert(output.name, output_config);
+    }
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mode, ModePreference, mode_arg};
+
+    fn mode(width: i32, height: i32, refresh: i32) -> Mode {
+        Mode { width, height, refresh }
+    }
+
+    // Like with xrandr: the preferred resolution at its highest refresh rate, not the highest
+    // resolution.
+    #[test]
+    fn highest_refresh_of_preferred_resolution() {
+        let modes = [mode(2560, 1440, 59951), mode(3840, 2160, 60000), mode(2560, 1440, 143912), mode(1920, 1080, 240000)];
+        let preference = Some(&ModePreference::HighestRefresh);
+        assert_eq!(mode_arg(preference, &modes, Some((2560, 1440))).as_deref(), Some("2560x1440@143.912Hz"));
+        assert_eq!(mode_arg(preference, &modes, None).as_deref(), Some("2560x1440@143.912Hz"));
+        assert_eq!(mode_arg(preference, &modes, Some((1280, 720))), None);
+        assert_eq!(mode_arg(Some(&ModePreference::Preferred), &modes, Some((2560, 1440))), None);
+    }
+}
@@ -0,0 +1,61 @@
+// Notifications of the services of Type=notify: systemd learns when the daemon is ready, and
+// restarts it when the main loop stops pinging the watchdog, e.g. when it is stuck in a callback.
+
+use std::{
+    env,
+    io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    process,
+    time::Duration,
+};
+
+use glib::{Continue, timeout_add_local};
+
+// Does nothing when the daemon is not started by systemd.
+pub fn notify(state: &str) -> io::Result<()> {
+    let path =
+        match env::var_os("NOTIFY_SOCKET") {
+            Some(path) if !path.is_empty() => path,
+            _ => return Ok(()),
+        };
+    // A leading @ stands for an abstract socket.
+    let address =
+        match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+// Pings the watchdog twice per interval, when it is enabled for this process.
+pub fn start_watchdog() {
+    let interval =
+        match watchdog_interval() {
+            Some(interval) => interval,
+            None => return,
+        };
+    timeout_add_local(interval / 2, || {
+        if let Err(error) = notify("WATCHDOG=1") {
+            tracing::error!("Cannot ping the systemd watchdog: {}", error);
+        }
+        Continue(true)
+    });
+}
+
+fn watchdog_interval() -> Option<Duration> {
+    let microseconds = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // Set when the watchdog is meant for another process of the service.
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(process::id()) {
+            return None;
+        }
+    }
+    Some(Duration::from_micros(microseconds))
+}
@@ -0,0 +1,232 @@
+// Restoration of the workspaces and event loop of the daemon against the fake i3 of mock_i3. The
+// sway backend is used since it reads the outputs from the IPC, and the outputs are named so that
+// they never match a DRM connector of the machine running the tests.
+
+use std::{
+    env,
+    fs,
+    path::PathBuf,
+    process,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use dashmap::DashMap;
+use glib::{Continue, timeout_add_local, timeout_add_local_once};
+use i3_ipc::event::Subscribe;
+use serde_json::json;
+
+use crate::{
+    Args,
+    Backend,
+    Daemon,
+    adjust_workspaces,
+    config::{Config, check_args},
+    mock_i3::{MockI3, output, tree, workspace},
+    restore_workspaces,
+    snapshot_workspaces,
+    subscribe,
+    wait_for_output_event,
+    watch_i3_events,
+};
+
+// How long the CPU usage of the daemon is measured.
+const CPU_WINDOW: Duration = Duration::from_secs(3);
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+// Enough for the daemon to handle an event.
+const EVENT_DELAY: Duration = Duration::from_millis(300);
+
+#[test]
+fn moves_workspace_back_to_reconnected_output() {
+    let i3 = MockI3::start().expect("mock i3");
+    let workspaces = Arc::new(DashMap::new());
+
+    i3.set_outputs(vec![output("MOCK-1", true, 0), output("MOCK-2", true, 1920)]);
+    i3.set_workspaces(vec![
+        workspace(1, "1", "MOCK-1", true, true),
+        workspace(2, "2", "MOCK-2", true, false),
+    ]);
+    adjust_workspaces(&workspaces);
+
+    // i3 moves the workspace of the disconnected output to the remaining one.
+    i3.set_outputs(vec![output("MOCK-1", true, 0), output("MOCK-2", false, 1920)]);
+    i3.set_workspaces(vec![
+        workspace(1, "1", "MOCK-1", true, true),
+        workspace(2, "2", "MOCK-1", false, false),
+    ]);
+    adjust_workspaces(&workspaces);
+
+    i3.set_outputs(vec![output("MOCK-1", true, 0), output("MOCK-2", true, 1920)]);
+    i3.set_tree(tree(&[("MOCK-1", &[("1", &[1]), ("2", &[2])])]));
+    let snapshot = snapshot_workspaces();
+    restore_workspaces(Backend::Sway, &workspaces, &snapshot, &Config::default()).expect("restore");
+
+    assert_eq!(i3.commands(), vec![[
+        r#"[workspace="^2$"] move workspace to output "MOCK-2""#,
+        r#"focus output "MOCK-1"; workspace --no-auto-back-and-forth "1""#,
+        r#"focus output "MOCK-2"; workspace --no-auto-back-and-forth "2""#,
+        r#"workspace --no-auto-back-and-forth "1""#,
+    ].join("; ")]);
+    assert_eq!(workspaces.get("2").and_then(|workspace| workspace.previous_output.clone()).as_deref(), Some("MOCK-2"));
+}
+
+#[test]
+fn forgets_hidden_empty_workspace() {
+    let i3 = MockI3::start().expect("mock i3");
+    let workspaces = Arc::new(DashMap::new());
+
+    i3.set_outputs(vec![output("MOCK-1", true, 0), output("MOCK-2", true, 1920)]);
+    i3.set_workspaces(vec![
+        workspace(1, "1", "MOCK-1", true, true),
+        workspace(2, "2", "MOCK-2", true, false),
+        workspace(3, "3", "MOCK-2", false, false),
+    ]);
+    adjust_workspaces(&workspaces);
+
+    // i3 destroys the empty workspace 3 instead of moving it.
+    i3.set_outputs(vec![output("MOCK-1", true, 0), output("MOCK-2", false, 1920)]);
+    i3.set_workspaces(vec![
+        workspace(1, "1", "MOCK-1", true, true),
+        workspace(2, "2", "MOCK-1", false, false),
+    ]);
+    adjust_workspaces(&workspaces);
+
+    i3.set_outputs(vec![output("MOCK-1", true, 0), output("MOCK-2", true, 1920)]);
+    i3.set_tree(tree(&[("MOCK-1", &[("1", &[1]), ("2", &[2])])]));
+    let snapshot = snapshot_workspaces();
+    restore_workspaces(Backend::Sway, &workspaces, &snapshot, &Config::default()).expect("restore");
+
+    let commands = i3.commands().join("; ");
+    assert!(!commands.contains(r#""3""#), "{}", commands);
+    assert!(!workspaces.contains_key("3"));
+}
+
+#[test]
+fn output_event_ends_the_wait() {
+    let i3 = MockI3::start().expect("mock i3");
+    let mut stream = subscribe(&[Subscribe::Output]).expect("subscribe");
+
+    let start = Instant::now();
+    i3.push_event(Subscribe::Output, json!({ "change": "unspecified" }));
+    wait_for_output_event(Some(&mut stream), Duration::from_secs(10));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+// The daemon used to spin on the i3 socket: between the events, it must not use the CPU.
+#[test]
+fn daemon_loop_idles_between_events() {
+    let i3 = Rc::new(MockI3::start().expect("mock i3"));
+    i3.set_outputs(vec![output("MOCK-1", true, 0)]);
+    i3.set_workspaces(vec![workspace(1, "1", "MOCK-1", true, true)]);
+
+    let (daemon, config) = start_daemon();
+
+    // Events that the daemon ignores, so that the reads on the socket are measured too.
+    let ticks = timeout_add_local(TICK_INTERVAL, {
+        let i3 = i3.clone();
+        move || {
+            i3.push_event(Subscribe::Tick, json!({ "first": false, "payload": "unrelated" }));
+            Continue(true)
+        }
+    });
+    timeout_add_local_once(CPU_WINDOW, {
+        let main_loop = daemon.main_loop.clone();
+        move || main_loop.quit()
+    });
+    let start = thread_cpu_time();
+    daemon.main_loop.run();
+    let used = thread_cpu_time() - start;
+    ticks.remove();
+    let _ = fs::remove_file(&config);
+
+    assert!(used < CPU_WINDOW / 10, "{:?} of CPU time in {:?}", used, CPU_WINDOW);
+}
+
+// Only the moves of the workspaces are written to the disk, not the focus changes.
+#[test]
+fn saves_history_when_workspaces_move() {
+    let i3 = MockI3::start().expect("mock i3");
+    i3.set_outputs(vec![output("MOCK-1", true, 0), output("MOCK-2", true, 1920)]);
+    i3.set_workspaces(vec![
+        workspace(1, "1", "MOCK-1", true, true),
+        workspace(2, "2", "MOCK-2", true, false),
+    ]);
+    let state_dir = env::temp_dir().join(format!("i3-aww-test-state-{}", process::id()));
+    env::set_var("XDG_STATE_HOME", &state_dir);
+    let saved = state_dir.join("i3-aww").join("workspaces.json");
+    let (daemon, config) = start_daemon();
+    let workspace_event = || {
+        i3.push_event(Subscribe::Workspace, json!({ "change": "focus", "current": null, "old": null }));
+        timeout_add_local_once(EVENT_DELAY, {
+            let main_loop = daemon.main_loop.clone();
+            move || main_loop.quit()
+        });
+        daemon.main_loop.run();
+    };
+
+    workspace_event();
+    let first_save = saved.exists();
+    let _ = fs::remove_file(&saved);
+
+    i3.set_workspaces(vec![
+        workspace(1, "1", "MOCK-1", true, false),
+        workspace(2, "2", "MOCK-2", true, true),
+    ]);
+    workspace_event();
+    let focus_saved = saved.exists();
+
+    i3.set_workspaces(vec![
+        workspace(1, "1", "MOCK-1", true, false),
+        workspace(2, "2", "MOCK-1", false, false),
+        workspace(3, "3", "MOCK-2", true, true),
+    ]);
+    workspace_event();
+    let move_saved = saved.exists();
+
+    env::remove_var("XDG_STATE_HOME");
+    let _ = fs::remove_dir_all(&state_dir);
+    let _ = fs::remove_file(&config);
+    assert!(first_save);
+    assert!(!focus_saved);
+    assert!(move_saved);
+}
+
+// The arguments of the outputs are xrandr arguments with i3 and `output` arguments with sway.
+#[test]
+fn checks_output_args_of_the_backend() {
+    let config = |args: &str| -> Config {
+        toml::from_str(&format!("[[profiles]]\n[profiles.outputs.DP-1]\nargs = {}\n", args)).expect("config")
+    };
+
+    let xrandr = config(r#"["--panning", "3840x2160", "--filter", "nearest"]"#);
+    assert!(check_args(&xrandr, Backend::I3).is_ok());
+    assert!(check_args(&xrandr, Backend::Sway).is_err());
+    assert!(check_args(&config(r#"["--filter", "linear"]"#), Backend::I3).is_err());
+
+    let sway = config(r#"["pos", "1920", "0", "subpixel", "rgb"]"#);
+    assert!(check_args(&sway, Backend::Sway).is_ok());
+    assert!(check_args(&config(r#"["mode", "--custom", "1920x1080@60Hz"]"#), Backend::Sway).is_ok());
+    assert!(check_args(&config(r#"["position", "0", "0", "--right-of", "eDP-1"]"#), Backend::Sway).is_err());
+    assert!(check_args(&config(r#"["rotate", "left"]"#), Backend::Sway).is_err());
+}
+
+// A daemon with an empty configuration, watching the events of the mock. Returns the path of the
+// configuration, to remove it.
+fn start_daemon() -> (Daemon, PathBuf) {
+    let config = env::temp_dir().join(format!("i3-aww-test-{}.toml", process::id()));
+    fs::write(&config, "").expect("config");
+    let args = Args::parse_from(["i3-aww", "--backend", "sway", "--config", &config.to_string_lossy()]);
+    let daemon = Daemon::new(&args, Arc::new(DashMap::new())).expect("daemon");
+    watch_i3_events(&daemon).expect("watch i3 events");
+    (daemon, config)
+}
+
+fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time);
+    }
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
@@ -0,0 +1,33 @@
+// USB devices attached to the machine, to tell docks apart when they are connected to the same
+// monitors.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::Path,
+};
+
+const USB_DEVICES_PATH: &str = "/sys/bus/usb/devices";
+
+// The vendor and product IDs of the attached devices, like "17ef:3082".
+pub fn devices() -> BTreeSet<String> {
+    let entries =
+        match fs::read_dir(USB_DEVICES_PATH) {
+            Ok(entries) => entries,
+            Err(error) => {
+                tracing::error!("Cannot list the USB devices: {}", error);
+                return BTreeSet::new();
+            },
+        };
+
+    entries.flatten()
+        .filter_map(|entry| device_id(&entry.path()))
+        .collect()
+}
+
+// The interfaces have no IDs, only the devices do.
+fn device_id(path: &Path) -> Option<String> {
+    let vendor = fs::read_to_string(path.join("idVendor")).ok()?;
+    let product = fs::read_to_string(path.join("idProduct")).ok()?;
+    Some(format!("{}:{}", vendor.trim(), product.trim()).to_ascii_lowercase())
+}
@@ -0,0 +1,31 @@
+// Wallpapers of the outputs, which are commonly lost or stretched when the size of the X screen
+// changes.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    io,
+    path::PathBuf,
+    process::Command,
+};
+
+// `wallpapers` maps the outputs to their image, which is zoomed to fill the output.
+pub fn xwallpaper(wallpapers: &BTreeMap<String, String>) -> io::Result<()> {
+    let mut command = Command::new("xwallpaper");
+    for (output, image) in wallpapers {
+        command.arg("--output").arg(output).arg("--zoom").arg(expand_home(image));
+    }
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("xwallpaper failed: {}", status)));
+    }
+    Ok(())
+}
+
+// Images can be given relative to the home directory, like "~/Pictures/left.png".
+pub fn expand_home(image: &str) -> PathBuf {
+    match (image.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(path), Some(home)) => PathBuf::from(home).join(path),
+        _ => PathBuf::from(image),
+    }
+}
@@ -0,0 +1,161 @@
+// End-to-end test in a nested X server, for `i3-aww test-xephyr`: Xephyr shows several outputs, a
+// throwaway i3 manages them, and the daemon has to put the workspaces back while the outputs are
+// pretended unplugged and plugged back with the simulate command.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    process::{self, Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use i3_ipc::{I3, Connect};
+
+use crate::{HotplugEvent, Request, control, randr, simulate};
+
+const SCREEN_SIZE: &str = "1024x768";
+const SCREENS: usize = 3;
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+// Much longer than the debounce and the delays of the daemon.
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Killed when the test ends, whatever its result.
+struct Processes(Vec<Child>);
+
+impl Drop for Processes {
+    fn drop(&mut self) {
+        for child in self.0.iter_mut().rev() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+pub fn run(display: u32) -> io::Result<()> {
+    if Path::new(&format!("/tmp/.X11-unix/X{}", display)).exists() {
+        return Err(io::Error::new(ErrorKind::AddrInUse, format!("the display :{} is in use, pick another one with --display", display)));
+    }
+    let dir = env::temp_dir().join(format!("i3-aww-xephyr-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let result = run_in(&dir, display);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn run_in(dir: &Path, display: u32) -> io::Result<()> {
+    let mut processes = Processes(vec![]);
+
+    // Everything that the window manager and the daemon use goes to the temporary directory, so
+    // that the session of the user is left alone.
+    let display = format!(":{}", display);
+    let i3_socket = dir.join("i3.sock");
+    env::set_var("DISPLAY", &display);
+    env::set_var("I3SOCK", &i3_socket);
+    env::set_var("XDG_RUNTIME_DIR", dir);
+    env::set_var("XDG_CONFIG_HOME", dir);
+    env::set_var("XDG_STATE_HOME", dir);
+
+    // Every screen of Xephyr is an output of the nested X server.
+    let mut xephyr = Command::new("Xephyr");
+    xephyr.arg(&display);
+    for _ in 0..SCREENS {
+        xephyr.arg("-screen").arg(SCREEN_SIZE);
+    }
+    xephyr.args(["+xinerama", "+extension", "RANDR", "-ac", "-noreset"]);
+    processes.0.push(spawn(&mut xephyr, "Xephyr", false)?);
+    wait_until(STARTUP_TIMEOUT, "Xephyr to start", || randr::version().is_ok())?;
+    let outputs: Vec<_> = randr::query()?.into_iter()
+        .filter(|output| output.connected)
+        .map(|output| output.name)
+        .collect();
+    if outputs.len() < 2 {
+        return Err(io::Error::other(format!("Xephyr shows a single output instead of {}: it needs Xinerama and RandR", SCREENS)));
+    }
+
+    let i3_config = dir.join("i3.config");
+    fs::write(&i3_config, format!("ipc-socket {}\n", i3_socket.display()))?;
+    processes.0.push(spawn(Command::new("i3").arg("-c").arg(&i3_config), "i3", false)?);
+    wait_until(STARTUP_TIMEOUT, "i3 to start", || I3::connect().is_ok())?;
+    // i3 starts with a workspace on every output.
+    let initial = placement()?;
+
+    let config = dir.join("config.toml");
+    fs::write(&config, "")?;
+    let mut daemon = Command::new(env::current_exe()?);
+    daemon.args(["--trigger", "randr", "--config"]).arg(&config);
+    processes.0.push(spawn(&mut daemon, "the daemon", true)?);
+    wait_until(STARTUP_TIMEOUT, "the daemon to start", || matches!(control::send(&Request::GetStatus), Ok(Some(_))))?;
+
+    // Unplug every output but the first one, then plug them back in the reverse order.
+    let mut active: BTreeSet<_> = outputs.iter().cloned().collect();
+    let unplugged = &outputs[1..];
+    let steps = unplugged.iter().map(|output| (HotplugEvent::Disconnect, output))
+        .chain(unplugged.iter().rev().map(|output| (HotplugEvent::Connect, output)));
+    for (event, output) in steps {
+        let description = format!("{} {}", if event == HotplugEvent::Connect { "connect" } else { "disconnect" }, output);
+        match event {
+            HotplugEvent::Connect => active.insert(output.clone()),
+            HotplugEvent::Disconnect => active.remove(output),
+        };
+        simulate(event, output)?;
+        let settled = wait_until(STEP_TIMEOUT, &description, || {
+            let placement = placement().unwrap_or_default();
+            active_outputs().is_ok_and(|outputs| outputs == active)
+                && !placement.is_empty() && placement.values().all(|output| active.contains(output))
+        });
+        if let Err(error) = settled {
+            return Err(io::Error::new(error.kind(), format!("{}: {:?} active, workspaces on {:?}", error,
+                active_outputs().unwrap_or_default(), placement().unwrap_or_default())));
+        }
+        println!("{:>8}  {}", "ok", description);
+    }
+
+    let restored = wait_until(STEP_TIMEOUT, "the workspaces to go back", || placement().is_ok_and(|placement| placement == initial));
+    if let Err(error) = restored {
+        return Err(io::Error::new(error.kind(), format!("{}: expected {:?}, got {:?}", error, initial, placement().unwrap_or_default())));
+    }
+    println!("{:>8}  workspaces back on their outputs", "ok");
+    Ok(())
+}
+
+// Only the daemon shows its messages, to see why a step fails.
+fn spawn(command: &mut Command, name: &str, show_errors: bool) -> io::Result<Child> {
+    command.stdout(Stdio::null());
+    if !show_errors {
+        command.stderr(Stdio::null());
+    }
+    command.spawn()
+        .map_err(|error| io::Error::new(error.kind(), format!("cannot run {}: {}", name, error)))
+}
+
+fn wait_until<F: FnMut() -> bool>(timeout: Duration, what: &str, mut condition: F) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    while !condition() {
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(ErrorKind::TimedOut, format!("timed out waiting for {}", what)));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+// Output of every workspace.
+fn placement() -> io::Result<BTreeMap<String, String>> {
+    let workspaces = I3::connect()?.get_workspaces()?;
+    Ok(workspaces.into_iter()
+        .map(|workspace| (workspace.name, workspace.output))
+        .collect())
+}
+
+fn active_outputs() -> io::Result<BTreeSet<String>> {
+    let outputs = I3::connect()?.get_outputs()?;
+    Ok(outputs.into_iter()
+        .filter(|output| output.active)
+        .map(|output| output.name)
+        .collect())
+}
@@ -0,0 +1,54 @@
+// Hotplug detection through RandR events on the X connection, for when udev events are not
+// available (containers, missing permissions, …).
+
+use std::{io, mem, ptr};
+
+use glib::{Continue, IOCondition, unix_fd_add_local};
+use x11::{xlib, xrandr};
+
+// Calls `callback` every time the X server reports a change of outputs.
+pub fn watch<F: Fn() + 'static>(callback: F) -> io::Result<()> {
+    let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+    if display.is_null() {
+        return Err(io::Error::other("cannot open the X display"));
+    }
+
+    let mut event_base = 0;
+    let mut error_base = 0;
+    if unsafe { xrandr::XRRQueryExtension(display, &mut event_base, &mut error_base) } == 0 {
+        unsafe {
+            xlib::XCloseDisplay(display);
+        }
+        return Err(io::Error::other("the X server does not support RandR"));
+    }
+
+    let fd = unsafe {
+        let root = xlib::XDefaultRootWindow(display);
+        xrandr::XRRSelectInput(display, root, xrandr::RRScreenChangeNotifyMask | xrandr::RROutputChangeNotifyMask);
+        xlib::XFlush(display);
+        xlib::XConnectionNumber(display)
+    };
+
+    // The display is kept open for the lifetime of the daemon.
+    unix_fd_add_local(fd, IOCondition::IN, move |_fd, _condition| {
+        let mut changed = false;
+        unsafe {
+            while xlib::XPending(display) > 0 {
+                let mut event: xlib::XEvent = mem::zeroed();
+                xlib::XNextEvent(display, &mut event);
+                let event_type = event.get_type();
+                if event_type == event_base + xrandr::RRScreenChangeNotify || event_type == event_base + xrandr::RRNotify {
+                    xrandr::XRRUpdateConfiguration(&mut event);
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            callback();
+        }
+        Continue(true)
+    });
+
+    Ok(())
+}
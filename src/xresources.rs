@@ -0,0 +1,77 @@
+// The Xft.dpi resource, like `xrdb -merge`: the applications size their fonts from it when they
+// start, and it does not follow the DPI of the X screen.
+
+use std::{
+    ffi::CStr,
+    io,
+    os::raw::c_int,
+    ptr,
+};
+
+use x11::xlib;
+
+const DPI_RESOURCE: &str = "Xft.dpi:";
+
+// Sets Xft.dpi in the resources of the X server. Returns whether it changed.
+pub fn set_dpi(dpi: f64) -> io::Result<bool> {
+    let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+    if display.is_null() {
+        return Err(io::Error::other("cannot open the X display"));
+    }
+
+    let resources = unsafe {
+        let resources = xlib::XResourceManagerString(display);
+        if resources.is_null() {
+            String::new()
+        }
+        else {
+            CStr::from_ptr(resources).to_string_lossy().into_owned()
+        }
+    };
+
+    let data = merge_dpi(&resources, dpi);
+    if let Some(ref data) = data {
+        unsafe {
+            let root = xlib::XDefaultRootWindow(display);
+            xlib::XChangeProperty(display, root, xlib::XA_RESOURCE_MANAGER, xlib::XA_STRING, 8, xlib::PropModeReplace,
+                data.as_ptr(), data.len() as c_int);
+            xlib::XSync(display, xlib::False);
+        }
+    }
+    unsafe {
+        xlib::XCloseDisplay(display);
+    }
+    Ok(data.is_some())
+}
+
+// The resources with Xft.dpi set, replacing the previous value, or None when it does not change.
+fn merge_dpi(resources: &str, dpi: f64) -> Option<String> {
+    let value = format!("{}\t{}", DPI_RESOURCE, dpi.round());
+    let mut lines: Vec<_> = resources.lines().map(str::to_string).collect();
+    match lines.iter_mut().find(|line| line.starts_with(DPI_RESOURCE)) {
+        Some(line) if line.split_whitespace().eq(value.split_whitespace()) => return None,
+        Some(line) => *line = value,
+        None => lines.push(value),
+    }
+    let mut data = lines.join("\n");
+    data.push('\n');
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_dpi;
+
+    #[test]
+    fn dpi_resource() {
+        assert_eq!(merge_dpi("", 96.0).as_deref(), Some("Xft.dpi:\t96\n"));
+
+        let resources = "Xcursor.size:\t24\nXft.dpi:\t96\nXft.hinting:\t1\n";
+        assert_eq!(merge_dpi(resources, 143.6).as_deref(), Some("Xcursor.size:\t24\nXft.dpi:\t144\nXft.hinting:\t1\n"));
+        assert_eq!(merge_dpi(resources, 96.2), None);
+        // Set with another spacing, like in a .Xresources.
+        assert_eq!(merge_dpi("Xft.dpi: 96\n", 96.0), None);
+
+        assert_eq!(merge_dpi("Xcursor.size:\t24", 192.0).as_deref(), Some("Xcursor.size:\t24\nXft.dpi:\t192\n"));
+    }
+}